@@ -1,4 +1,5 @@
-//! Functions for CSI stage, unstage, publish and unpublish filesystem volumes.
+//! Functions for CSI stage, unstage, publish, unpublish and expand filesystem
+//! volumes.
 
 use std::{fs, io::ErrorKind, path::PathBuf};
 
@@ -11,7 +12,7 @@ macro_rules! failure {
 
 use crate::{
     csi::{volume_capability::MountVolume, *},
-    format::prepare_device,
+    format::{grow_filesystem, prepare_device},
     mount::{self, subset, ReadOnly},
 };
 
@@ -346,3 +347,43 @@ pub fn unpublish_fs_volume(
     info!("Volume {} unpublished from {}", volume_id, target_path);
     Ok(())
 }
+
+/// Grow the filesystem of a published filesystem volume to the size of the
+/// underlying device
+pub async fn expand_fs_volume(
+    msg: &NodeExpandVolumeRequest,
+) -> Result<(), Status> {
+    let volume_id = &msg.volume_id;
+    let volume_path = &msg.volume_path;
+
+    let mountinfo =
+        mount::find_mount(None, Some(volume_path)).ok_or_else(|| {
+            failure!(
+                Code::NotFound,
+                "Failed to expand volume {}: no filesystem mounted onto {}",
+                volume_id,
+                volume_path
+            )
+        })?;
+
+    debug!(
+        "Expanding volume {}: growing filesystem on device {}",
+        volume_id, mountinfo.source
+    );
+
+    if let Err(error) =
+        grow_filesystem(&mountinfo.source, volume_path, &mountinfo.fstype).await
+    {
+        return Err(failure!(
+            Code::Internal,
+            "Failed to expand volume {}: error growing filesystem on device {}: {}",
+            volume_id,
+            mountinfo.source,
+            error
+        ));
+    }
+
+    info!("Volume {} expanded at {}", volume_id, volume_path);
+
+    Ok(())
+}
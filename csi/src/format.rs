@@ -1,4 +1,5 @@
-//! Utility function for formatting a device with filesystem
+//! Utility functions for formatting a device with filesystem and for growing
+//! the filesystem on a device
 
 use std::process::Command;
 
@@ -46,3 +47,43 @@ pub(crate) async fn prepare_device(
         String::from_utf8(output.stderr).unwrap()
     ))
 }
+
+/// Grow the filesystem mounted on `mountpoint` from `device` to the size of
+/// the device.
+pub(crate) async fn grow_filesystem(
+    device: &str,
+    mountpoint: &str,
+    fstype: &str,
+) -> Result<(), String> {
+    // ext filesystems are grown through the device, xfs through the mount
+    let (binary, target) = match fstype {
+        "ext2" | "ext3" | "ext4" => ("resize2fs", device),
+        "xfs" => ("xfs_growfs", mountpoint),
+        _ => {
+            return Err(format!("unsupported filesystem type {}", fstype));
+        }
+    };
+
+    debug!("Growing filesystem ({}) on device {}", fstype, device);
+
+    let output = Command::new(binary)
+        .arg(target)
+        .output()
+        .map_err(|error| format!("failed to execute {}: {}", binary, error))?;
+
+    trace!(
+        "Output from {} command: {}",
+        binary,
+        String::from_utf8(output.stdout.clone()).unwrap()
+    );
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "{} command failed: {}",
+        binary,
+        String::from_utf8(output.stderr).unwrap()
+    ))
+}
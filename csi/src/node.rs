@@ -1,4 +1,11 @@
-use std::{boxed::Box, path::Path, time::Duration, vec::Vec};
+use std::{
+    boxed::Box,
+    fs,
+    os::unix::fs::FileTypeExt,
+    path::Path,
+    time::Duration,
+    vec::Vec,
+};
 
 use tonic::{Code, Request, Response, Status};
 
@@ -18,6 +25,7 @@ use crate::{
     },
     dev::Device,
     filesystem_vol::{
+        expand_fs_volume,
         publish_fs_volume,
         stage_fs_volume,
         unpublish_fs_volume,
@@ -149,11 +157,13 @@ impl node_server::Node for Node {
         &self,
         _request: Request<NodeGetCapabilitiesRequest>,
     ) -> Result<Response<NodeGetCapabilitiesResponse>, Status> {
-        let caps = vec![node_service_capability::rpc::Type::StageUnstageVolume];
+        let caps = vec![
+            node_service_capability::rpc::Type::StageUnstageVolume,
+            node_service_capability::rpc::Type::ExpandVolume,
+        ];
 
         debug!("NodeGetCapabilities request: {:?}", caps);
 
-        // We don't support stage/unstage and expand volume rpcs
         Ok(Response::new(NodeGetCapabilitiesResponse {
             capabilities: caps
                 .into_iter()
@@ -345,13 +355,54 @@ impl node_server::Node for Node {
         Err(Status::new(Code::Unimplemented, "Method not implemented"))
     }
 
+    /// The nexus has already been grown by the time this RPC is called, so
+    /// all that is left to do here is growing the filesystem of filesystem
+    /// volumes. Block volumes pick up the new size from the device itself.
     async fn node_expand_volume(
         &self,
         request: Request<NodeExpandVolumeRequest>,
     ) -> Result<Response<NodeExpandVolumeResponse>, Status> {
         let msg = request.into_inner();
-        error!("Unimplemented {:?}", msg);
-        Err(Status::new(Code::Unimplemented, "Method not implemented"))
+
+        trace!("node_expand_volume {:?}", msg);
+
+        if msg.volume_id.is_empty() {
+            return Err(failure!(
+                Code::InvalidArgument,
+                "Failed to expand volume: missing volume id"
+            ));
+        }
+
+        if msg.volume_path.is_empty() {
+            return Err(failure!(
+                Code::InvalidArgument,
+                "Failed to expand volume {}: missing volume path",
+                &msg.volume_id
+            ));
+        }
+
+        let metadata = fs::metadata(&msg.volume_path).map_err(|error| {
+            failure!(
+                Code::NotFound,
+                "Failed to expand volume {}: error accessing {}: {}",
+                &msg.volume_id,
+                &msg.volume_path,
+                error
+            )
+        })?;
+
+        if metadata.file_type().is_block_device() {
+            debug!("Volume {} is a block volume", &msg.volume_id);
+        } else {
+            expand_fs_volume(&msg).await?;
+        }
+
+        Ok(Response::new(NodeExpandVolumeResponse {
+            capacity_bytes: msg
+                .capacity_range
+                .map(|range| range.required_bytes)
+                .unwrap_or_default(),
+        }))
     }
 
     async fn node_stage_volume(
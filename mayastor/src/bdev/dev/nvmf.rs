@@ -21,7 +21,7 @@ use spdk_sys::{
 
 use crate::{
     bdev::{util::uri, CreateDestroy, GetName},
-    core::Bdev,
+    core::{Bdev, MayastorEnvironment},
     ffihelper::{cb_arg, errno_result_from_i32, ErrnoResult},
     nexus_uri::{self, NexusBdevError},
 };
@@ -41,6 +41,8 @@ pub(super) struct Nvmf {
     port: u16,
     /// the nqn of the subsystem we want to connect to
     subnqn: String,
+    /// the nqn we identify ourselves with as host to the target
+    hostnqn: String,
     /// Enable protection information checking (reftag, guard)
     prchk_flags: u32,
     /// uuid of the spdk bdev
@@ -101,6 +103,10 @@ impl TryFrom<&Url> for Nvmf {
             }
         }
 
+        let hostnqn = parameters.remove("hostnqn").unwrap_or_else(|| {
+            MayastorEnvironment::global_or_default().host_nqn()
+        });
+
         let uuid = uri::uuid(parameters.remove("uuid")).context(
             nexus_uri::UuidParamParseError {
                 uri: url.to_string(),
@@ -118,6 +124,7 @@ impl TryFrom<&Url> for Nvmf {
             host: host.to_string(),
             port: url.port().unwrap_or(DEFAULT_NVMF_PORT),
             subnqn: segments[0].to_string(),
+            hostnqn,
             prchk_flags,
             uuid,
         })
@@ -159,6 +166,7 @@ impl CreateDestroy for Nvmf {
         }
 
         let cname = CString::new(self.name.clone()).unwrap();
+        let chostnqn = CString::new(self.hostnqn.clone()).unwrap();
         let mut context = NvmeCreateContext::new(self);
 
         let (sender, receiver) = oneshot::channel::<ErrnoResult<()>>();
@@ -170,7 +178,7 @@ impl CreateDestroy for Nvmf {
                 cname.as_ptr(),
                 &mut context.names[0],
                 context.count,
-                std::ptr::null_mut(),
+                chostnqn.as_ptr(),
                 context.prchk_flags,
                 Some(done_nvme_create_cb),
                 cb_arg(sender),
@@ -7,11 +7,13 @@ pub use nexus::{
         Nexus,
         NexusState,
         NexusStatus,
+        ReadPolicy,
         VerboseError,
     },
     nexus_child::{ChildState, Reason},
     nexus_child_error_store::{ActionType, NexusErrStore, QueryType},
     nexus_child_status_config,
+    nexus_events,
     nexus_label::{GPTHeader, GptEntry},
     nexus_metadata_content::{
         NexusConfig,
@@ -19,6 +21,7 @@ pub use nexus::{
         NexusConfigVersion2,
         NexusConfigVersion3,
     },
+    nexus_qos::{NexusQos, QosLimit},
 };
 
 pub trait BdevCreateDestroy: CreateDestroy + GetName + std::fmt::Debug {}
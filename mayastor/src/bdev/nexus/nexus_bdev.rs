@@ -13,7 +13,7 @@ use std::{
 
 use futures::channel::oneshot;
 use nix::errno::Errno;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use tonic::{Code, Status};
 
@@ -42,18 +42,21 @@ use crate::{
             instances,
             nexus_channel::{DREvent, NexusChannel, NexusChannelInner},
             nexus_child::{ChildError, ChildState, NexusChild},
-            nexus_io::{io_status, nvme_admin_opc, Bio},
+            nexus_events::{self, NexusEventType},
+            nexus_io::{io_status, io_type, nvme_admin_opc, Bio},
             nexus_iscsi::{NexusIscsiError, NexusIscsiTarget},
             nexus_label::LabelError,
             nexus_nbd::{NbdDisk, NbdError},
             nexus_nvmf::{NexusNvmfError, NexusNvmfTarget},
+            nexus_qos::{NexusQos, QosLimiter},
+            nexus_stats::IoStats,
         },
     },
     core::{Bdev, CoreError, DmaError, Share},
     ffihelper::errno_result_from_i32,
-    lvs::Lvol,
+    lvs::{Error as LvsError, Lvol},
     nexus_uri::{bdev_destroy, NexusBdevError},
-    rebuild::RebuildError,
+    rebuild::{ClientOperations, RebuildError, RebuildJob, ScrubError},
     subsys,
     subsys::Config,
 };
@@ -114,6 +117,33 @@ pub enum Error {
         source: NexusNvmfError,
         name: String,
     },
+    #[snafu(display("Failed to set the allowed hosts of nexus {}", name))]
+    SetAllowedHosts { source: CoreError, name: String },
+    #[snafu(display("The nexus {} has not been shared over nvmf", name))]
+    NotSharedNvmf { name: String },
+    #[snafu(display("Failed to set the ANA state of nexus {}", name))]
+    SetAnaState {
+        source: NexusNvmfError,
+        name: String,
+    },
+    #[snafu(display("Invalid listener address {}", address))]
+    InvalidListenerAddress { address: String },
+    #[snafu(display("Nexus {} has no listener {}", name, listener))]
+    ListenerNotFound { listener: String, name: String },
+    #[snafu(display("Nexus {} already has listener {}", name, listener))]
+    ListenerExists { listener: String, name: String },
+    #[snafu(display("Cannot remove the last listener of nexus {}", name))]
+    RemoveLastListener { name: String },
+    #[snafu(display("Failed to add a listener to nexus {}", name))]
+    AddListener {
+        source: NexusNvmfError,
+        name: String,
+    },
+    #[snafu(display("Failed to remove a listener from nexus {}", name))]
+    RemoveListener {
+        source: NexusNvmfError,
+        name: String,
+    },
     #[snafu(display("Failed to allocate label of nexus {}", name))]
     AllocLabel { source: DmaError, name: String },
     #[snafu(display("Failed to write label of nexus {}", name))]
@@ -219,8 +249,28 @@ pub enum Error {
         name: String,
         source: RebuildError,
     },
+    #[snafu(display(
+        "Nexus {} needs at least two healthy children to be scrubbed",
+        name
+    ))]
+    ScrubTooFewChildren { name: String },
+    #[snafu(display(
+        "Child {} of nexus {} is not healthy and cannot be used to repair \
+         the others",
+        child,
+        name
+    ))]
+    ScrubAuthorityNotHealthy { child: String, name: String },
+    #[snafu(display("Failed to create scrub job of nexus {}", name))]
+    CreateScrubError { source: ScrubError, name: String },
+    #[snafu(display("Scrub job not found for nexus {}", name))]
+    ScrubNotFound { source: ScrubError, name: String },
     #[snafu(display("Invalid ShareProtocol value {}", sp_value))]
     InvalidShareProtocol { sp_value: i32 },
+    #[snafu(display("Invalid ReadPolicy value {}", value))]
+    InvalidReadPolicy { value: i32 },
+    #[snafu(display("Invalid NvmeAnaState value {}", value))]
+    InvalidAnaState { value: i32 },
     #[snafu(display("Failed to create nexus {}", name))]
     NexusCreate { name: String },
     #[snafu(display("Failed to destroy nexus {}", name))]
@@ -236,10 +286,93 @@ pub enum Error {
         name: String,
         state: String,
     },
+    #[snafu(display(
+        "Nexus {} cannot be shrunk from {} to {} bytes",
+        name,
+        current,
+        requested
+    ))]
+    ShrinkNexus {
+        name: String,
+        current: u64,
+        requested: u64,
+    },
+    #[snafu(display("Nexus {} must be online to be resized", name))]
+    ResizeNotOnline { name: String },
+    #[snafu(display("Encrypted nexus {} cannot be resized", name))]
+    ResizeEncrypted { name: String },
+    #[snafu(display(
+        "Child {} of nexus {} must be grown to at least {} blocks",
+        child,
+        name,
+        blocks
+    ))]
+    ChildTooSmall {
+        child: String,
+        name: String,
+        blocks: u64,
+    },
+    #[snafu(display("Failed to grow child {} of nexus {}", child, name))]
+    GrowChild {
+        source: LvsError,
+        child: String,
+        name: String,
+    },
+    #[snafu(display("Failed to change the block count of nexus {}", name))]
+    ResizeNexus { source: Errno, name: String },
     #[snafu(display("Failed to get BdevHandle for snapshot operation"))]
     FailedGetHandle,
     #[snafu(display("Failed to create snapshot on nexus {}", name))]
     FailedCreateSnapshot { name: String, source: CoreError },
+    #[snafu(display("Failed to pause IO on nexus {} for snapshot", name))]
+    SnapshotPauseIo { source: Errno, name: String },
+    #[snafu(display("Failed to resume IO on nexus {} after snapshot", name))]
+    SnapshotResumeIo { source: Errno, name: String },
+    #[snafu(display(
+        "No child of nexus {} has a snapshot taken at {}",
+        name,
+        snapshot_time
+    ))]
+    SnapshotNotFound { name: String, snapshot_time: u64 },
+    #[snafu(display("Nexus {} cannot be reverted whilst rebuilding", name))]
+    RevertRebuilding { name: String },
+    #[snafu(display("Failed to revert any child of nexus {}", name))]
+    RevertFailed { name: String },
+    #[snafu(display(
+        "Nexus {} cannot be reverted whilst published other than over nvmf",
+        name
+    ))]
+    RevertPublished { name: String },
+    #[snafu(display(
+        "Failed to pause the hosts of nexus {} for revert",
+        name
+    ))]
+    RevertPauseIo {
+        source: NexusNvmfError,
+        name: String,
+    },
+    #[snafu(display(
+        "Failed to resume the hosts of nexus {} after revert",
+        name
+    ))]
+    RevertResumeIo {
+        source: NexusNvmfError,
+        name: String,
+    },
+    #[snafu(display(
+        "Events from sequence number {} are not available, the oldest is {} \
+         and the next is {}",
+        seq,
+        oldest,
+        next
+    ))]
+    EventsUnavailable { seq: u64, oldest: u64, next: u64 },
+    #[snafu(display(
+        "Events of epoch {} are not available, the current epoch is {}",
+        epoch,
+        current
+    ))]
+    EventsEpochMismatch { epoch: u64, current: u64 },
 }
 
 impl From<Error> for tonic::Status {
@@ -254,12 +387,33 @@ impl From<Error> for tonic::Status {
             Error::InvalidKey {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            Error::InvalidReadPolicy {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::InvalidAnaState {
+                ..
+            } => Status::invalid_argument(e.to_string()),
             Error::AlreadyShared {
                 ..
             } => Status::invalid_argument(e.to_string()),
             Error::NotShared {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            Error::NotSharedNvmf {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::InvalidListenerAddress {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::ListenerNotFound {
+                ..
+            } => Status::not_found(e.to_string()),
+            Error::ListenerExists {
+                ..
+            } => Status::already_exists(e.to_string()),
+            Error::RemoveLastListener {
+                ..
+            } => Status::failed_precondition(e.to_string()),
             Error::CreateChild {
                 ..
             } => Status::invalid_argument(e.to_string()),
@@ -278,6 +432,42 @@ impl From<Error> for tonic::Status {
             Error::ChildNotFound {
                 ..
             } => Status::not_found(e.to_string()),
+            Error::ShrinkNexus {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::ResizeNotOnline {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::ResizeEncrypted {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::ChildTooSmall {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::SnapshotNotFound {
+                ..
+            } => Status::not_found(e.to_string()),
+            Error::RevertRebuilding {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::RevertPublished {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::ScrubTooFewChildren {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::ScrubAuthorityNotHealthy {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::ScrubNotFound {
+                ..
+            } => Status::not_found(e.to_string()),
+            Error::EventsUnavailable {
+                ..
+            } => Status::out_of_range(e.to_string()),
+            Error::EventsEpochMismatch {
+                ..
+            } => Status::out_of_range(e.to_string()),
             e => Status::new(Code::Internal, e.to_string()),
         }
     }
@@ -327,8 +517,23 @@ pub struct Nexus {
     pub(crate) share_handle: Option<String>,
     /// enum containing the protocol-specific target used to publish the nexus
     pub nexus_target: Option<NexusTarget>,
+    /// host NQNs allowed to connect when published over nvmf, empty allows
+    /// any host
+    pub(crate) allowed_hosts: Vec<String>,
     /// the maximum number of times to attempt to send an IO
     pub(crate) max_io_attempts: i32,
+    /// the policy used to select the child to read from
+    pub(crate) read_policy: ReadPolicy,
+    /// the IOPS and bandwidth limits of the nexus
+    pub(crate) qos: NexusQos,
+    /// the token buckets enforcing the limits on all cores
+    pub(crate) qos_limiter: QosLimiter,
+    /// counters of the IO completed by the nexus
+    pub(crate) stats: IoStats,
+    /// the status last reported to the event watchers
+    reported_status: NexusStatus,
+    /// the nexus is being destroyed, its rebuild checkpoints are kept
+    pub(crate) destroying: bool,
 }
 
 unsafe impl core::marker::Sync for Nexus {}
@@ -354,6 +559,25 @@ pub enum NexusState {
     Open,
 }
 
+/// Policy used to select the child a read is sent to. Writes always go to
+/// all children.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ReadPolicy {
+    /// rotate between all healthy children
+    RoundRobin,
+    /// the child with the fewest reads outstanding on the submitting core
+    LeastQueueDepth,
+    /// rotate between the healthy children on the same node as the nexus,
+    /// or between all healthy children if there are none
+    PreferLocal,
+}
+
+impl Default for ReadPolicy {
+    fn default() -> Self {
+        ReadPolicy::RoundRobin
+    }
+}
+
 impl ToString for NexusState {
     fn to_string(&self) -> String {
         match *self {
@@ -420,7 +644,14 @@ impl Nexus {
             share_handle: None,
             size,
             nexus_target: None,
+            allowed_hosts: Vec::new(),
             max_io_attempts: cfg.err_store_opts.max_io_attempts,
+            read_policy: ReadPolicy::default(),
+            qos: NexusQos::default(),
+            qos_limiter: QosLimiter::default(),
+            stats: IoStats::default(),
+            reported_status: NexusStatus::Degraded,
+            destroying: false,
         });
 
         n.bdev.set_uuid(match uuid {
@@ -446,8 +677,65 @@ impl Nexus {
             self.name, self.state, state
         );
         self.state = state;
+        self.report_status();
         state
     }
+
+    /// let the event watchers know if the status of the nexus has changed
+    pub(crate) fn report_status(&mut self) {
+        let status = self.status();
+        if status != self.reported_status {
+            nexus_events::emit(
+                &self.name,
+                None,
+                NexusEventType::NexusStatus {
+                    old: self.reported_status,
+                    new: status,
+                },
+            );
+            self.reported_status = status;
+        }
+    }
+
+    /// set the policy used to select the child to read from, the IO
+    /// channels pick it up with the next read they submit
+    pub fn set_read_policy(&mut self, policy: ReadPolicy) {
+        if self.read_policy != policy {
+            info!(
+                "{}: read policy changed from {:?} to {:?}",
+                self.name, self.read_policy, policy
+            );
+            self.read_policy = policy;
+        }
+    }
+
+    /// set the IOPS and bandwidth limits, IO held back by the previous limits
+    /// is submitted as soon as the new limits allow it
+    pub fn set_qos(&mut self, qos: NexusQos) {
+        if self.qos != qos {
+            info!(
+                "{}: QoS limits changed from {:?} to {:?}",
+                self.name, self.qos, qos
+            );
+            self.qos_limiter.set(&qos);
+            self.qos = qos;
+        }
+    }
+
+    /// the limits applied to the IO of the nexus
+    pub fn qos(&self) -> NexusQos {
+        self.qos
+    }
+
+    /// returns the number of reads and writes that have been held back by the
+    /// QoS limits
+    pub fn throttled_io(&self) -> (u64, u64) {
+        (
+            self.qos_limiter.throttled_reads(),
+            self.qos_limiter.throttled_writes(),
+        )
+    }
+
     /// returns the size in bytes of the nexus instance
     pub fn size(&self) -> u64 {
         u64::from(self.bdev.block_len()) * self.bdev.num_blocks()
@@ -472,6 +760,8 @@ impl Nexus {
             "{}: Dynamic reconfiguration event: {:?} completed {:?}",
             self.name, event, result
         );
+
+        self.report_status();
     }
 
     /// Opens the Nexus instance for IO
@@ -480,6 +770,8 @@ impl Nexus {
 
         self.try_open_children()?;
         self.sync_labels().await?;
+        self.load_dirty_logs().await;
+        self.load_rebuild_checkpoints().await;
         self.register()
     }
 
@@ -529,6 +821,8 @@ impl Nexus {
 
     /// Destroy the nexus
     pub async fn destroy(&mut self) -> Result<(), Error> {
+        self.destroying = true;
+
         // used to synchronize the destroy call
         extern "C" fn nexus_destroy_cb(arg: *mut c_void, rc: i32) {
             let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<bool>) };
@@ -548,8 +842,27 @@ impl Nexus {
         // gone
         self.bdev.unshare().await.unwrap();
 
-        for child in self.children.iter() {
-            self.stop_rebuild(&child.name).await.ok();
+        self.remove_scrub().await;
+
+        // the progress of the rebuilds is taken before they are stopped so
+        // that they can resume when the nexus is created again
+        let checkpoints = self.rebuild_checkpoints();
+        let stopped = self
+            .children
+            .iter()
+            .filter_map(|c| RebuildJob::lookup(&c.name).ok())
+            .map(|job| job.as_client().terminate())
+            .collect::<Vec<_>>();
+        for job in stopped {
+            job.await.ok();
+        }
+        for checkpoint in checkpoints.iter() {
+            self.write_rebuild_checkpoint(checkpoint).await;
+        }
+
+        // no more IO can reach the children so the dirty logs are final
+        if self.children.iter().any(|c| c.dirty_log.is_some()) {
+            self.persist_dirty_logs(true).await;
         }
 
         for child in self.children.iter_mut() {
@@ -664,6 +977,24 @@ impl Nexus {
 
             pio.ctx_as_mut_ref().status = io_status::FAILED;
         }
+
+        if chio.io_type() == io_type::READ {
+            NexusChannel::inner_from_channel(pio.io_channel())
+                .read_completed(&chio.bdev_as_ref());
+        }
+
+        // account for the IO on the child it was sent to
+        if success {
+            let attempts = pio.ctx_as_mut_ref().io_attempts;
+            let nexus = pio.nexus_as_ref();
+            let bdev = chio.bdev_as_ref();
+            if let Some(child) = nexus.children.iter().find(|c| {
+                c.bdev.as_ref().map(|b| b.as_ptr()) == Some(bdev.as_ptr())
+            }) {
+                child.stats.record(&chio, attempts < nexus.max_io_attempts);
+            }
+        }
+
         pio.assess(&mut chio, success);
         // always free the child IO
         chio.free();
@@ -707,20 +1038,23 @@ impl Nexus {
             warn!("{}: Failed to get io buffer for io {:?}", nexus.name, bio);
         }
 
-        let ch = NexusChannel::inner_from_channel(ch);
-        let (desc, ch) = ch.readers[ch.previous].io_tuple();
+        let channels = NexusChannel::inner_from_channel(ch);
+        let child = channels.previous;
+        let (desc, ch) = channels.readers[child].io_tuple();
         let ret = Self::readv_impl(io, desc, ch);
         if ret != 0 {
             let bio = Bio::from(io);
             let nexus = bio.nexus_as_ref();
             error!("{}: Failed to submit IO {:?}", nexus.name, bio);
+        } else {
+            channels.queue_depth[child] += 1;
         }
     }
 
     /// read vectored io from the underlying children.
     pub(crate) fn readv(&self, io: &Bio, channels: &mut NexusChannelInner) {
-        // we use RR to read from the children.
-        let child = channels.child_select();
+        // select the child to read from according to the read policy
+        let child = channels.child_select(self.read_policy);
 
         // if there is no buffer space for us allocated within the request
         // allocate it now, taking care of proper alignment
@@ -747,6 +1081,8 @@ impl Nexus {
             );
 
             io.fail();
+        } else {
+            channels.queue_depth[child] += 1;
         }
     }
 
@@ -803,6 +1139,7 @@ impl Nexus {
 
     /// write vectored IO to the underlying children.
     pub(crate) fn writev(&self, io: &Bio, channels: &NexusChannelInner) {
+        self.dirty_log_mark(io.offset(), io.num_blocks());
         // in case of writes, we want to write to all underlying children
         let results = channels
             .writers
@@ -833,6 +1170,7 @@ impl Nexus {
     }
 
     pub(crate) fn unmap(&self, io: &Bio, channels: &NexusChannelInner) {
+        self.dirty_log_mark(io.offset(), io.num_blocks());
         let results = channels
             .writers
             .iter()
@@ -859,6 +1197,7 @@ impl Nexus {
     }
 
     pub(crate) fn write_zeroes(&self, io: &Bio, channels: &NexusChannelInner) {
+        self.dirty_log_mark(io.offset(), io.num_blocks());
         let results = channels
             .writers
             .iter()
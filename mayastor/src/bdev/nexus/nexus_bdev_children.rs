@@ -222,6 +222,7 @@ impl Nexus {
             });
         }
 
+        self.cancel_child_scrub(uri).await;
         let cancelled_rebuilding_children =
             self.cancel_child_rebuild_jobs(uri).await;
 
@@ -256,6 +257,7 @@ impl Nexus {
     ) -> Result<NexusStatus, Error> {
         trace!("{}: Offline child request for {}", self.name, name);
 
+        self.cancel_child_scrub(name).await;
         let cancelled_rebuilding_children =
             self.cancel_child_rebuild_jobs(name).await;
 
@@ -303,6 +305,7 @@ impl Nexus {
             });
         }
 
+        self.cancel_child_scrub(name).await;
         let cancelled_rebuilding_children =
             self.cancel_child_rebuild_jobs(name).await;
 
@@ -17,11 +17,13 @@ use crate::{
             },
             nexus_channel::DREvent,
             nexus_child::{ChildState, NexusChild, Reason},
+            nexus_events::{self, NexusEventType},
+            nexus_metadata_content::RebuildCheckpoint,
         },
         VerboseError,
     },
     core::Reactors,
-    rebuild::{ClientOperations, RebuildError, RebuildJob, RebuildState},
+    rebuild::{ClientOperations, RebuildJob, RebuildPriority, RebuildState},
 };
 
 impl Nexus {
@@ -33,15 +35,21 @@ impl Nexus {
     ) -> Result<Receiver<RebuildState>, Error> {
         trace!("{}: start rebuild request for {}", self.name, name);
 
-        let src_child_name = match self
-            .children
-            .iter()
-            .find(|c| c.state() == ChildState::Open && c.name != name)
-        {
-            Some(child) => Ok(child.name.clone()),
-            None => Err(Error::NoRebuildSource {
-                name: self.name.clone(),
-            }),
+        let resume = self.resume_checkpoint(name).await;
+
+        let src_child_name = match resume.as_ref() {
+            // what has been copied before is only in sync with that source
+            Some(checkpoint) => Ok(checkpoint.source.clone()),
+            None => match self
+                .children
+                .iter()
+                .find(|c| c.state() == ChildState::Open && c.name != name)
+            {
+                Some(child) => Ok(child.name.clone()),
+                None => Err(Error::NoRebuildSource {
+                    name: self.name.clone(),
+                }),
+            },
         }?;
 
         let dst_child_name =
@@ -81,6 +89,19 @@ impl Nexus {
             name: self.name.clone(),
         })?;
 
+        // the segments are read from all the healthy children in turn, so a
+        // source which fails a read does not fail the rebuild
+        let sources = self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open && c.name != name)
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+        job.add_sources(&sources).context(CreateRebuildError {
+            child: name.to_owned(),
+            name: self.name.clone(),
+        })?;
+
         // We're now rebuilding the `dst_child` which means it HAS to become an
         // active participant in the frontend nexus bdev for Writes.
         // This is because the rebuild job copies from src to target child
@@ -91,10 +112,73 @@ impl Nexus {
         // rebuilt ranges in sync with the other children.
         self.reconfigure(DREvent::ChildRebuild).await;
 
-        job.as_client().start().context(RebuildOperationError {
-            job: name.to_owned(),
-            name: self.name.clone(),
-        })
+        let checkpoint = match resume {
+            Some(checkpoint) => {
+                info!(
+                    "{}: resuming the rebuild of child {} from block {}",
+                    self.name, name, checkpoint.next
+                );
+                // the writes missed whilst the rebuild was stopped are copied
+                // as well, the child receives them all from here on
+                let regions = if checkpoint.stopped {
+                    checkpoint.remaining_with(
+                        self.dirty_regions(name).unwrap_or_default(),
+                    )
+                } else {
+                    checkpoint.remaining()
+                };
+                job.set_regions(regions).context(CreateRebuildError {
+                    child: name.to_owned(),
+                    name: self.name.clone(),
+                })?;
+                RebuildCheckpoint {
+                    stopped: false,
+                    ..checkpoint
+                }
+            }
+            None => {
+                // Now that the child receives all frontend Write IO its dirty
+                // log, if it has one, holds every region that it has missed
+                // out on, so only those have to be copied.
+                if let Some(regions) = self.dirty_regions(name) {
+                    info!(
+                        "{}: rebuilding {} dirty region(s) of child {}",
+                        self.name,
+                        regions.len(),
+                        name
+                    );
+                    job.set_regions(regions).context(CreateRebuildError {
+                        child: name.to_owned(),
+                        name: self.name.clone(),
+                    })?;
+                }
+                RebuildCheckpoint::new()
+            }
+        };
+        self.get_child_by_name(name)?.rebuild_checkpoint = Some(checkpoint);
+
+        // the source being the last healthy child, the volume cannot afford
+        // to lose it before the rebuild has completed
+        let healthy = self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .count();
+        if healthy <= 1 {
+            job.set_priority(RebuildPriority::High);
+        }
+
+        let receiver =
+            job.as_client().start().context(RebuildOperationError {
+                job: name.to_owned(),
+                name: self.name.clone(),
+            })?;
+        nexus_events::emit(
+            &self.name,
+            Some(name),
+            NexusEventType::RebuildStarted,
+        );
+        Ok(receiver)
     }
 
     /// Terminates a rebuild in the background
@@ -140,6 +224,16 @@ impl Nexus {
         })
     }
 
+    /// Limit the bandwidth of a rebuild job to `rate` MiB/s, 0 for no limit
+    pub async fn set_rebuild_rate(
+        &mut self,
+        name: &str,
+        rate: u64,
+    ) -> Result<(), Error> {
+        self.get_rebuild_job(name)?.set_rate(rate);
+        Ok(())
+    }
+
     /// Return the state of a rebuild job
     pub async fn get_rebuild_state(
         &mut self,
@@ -234,10 +328,19 @@ impl Nexus {
         &mut self,
         job: &RebuildJob,
     ) -> Result<(), Error> {
+        let name = self.name.clone();
         let recovering_child = self.get_child_by_name(&job.destination)?;
 
+        let event = match job.state() {
+            RebuildState::Completed => NexusEventType::RebuildCompleted,
+            RebuildState::Stopped => NexusEventType::RebuildStopped,
+            _ => NexusEventType::RebuildFailed,
+        };
+        nexus_events::emit(&name, Some(&job.destination), event);
+
         match job.state() {
             RebuildState::Completed => {
+                recovering_child.dirty_log = None;
                 recovering_child.set_state(ChildState::Open);
                 NexusChild::save_state_change();
                 info!(
@@ -254,12 +357,6 @@ impl Nexus {
             RebuildState::Failed => {
                 // rebuild has failed so we need to set the child as faulted
                 // allowing the control plane to replace it with another
-                if let Some(RebuildError::ReadIoError {
-                    ..
-                }) = job.error
-                {
-                    // todo: retry rebuild using another child as source?
-                }
                 recovering_child.fault(Reason::RebuildFailed);
                 error!(
                     "Rebuild job for child {} of nexus {} failed, error: {}",
@@ -290,19 +387,51 @@ impl Nexus {
         })?;
 
         if !j.state().done() {
-            // Leave all states as they are
+            // Leave all states as they are, only checkpoint the progress
+            self.persist_rebuild_checkpoint(&job).await;
             return Ok(());
         }
 
+        // the checkpoints written by destroy are left alone, whatever the
+        // order in which the stopped jobs are reported
+        if !self.destroying {
+            match j.state() {
+                RebuildState::Completed | RebuildState::Failed => {
+                    self.clear_rebuild_checkpoint(&job).await
+                }
+                RebuildState::Stopped => {
+                    self.keep_rebuild_checkpoint(&job).await
+                }
+                _ => {}
+            }
+        }
+
         let complete_err = self.on_rebuild_complete_job(&j).await;
-        let remove_err = RebuildJob::remove(&job)
+        let failed_sources = RebuildJob::remove(&job)
             .context(RemoveRebuildJob {
                 child: job,
                 name: self.name.clone(),
             })
-            .map(|_| ());
+            .map(|job| match job.state() {
+                RebuildState::Completed => job.failed_sources(),
+                _ => Vec::new(),
+            });
+
+        // The sources which failed a read that another source served are
+        // only faulted once the job is removed, as faulting a child
+        // terminates the rebuild jobs reading from it.
+        for source in failed_sources.iter().flatten() {
+            if let Err(e) = self.fault_child(source, Reason::IoError).await {
+                error!(
+                    "{}: failed to fault rebuild source {}: {}",
+                    self.name,
+                    source,
+                    e.verbose()
+                );
+            }
+        }
 
-        complete_err.and(remove_err)
+        complete_err.and(failed_sources.map(|_| ()))
     }
 
     /// Rebuild updated callback when a rebuild job state updates
@@ -0,0 +1,145 @@
+//! Implements online growing of a nexus.
+
+use std::{collections::HashMap, convert::TryFrom};
+
+use nix::errno::Errno;
+use snafu::ResultExt;
+
+use crate::{
+    bdev::nexus::{
+        nexus_bdev::{
+            Error,
+            GrowChild,
+            Nexus,
+            NexusStatus,
+            ResizeNexus,
+            WriteLabel,
+        },
+        nexus_label::{Aligned, GPTHeader},
+    },
+    lvs::{Error as LvsError, Lvol},
+};
+
+impl Nexus {
+    /// The number of blocks a child needs to hold a data partition of
+    /// `data_blocks` blocks starting at block `data_offset`. The data
+    /// partition is followed by the backup partition table and the backup
+    /// GPT header.
+    pub(crate) fn child_blocks(
+        data_offset: u64,
+        data_blocks: u64,
+        block_len: u64,
+    ) -> u64 {
+        data_offset
+            + data_blocks
+            + Aligned::get_blocks(GPTHeader::PARTITION_TABLE_SIZE, block_len)
+            + 1
+    }
+
+    /// Grow the nexus to `size` bytes whilst it is online. Children that are
+    /// local lvols are grown as needed, any other child, such as a replica on
+    /// another node, must have been grown beforehand through ResizeReplica
+    /// on its node. No child is grown unless all of them can be. The labels of
+    /// all children are then rewritten for the new size and the new block
+    /// count is announced to the bdev layer, which lets the NVMe-oF target
+    /// notify the connected hosts of the new capacity.
+    pub async fn resize(&mut self, size: u64) -> Result<(), Error> {
+        if size < self.size {
+            return Err(Error::ShrinkNexus {
+                name: self.name.clone(),
+                current: self.size,
+                requested: size,
+            });
+        }
+
+        if size == self.size {
+            return Ok(());
+        }
+
+        // a child that is out of the IO path would miss the new label
+        if self.status() != NexusStatus::Online {
+            return Err(Error::ResizeNotOnline {
+                name: self.name.clone(),
+            });
+        }
+
+        // the crypto bdev on top of the nexus keeps its size
+        if matches!(&self.share_handle, Some(h) if *h != self.name) {
+            return Err(Error::ResizeEncrypted {
+                name: self.name.clone(),
+            });
+        }
+
+        let block_len = u64::from(self.bdev.block_len());
+        let size_blocks = size / block_len;
+
+        let required =
+            Self::child_blocks(self.data_ent_offset, size_blocks, block_len);
+
+        // every child is checked before any is grown, such that a child that
+        // cannot be grown does not leave the others grown for nothing
+        let mut grow = Vec::new();
+        let mut allocation: HashMap<String, u64> = HashMap::new();
+        for child in self.children.iter() {
+            let bdev = match child.bdev.as_ref() {
+                Some(bdev) if bdev.num_blocks() < required => bdev.clone(),
+                _ => continue,
+            };
+
+            let lvol =
+                Lvol::try_from(bdev).map_err(|_| Error::ChildTooSmall {
+                    child: child.name.clone(),
+                    name: self.name.clone(),
+                    blocks: required,
+                })?;
+
+            // thick lvols sharing a pool all allocate from it
+            let lvs = lvol.lvs();
+            let needed = allocation.entry(lvs.name().to_string()).or_default();
+            *needed += lvol.grow_allocation(required * block_len);
+            if *needed > lvs.available() {
+                return Err(Error::GrowChild {
+                    source: LvsError::RepResizeNoSpace {
+                        source: Errno::ENOSPC,
+                        name: lvol.name(),
+                        pool: lvs.name().to_string(),
+                        size: required * block_len,
+                    },
+                    child: child.name.clone(),
+                    name: self.name.clone(),
+                });
+            }
+
+            grow.push((child.name.clone(), lvol));
+        }
+
+        for (child, lvol) in grow {
+            lvol.resize(required * block_len).await.context(GrowChild {
+                child,
+                name: self.name.clone(),
+            })?;
+        }
+
+        let label = self.grow_labels(self.min_num_blocks()).await.context(
+            WriteLabel {
+                name: self.name.clone(),
+            },
+        )?;
+
+        // the data partition may be larger than requested as lvols are
+        // allocated in clusters
+        let blocks = std::cmp::min(size_blocks, label.get_block_count());
+        self.bdev
+            .notify_block_count_change(blocks)
+            .context(ResizeNexus {
+                name: self.name.clone(),
+            })?;
+
+        info!(
+            "{}: resized from {} to {} bytes",
+            self.name, self.size, size
+        );
+        self.size = size;
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+//!
+//! Scrubbing of the nexus, verifying that its healthy children hold the same
+//! data. The scrub is run by a scrub job, of which there is at most one per
+//! nexus; the job of the last scrub is kept until the next one is started so
+//! that its results can still be queried.
+
+use futures::channel::oneshot::Receiver;
+use snafu::ResultExt;
+
+use rpc::mayastor::{ScrubMismatch, ScrubProgressReply};
+
+use crate::{
+    bdev::nexus::{
+        nexus_bdev::{CreateScrubError, Error, Nexus, ScrubNotFound},
+        nexus_child::ChildState,
+    },
+    rebuild::{RebuildState, ScrubJob},
+};
+
+impl Nexus {
+    /// Starts a scrub job comparing all the healthy children and returns a
+    /// receiver channel which can be used to await its completion. The
+    /// divergent blocks are rewritten from child `authority`, if given.
+    pub async fn start_scrub(
+        &mut self,
+        authority: Option<&str>,
+    ) -> Result<Receiver<RebuildState>, Error> {
+        trace!(
+            "{}: start scrub request with authority {:?}",
+            self.name,
+            authority
+        );
+
+        let children = self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+        if children.len() < 2 {
+            return Err(Error::ScrubTooFewChildren {
+                name: self.name.clone(),
+            });
+        }
+
+        if let Some(authority) = authority {
+            if !children.iter().any(|c| c == authority) {
+                return Err(Error::ScrubAuthorityNotHealthy {
+                    child: authority.to_owned(),
+                    name: self.name.clone(),
+                });
+            }
+        }
+
+        let job = ScrubJob::create(
+            &self.name,
+            &children,
+            authority,
+            std::ops::Range::<u64> {
+                start: self.data_ent_offset,
+                end: self.bdev.num_blocks() + self.data_ent_offset,
+            },
+        )
+        .context(CreateScrubError {
+            name: self.name.clone(),
+        })?;
+
+        job.start().context(CreateScrubError {
+            name: self.name.clone(),
+        })
+    }
+
+    /// Stops the scrub job of the nexus and waits for it to stop
+    pub async fn stop_scrub(&self) -> Result<(), Error> {
+        let job = ScrubJob::lookup(&self.name).context(ScrubNotFound {
+            name: self.name.clone(),
+        })?;
+        let _ = job.stop().await;
+        Ok(())
+    }
+
+    /// Returns the progress of the scrub job of the nexus along with the
+    /// ranges found to differ so far
+    pub fn get_scrub_progress(&self) -> Result<ScrubProgressReply, Error> {
+        let job = ScrubJob::lookup(&self.name).context(ScrubNotFound {
+            name: self.name.clone(),
+        })?;
+
+        Ok(ScrubProgressReply {
+            state: job.state().to_string(),
+            progress: job.stats().progress as u32,
+            mismatches: job
+                .mismatches()
+                .iter()
+                .map(|m| ScrubMismatch {
+                    offset: m.offset,
+                    num_blocks: m.num_blocks,
+                    repaired: m.repaired,
+                })
+                .collect(),
+        })
+    }
+
+    /// Stops the scrub job if it compares child `name`, which is going away
+    pub(crate) async fn cancel_child_scrub(&self, name: &str) {
+        if let Ok(job) = ScrubJob::lookup(&self.name) {
+            if job.children.iter().any(|c| c == name) {
+                let _ = job.stop().await;
+            }
+        }
+    }
+
+    /// Stops the scrub job of the nexus, if any, and removes it
+    pub(crate) async fn remove_scrub(&self) {
+        if let Ok(job) = ScrubJob::lookup(&self.name) {
+            let _ = job.stop().await;
+            let _ = ScrubJob::remove(&self.name);
+        }
+    }
+}
@@ -1,28 +1,351 @@
 //! Implements snapshot operations on a nexus.
 
-use rpc::mayastor::CreateSnapshotReply;
+use std::{
+    convert::TryFrom,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use snafu::ResultExt;
+
+use rpc::mayastor::{ChildSnapshot, CreateSnapshotReply};
 
 use crate::{
-    bdev::nexus::nexus_bdev::{Error, Nexus},
-    core::BdevHandle,
+    bdev::nexus::{
+        nexus_bdev::{
+            Error,
+            Nexus,
+            NexusTarget,
+            RevertPauseIo,
+            RevertResumeIo,
+            SnapshotPauseIo,
+            SnapshotResumeIo,
+        },
+        nexus_channel::DREvent,
+        nexus_child::{ChildState, NexusChild, Reason},
+    },
+    core::{Bdev, BdevHandle, RangeContext},
     lvs::Lvol,
 };
 
+/// How a child of the nexus is reverted to a snapshot
+enum ChildRevert {
+    /// a local replica along with its snapshot
+    Local(Lvol, Lvol),
+    /// a remote replica, through a handle to send the admin command on
+    Remote(BdevHandle),
+}
+
 impl Nexus {
-    /// Create a snapshot on all children
+    /// Create a crash consistent snapshot on all children. Writes to the
+    /// nexus are paused for the duration by locking its entire range, which
+    /// also waits for the writes in flight to complete.
     pub async fn create_snapshot(&self) -> Result<CreateSnapshotReply, Error> {
-        if let Ok(h) = BdevHandle::open_with_bdev(&self.bdev, false) {
-            match h.create_snapshot().await {
-                Ok(t) => Ok(CreateSnapshotReply {
-                    name: Lvol::format_snapshot_name(&self.bdev.name(), t),
-                }),
-                Err(e) => Err(Error::FailedCreateSnapshot {
-                    name: self.bdev.name(),
-                    source: e,
-                }),
+        let desc = self.bdev.open(false).map_err(|_| Error::FailedGetHandle)?;
+        let ch = desc.get_channel().ok_or(Error::FailedGetHandle)?;
+        let mut ctx = RangeContext::new(0, self.bdev.num_blocks());
+
+        desc.lock_lba_range(&mut ctx, &ch)
+            .await
+            .context(SnapshotPauseIo {
+                name: self.name.clone(),
+            })?;
+
+        let snapshot_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut children = Vec::new();
+        for child in self.children.iter() {
+            children.push(self.snapshot_child(child, snapshot_time).await);
+        }
+
+        desc.unlock_lba_range(&mut ctx, &ch).await.context(
+            SnapshotResumeIo {
+                name: self.name.clone(),
+            },
+        )?;
+
+        let complete = children.iter().all(|c| c.created);
+        if !complete {
+            warn!(
+                "{}: snapshot at {} is missing on some children",
+                self.name, snapshot_time
+            );
+        }
+
+        Ok(CreateSnapshotReply {
+            name: Lvol::format_snapshot_name(&self.bdev.name(), snapshot_time),
+            children,
+            complete,
+            timestamp: snapshot_time,
+        })
+    }
+
+    /// Revert the nexus to the snapshot taken at `snapshot_time` by
+    /// create_snapshot. All IO to the nexus is stopped whilst its replicas
+    /// are reverted, for which the nexus must either be unpublished or
+    /// published over nvmf, in which case its hosts are paused. Local
+    /// replicas are reverted directly whereas remote replicas are sent our
+    /// vendor specific NVMe admin command. Any other child no longer matches
+    /// the data of the nexus and is faulted so that it gets rebuilt.
+    pub async fn revert_to_snapshot(
+        &mut self,
+        snapshot_time: u64,
+    ) -> Result<(), Error> {
+        if self.children.iter().any(|c| c.rebuilding()) {
+            return Err(Error::RevertRebuilding {
+                name: self.name.clone(),
+            });
+        }
+
+        let reverts = self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .filter_map(|c| {
+                Self::child_revert(c, snapshot_time)
+                    .map(|revert| (c.name.clone(), revert))
+            })
+            .collect::<Vec<_>>();
+
+        if reverts.is_empty() {
+            return Err(Error::SnapshotNotFound {
+                name: self.name.clone(),
+                snapshot_time,
+            });
+        }
+
+        match self.nexus_target {
+            None | Some(NexusTarget::NexusNvmfTarget(_)) => {}
+            Some(_) => {
+                return Err(Error::RevertPublished {
+                    name: self.name.clone(),
+                })
             }
+        }
+
+        // the findings of a scrub would no longer apply
+        self.remove_scrub().await;
+
+        if let Some(NexusTarget::NexusNvmfTarget(target)) = &self.nexus_target {
+            target.pause().await.context(RevertPauseIo {
+                name: self.name.clone(),
+            })?;
+        }
+
+        let result = self.revert_children(reverts, snapshot_time).await;
+
+        // the hosts are resumed whatever the outcome, by which time the
+        // children that were not reverted have left the IO path
+        let resumed = match &self.nexus_target {
+            Some(NexusTarget::NexusNvmfTarget(target)) => {
+                target.resume().await.context(RevertResumeIo {
+                    name: self.name.clone(),
+                })
+            }
+            _ => Ok(()),
+        };
+
+        let reverted = result?;
+        resumed?;
+
+        self.sync_labels().await?;
+
+        info!(
+            "{}: reverted to snapshot {} on {:?}",
+            self.name, snapshot_time, reverted
+        );
+        Ok(())
+    }
+
+    /// Revert the given children to their snapshot taken at `snapshot_time`,
+    /// returning the names of the children reverted. The range of the nexus
+    /// is locked meanwhile, which also waits for the writes in flight of any
+    /// other user of the nexus. Before it is unlocked, the children that were
+    /// not reverted are faulted as they no longer match the data of the
+    /// nexus, unless no child could be reverted at all.
+    async fn revert_children(
+        &mut self,
+        reverts: Vec<(String, ChildRevert)>,
+        snapshot_time: u64,
+    ) -> Result<Vec<String>, Error> {
+        let desc = self.bdev.open(false).map_err(|_| Error::FailedGetHandle)?;
+        let ch = desc.get_channel().ok_or(Error::FailedGetHandle)?;
+        let mut ctx = RangeContext::new(0, self.bdev.num_blocks());
+
+        desc.lock_lba_range(&mut ctx, &ch)
+            .await
+            .context(SnapshotPauseIo {
+                name: self.name.clone(),
+            })?;
+
+        let mut reverted = Vec::new();
+        let mut failed = Vec::new();
+        for (name, revert) in reverts {
+            let result = match revert {
+                ChildRevert::Local(lvol, snapshot) => {
+                    lvol.revert(&snapshot).await.map_err(|e| e.to_string())
+                }
+                ChildRevert::Remote(hndl) => hndl
+                    .revert_snapshot_at(snapshot_time)
+                    .await
+                    .map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(_) => reverted.push(name),
+                Err(e) => {
+                    error!(
+                        "{}: failed to revert child {}: {}",
+                        self.name, name, e
+                    );
+                    failed.push(name);
+                }
+            }
+        }
+
+        if !reverted.is_empty() {
+            self.fault_unreverted(&reverted, &failed, snapshot_time)
+                .await;
+        }
+
+        desc.unlock_lba_range(&mut ctx, &ch).await.context(
+            SnapshotResumeIo {
+                name: self.name.clone(),
+            },
+        )?;
+
+        if reverted.is_empty() {
+            return Err(Error::RevertFailed {
+                name: self.name.clone(),
+            });
+        }
+
+        Ok(reverted)
+    }
+
+    /// Fault the children that were not reverted, which either failed to
+    /// revert or lack the snapshot, and take them out of the IO path.
+    async fn fault_unreverted(
+        &mut self,
+        reverted: &[String],
+        failed: &[String],
+        snapshot_time: u64,
+    ) {
+        for child in self
+            .children
+            .iter_mut()
+            .filter(|c| !reverted.contains(&c.name))
+        {
+            if failed.contains(&child.name) {
+                child.fault(Reason::IoError);
+            } else if child.state() == ChildState::Open {
+                warn!(
+                    "{}: child {} lacks snapshot {}",
+                    self.name, child.name, snapshot_time
+                );
+                child.fault(Reason::SnapshotMissing);
+            } else {
+                // whatever it missed so far, it needs a full rebuild now
+                child.dirty_log = None;
+            }
+        }
+        self.reconfigure(DREvent::ChildFault).await;
+    }
+
+    /// Returns how the child is reverted to its snapshot taken at the given
+    /// time, if it can be. A local replica must have the snapshot, whether a
+    /// remote replica has it only shows when it is reverted.
+    fn child_revert(
+        child: &NexusChild,
+        snapshot_time: u64,
+    ) -> Option<ChildRevert> {
+        let bdev = child.bdev.as_ref()?;
+
+        if let Ok(lvol) = Lvol::try_from(bdev.clone()) {
+            let snapshot = Lvol::try_from(Bdev::lookup_by_name(
+                &Lvol::format_snapshot_name(&lvol.name(), snapshot_time),
+            )?)
+            .ok()?;
+
+            if snapshot.is_snapshot() {
+                Some(ChildRevert::Local(lvol, snapshot))
+            } else {
+                None
+            }
+        } else if bdev.driver() == "nvme" {
+            child
+                .get_dev()
+                .map(|(_, hndl)| ChildRevert::Remote(hndl))
+                .ok()
         } else {
-            Err(Error::FailedGetHandle)
+            None
         }
     }
+
+    /// Snapshot a single child, local replicas are snapshotted directly
+    /// whereas remote replicas are sent our vendor specific NVMe admin
+    /// command.
+    async fn snapshot_child(
+        &self,
+        child: &NexusChild,
+        snapshot_time: u64,
+    ) -> ChildSnapshot {
+        let mut snapshot = ChildSnapshot {
+            uri: child.name.clone(),
+            name: String::new(),
+            created: false,
+        };
+
+        let bdev = match child.bdev.as_ref() {
+            Some(bdev) if child.state() == ChildState::Open => bdev.clone(),
+            _ => {
+                warn!(
+                    "{}: not snapshotting child {} in state {}",
+                    self.name,
+                    child.name,
+                    child.state()
+                );
+                return snapshot;
+            }
+        };
+
+        if let Ok(lvol) = Lvol::try_from(bdev.clone()) {
+            snapshot.name =
+                Lvol::format_snapshot_name(&lvol.name(), snapshot_time);
+            match lvol.snapshot(&snapshot.name).await {
+                Ok(_) => snapshot.created = true,
+                Err(e) => error!(
+                    "{}: failed to snapshot child {}: {}",
+                    self.name, child.name, e
+                ),
+            }
+        } else if bdev.driver() == "nvme" {
+            // replicas are shared under an NQN that ends in their name
+            let replica = child.name.rsplit(':').next().unwrap_or_default();
+            snapshot.name = Lvol::format_snapshot_name(replica, snapshot_time);
+            match child.get_dev() {
+                Ok((_, hndl)) => {
+                    match hndl.create_snapshot_at(snapshot_time).await {
+                        Ok(_) => snapshot.created = true,
+                        Err(e) => error!(
+                            "{}: failed to snapshot child {}: {}",
+                            self.name, child.name, e
+                        ),
+                    }
+                }
+                Err(e) => error!(
+                    "{}: failed to get handle of child {}: {}",
+                    self.name, child.name, e
+                ),
+            }
+        } else {
+            warn!(
+                "{}: child {} does not support snapshots",
+                self.name, child.name
+            );
+        }
+
+        snapshot
+    }
 }
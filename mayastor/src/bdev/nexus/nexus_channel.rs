@@ -1,6 +1,6 @@
 //!
 //! IO is driven by means of so called channels.
-use std::{convert::TryFrom, ffi::c_void};
+use std::{collections::VecDeque, convert::TryFrom, ffi::c_void};
 
 use spdk_sys::{
     spdk_for_each_channel,
@@ -9,11 +9,22 @@ use spdk_sys::{
     spdk_io_channel_iter,
     spdk_io_channel_iter_get_channel,
     spdk_io_channel_iter_get_io_device,
+    spdk_poller,
+    spdk_poller_register,
+    spdk_poller_unregister,
 };
 
 use crate::{
-    bdev::{nexus::nexus_child::ChildState, Nexus},
-    core::BdevHandle,
+    bdev::{
+        nexus::{
+            nexus_bdev::ReadPolicy,
+            nexus_child::{ChildState, NexusChild},
+            nexus_fn_table::NexusFnTable,
+            nexus_io::{io_type, Bio},
+        },
+        Nexus,
+    },
+    core::{Bdev, BdevHandle},
 };
 
 /// io channel, per core
@@ -28,10 +39,21 @@ pub(crate) struct NexusChannel {
 pub(crate) struct NexusChannelInner {
     pub(crate) writers: Vec<BdevHandle>,
     pub(crate) readers: Vec<BdevHandle>,
+    /// number of reads outstanding on each of the readers
+    pub(crate) queue_depth: Vec<u32>,
+    /// whether each of the readers is local to the nexus
+    local: Vec<bool>,
     pub(crate) previous: usize,
     device: *mut c_void,
+    /// IO held back by the QoS limits of the nexus, in submission order
+    throttled: VecDeque<Bio>,
+    /// poller submitting the held back IO once the limits allow it
+    qos_poller: *mut spdk_poller,
 }
 
+/// the interval in microseconds at which held back IO is retried
+const QOS_POLL_PERIOD_US: u64 = 1000;
+
 #[derive(Debug)]
 /// Dynamic Reconfiguration Events occur when a child is added or removed
 pub enum DREvent {
@@ -50,17 +72,113 @@ pub enum DREvent {
 }
 
 impl NexusChannelInner {
-    /// very simplistic routine to rotate between children for read operations
-    pub(crate) fn child_select(&mut self) -> usize {
+    /// select the child to read from according to the given policy. The
+    /// candidates are considered in round robin order starting after the
+    /// previously selected one, so that ties are rotated as well.
+    pub(crate) fn child_select(&mut self, policy: ReadPolicy) -> usize {
         debug_assert!(!self.readers.is_empty());
-        if self.previous < self.readers.len() - 1 {
-            self.previous += 1;
-        } else {
-            self.previous = 0;
-        }
+        let len = self.readers.len();
+        let previous = self.previous;
+        let mut candidates = (1 ..= len).map(|n| (previous + n) % len);
+
+        let selected = match policy {
+            ReadPolicy::RoundRobin => candidates.next(),
+            ReadPolicy::LeastQueueDepth => {
+                candidates.min_by_key(|i| self.queue_depth[*i])
+            }
+            ReadPolicy::PreferLocal => candidates
+                .clone()
+                .find(|i| self.local[*i])
+                .or_else(|| candidates.next()),
+        };
+
+        self.previous = selected.unwrap_or(0);
         self.previous
     }
 
+    /// account for a completed read on the reader with the given bdev
+    pub(crate) fn read_completed(&mut self, bdev: &Bdev) {
+        if let Some(i) = self
+            .readers
+            .iter()
+            .position(|r| r.get_bdev().as_ptr() == bdev.as_ptr())
+        {
+            // the counters are reset when the channel is refreshed
+            self.queue_depth[i] = self.queue_depth[i].saturating_sub(1);
+        }
+    }
+
+    /// hold back a read or write if it exceeds the QoS limits of the nexus,
+    /// or if IO submitted before it is still held back. Returns true if the
+    /// IO has been queued, it is submitted by the QoS poller later on.
+    pub(crate) fn throttle(&mut self, bio: &Bio) -> bool {
+        let io = bio.io_type();
+        if io != io_type::READ && io != io_type::WRITE {
+            return false;
+        }
+
+        let limiter = &bio.nexus_as_ref().qos_limiter;
+        if self.throttled.is_empty()
+            && limiter.admit(io, bio.num_blocks() * bio.block_len())
+        {
+            return false;
+        }
+
+        limiter.throttled(io);
+        self.throttled.push_back(bio.clone());
+
+        if self.qos_poller.is_null() {
+            self.qos_poller = unsafe {
+                spdk_poller_register(
+                    Some(Self::qos_poll),
+                    self as *mut _ as *mut c_void,
+                    QOS_POLL_PERIOD_US,
+                )
+            };
+        }
+
+        true
+    }
+
+    /// submit the held back IO for as long as the limits allow it, the
+    /// poller is unregistered once the queue has been drained
+    extern "C" fn qos_poll(ctx: *mut c_void) -> i32 {
+        let inner = unsafe { &mut *(ctx as *mut NexusChannelInner) };
+        let mut submitted = 0;
+
+        while let Some(bio) = inner.throttled.front() {
+            if !bio
+                .nexus_as_ref()
+                .qos_limiter
+                .admit(bio.io_type(), bio.num_blocks() * bio.block_len())
+            {
+                break;
+            }
+
+            let mut bio = inner.throttled.pop_front().unwrap();
+            NexusFnTable::io_submit_or_resubmit(bio.io_channel(), &mut bio);
+            submitted += 1;
+        }
+
+        if inner.throttled.is_empty() {
+            unsafe { spdk_poller_unregister(&mut inner.qos_poller) };
+        }
+
+        (submitted > 0) as i32
+    }
+
+    /// add a child to both the writers and readers
+    fn add_reader(&mut self, child: &NexusChild) {
+        self.writers.push(
+            BdevHandle::try_from(child.get_descriptor().unwrap()).unwrap(),
+        );
+        self.readers.push(
+            BdevHandle::try_from(child.get_descriptor().unwrap()).unwrap(),
+        );
+        self.queue_depth.push(0);
+        self.local.push(child.is_local().unwrap_or(false));
+    }
+
     /// refreshing our channels simply means that we either have a child going
     /// online or offline. We don't know which child has gone, or was added, so
     /// we simply put back all the channels, and reopen the bdevs that are in
@@ -86,21 +204,16 @@ impl NexusChannelInner {
         // channel
         self.writers.clear();
         self.readers.clear();
+        self.queue_depth.clear();
+        self.local.clear();
         self.previous = 0;
 
         // iterate over all our children which are in the open state
         nexus
             .children
-            .iter_mut()
+            .iter()
             .filter(|c| c.state() == ChildState::Open)
-            .for_each(|c| {
-                self.writers.push(
-                    BdevHandle::try_from(c.get_descriptor().unwrap()).unwrap(),
-                );
-                self.readers.push(
-                    BdevHandle::try_from(c.get_descriptor().unwrap()).unwrap(),
-                );
-            });
+            .for_each(|c| self.add_reader(c));
 
         // then add write-only children
         if !self.readers.is_empty() {
@@ -142,23 +255,19 @@ impl NexusChannel {
         let mut channels = Box::new(NexusChannelInner {
             writers: Vec::new(),
             readers: Vec::new(),
+            queue_depth: Vec::new(),
+            local: Vec::new(),
             previous: 0,
             device,
+            throttled: VecDeque::new(),
+            qos_poller: std::ptr::null_mut(),
         });
 
         nexus
             .children
-            .iter_mut()
+            .iter()
             .filter(|c| c.state() == ChildState::Open)
-            .map(|c| {
-                channels.writers.push(
-                    BdevHandle::try_from(c.get_descriptor().unwrap()).unwrap(),
-                );
-                channels.readers.push(
-                    BdevHandle::try_from(c.get_descriptor().unwrap()).unwrap(),
-                );
-            })
-            .for_each(drop);
+            .for_each(|c| channels.add_reader(c));
         ch.inner = Box::into_raw(channels);
         0
     }
@@ -170,6 +279,12 @@ impl NexusChannel {
         let inner = NexusChannel::from_raw(ctx).inner_mut();
         inner.writers.clear();
         inner.readers.clear();
+
+        // nobody is left to submit the held back IO
+        inner.throttled.drain(..).for_each(|bio| bio.fail());
+        if !inner.qos_poller.is_null() {
+            unsafe { spdk_poller_unregister(&mut inner.qos_poller) };
+        }
     }
 
     /// function called when we receive a Dynamic Reconfigure event (DR)
@@ -11,6 +11,10 @@ use crate::{
         nexus::{
             nexus_child::ChildState::Faulted,
             nexus_child_status_config::ChildStatusConfig,
+            nexus_dirty_log::DirtyLog,
+            nexus_events::{self, NexusEventType},
+            nexus_metadata_content::RebuildCheckpoint,
+            nexus_stats::IoStats,
         },
         NexusErrStore,
     },
@@ -63,6 +67,8 @@ pub enum Reason {
     IoError,
     /// the child has been explicitly faulted due to a rpc call
     Rpc,
+    /// the nexus has been reverted to a snapshot the child does not have
+    SnapshotMissing,
 }
 
 impl Display for Reason {
@@ -78,6 +84,10 @@ impl Display for Reason {
             }
             Self::IoError => write!(f, "The child had too many I/O errors"),
             Self::Rpc => write!(f, "The child is faulted due to a rpc call"),
+            Self::SnapshotMissing => write!(
+                f,
+                "The child lacks the snapshot the nexus was reverted to"
+            ),
         }
     }
 }
@@ -129,6 +139,15 @@ pub struct NexusChild {
     /// record of most-recent IO errors
     #[serde(skip_serializing)]
     pub(crate) err_store: Option<NexusErrStore>,
+    /// regions written to whilst the child was out of the IO path
+    #[serde(skip_serializing)]
+    pub(crate) dirty_log: Option<DirtyLog>,
+    /// checkpoint of the rebuild of this child
+    #[serde(skip_serializing)]
+    pub(crate) rebuild_checkpoint: Option<RebuildCheckpoint>,
+    /// counters of the IO completed by the child
+    #[serde(skip_serializing)]
+    pub(crate) stats: IoStats,
 }
 
 impl Display for NexusChild {
@@ -159,6 +178,16 @@ impl NexusChild {
             state.to_string(),
         );
 
+        if self.state != state {
+            nexus_events::emit(
+                &self.parent,
+                Some(&self.name),
+                NexusEventType::ChildState {
+                    old: self.state,
+                    new: state,
+                },
+            );
+        }
         self.state = state;
     }
 
@@ -235,12 +264,21 @@ impl NexusChild {
     /// Fault the child with a specific reason.
     /// We do not close the child if it is out-of-sync because it will
     /// subsequently be rebuilt.
+    /// Only a child faulted through a rpc call keeps track of the writes it
+    /// misses, for any other reason we can no longer tell which regions are
+    /// in sync.
     pub(crate) fn fault(&mut self, reason: Reason) {
         match reason {
             Reason::OutOfSync => {
                 self.set_state(ChildState::Faulted(reason));
             }
+            Reason::Rpc => {
+                self.start_dirty_log();
+                self._close();
+                self.set_state(ChildState::Faulted(reason));
+            }
             _ => {
+                self.dirty_log = None;
                 self._close();
                 self.set_state(ChildState::Faulted(reason));
             }
@@ -251,6 +289,7 @@ impl NexusChild {
     /// Set the child as temporarily offline
     /// TODO: channels need to be updated when bdevs are closed
     pub(crate) fn offline(&mut self) {
+        self.start_dirty_log();
         self.close();
         NexusChild::save_state_change();
     }
@@ -311,6 +350,9 @@ impl NexusChild {
         // just to be explicit
         let desc = self.desc.take();
         drop(desc);
+        // the child misses out on writes from here on, so what a rebuild has
+        // copied to it so far can no longer be relied upon
+        self.rebuild_checkpoint = None;
     }
 
     /// close the bdev -- we have no means of determining if this succeeds
@@ -330,6 +372,9 @@ impl NexusChild {
             ch: std::ptr::null_mut(),
             state: ChildState::Init,
             err_store: None,
+            dirty_log: None,
+            rebuild_checkpoint: None,
+            stats: IoStats::default(),
         }
     }
 
@@ -0,0 +1,327 @@
+//!
+//! Dirty region log (write bitmap) of the nexus children.
+//!
+//! Whilst a child that was in sync is kept out of the IO path of the nexus
+//! (i.e. it has been offlined or faulted through an RPC call) every write,
+//! unmap and write zeroes that is submitted to the nexus is recorded in a per
+//! child bitmap, where each bit covers a fixed size region of the data
+//! partition. When the child is brought back, the rebuild only has to copy
+//! the regions that are marked dirty rather than the whole data partition.
+//!
+//! Writes that failed on a child are not tracked, so a child that is faulted
+//! because of IO errors (or a failed rebuild) always gets a full rebuild.
+//!
+//! The logs are persisted in the "MayaMeta" partition of the healthy children.
+//! Logs written while the nexus is live are flagged as not clean, as the in
+//! memory bitmap moves on after they have been written; only logs written when
+//! the nexus is destroyed are trusted when the nexus is created again.
+
+use std::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use crate::{
+    bdev::nexus::{
+        nexus_bdev::Nexus,
+        nexus_child::{ChildState, NexusChild},
+        nexus_metadata::MetaDataError,
+        nexus_metadata_content::{
+            DirtyLogContent,
+            NexusConfig,
+            NexusDirtyLogs,
+        },
+    },
+    rebuild::SEGMENT_SIZE,
+};
+
+/// Bitmap of the regions of a child that have been written to whilst the
+/// child was not receiving any writes. Bits are set from the IO path of any
+/// core, hence the atomics.
+#[derive(Debug)]
+pub struct DirtyLog {
+    /// number of blocks covered by a single bit
+    region_blks: u64,
+    /// number of blocks covered by the log
+    num_blocks: u64,
+    bitmap: Vec<AtomicU64>,
+}
+
+impl DirtyLog {
+    /// Upper bound on the number of regions, which keeps the log of a large
+    /// child well within the size of the metadata partition
+    const MAX_REGIONS: u64 = 1 << 18;
+
+    /// Create a clean log covering `num_blocks` blocks. The region size is a
+    /// multiple of the rebuild segment size, growing with the size of the
+    /// child.
+    pub fn new(num_blocks: u64, block_len: u32) -> Self {
+        let mut region_blks =
+            std::cmp::max(SEGMENT_SIZE / u64::from(block_len), 1);
+        while num_blocks / region_blks >= Self::MAX_REGIONS {
+            region_blks *= 2;
+        }
+
+        let regions = (num_blocks + region_blks - 1) / region_blks;
+        Self {
+            region_blks,
+            num_blocks,
+            bitmap: (0 .. (regions + 63) / 64)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+        }
+    }
+
+    /// Mark all regions overlapping with the given block range as dirty
+    pub fn mark(&self, offset: u64, num_blocks: u64) {
+        if num_blocks == 0 || offset >= self.num_blocks {
+            return;
+        }
+
+        let last = std::cmp::min(offset + num_blocks, self.num_blocks) - 1;
+        for region in offset / self.region_blks ..= last / self.region_blks {
+            self.bitmap[(region / 64) as usize]
+                .fetch_or(1u64 << (region % 64), Ordering::Relaxed);
+        }
+    }
+
+    fn is_dirty(&self, region: u64) -> bool {
+        self.bitmap[(region / 64) as usize].load(Ordering::Relaxed)
+            & (1u64 << (region % 64))
+            != 0
+    }
+
+    /// Returns the dirty block ranges, adjacent dirty regions are merged
+    pub fn dirty_ranges(&self) -> Vec<Range<u64>> {
+        let regions =
+            (self.num_blocks + self.region_blks - 1) / self.region_blks;
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+
+        for region in (0 .. regions).filter(|r| self.is_dirty(*r)) {
+            let start = region * self.region_blks;
+            let end = std::cmp::min(start + self.region_blks, self.num_blocks);
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start .. end),
+            }
+        }
+
+        ranges
+    }
+
+    /// Returns the on-disk representation of the log of the given child
+    pub fn content(&self, child: &str) -> DirtyLogContent {
+        DirtyLogContent {
+            child: child.to_string(),
+            region_blks: self.region_blks,
+            num_blocks: self.num_blocks,
+            bitmap: self
+                .bitmap
+                .iter()
+                .map(|w| w.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+}
+
+impl From<&DirtyLogContent> for DirtyLog {
+    fn from(content: &DirtyLogContent) -> Self {
+        Self {
+            region_blks: content.region_blks,
+            num_blocks: content.num_blocks,
+            bitmap: content.bitmap.iter().map(|w| AtomicU64::new(*w)).collect(),
+        }
+    }
+}
+
+impl NexusChild {
+    /// Start recording the writes that this child is about to miss. A new log
+    /// is only started when the child is currently in sync, otherwise the
+    /// existing log (if any) is left as is.
+    pub(crate) fn start_dirty_log(&mut self) {
+        if self.state() == ChildState::Open {
+            if let Some(bdev) = self.bdev.as_ref() {
+                self.dirty_log =
+                    Some(DirtyLog::new(bdev.num_blocks(), bdev.block_len()));
+            }
+        }
+    }
+
+    /// Start recording the writes that this child, which is being rebuilt,
+    /// is about to miss, unless it already keeps a log.
+    pub(crate) fn keep_dirty_log(&mut self) {
+        if self.dirty_log.is_none() {
+            if let Some(bdev) = self.bdev.as_ref() {
+                self.dirty_log =
+                    Some(DirtyLog::new(bdev.num_blocks(), bdev.block_len()));
+            }
+        }
+    }
+
+    /// Replace any dirty logs stored on the "MayaMeta" partition with the
+    /// given ones.
+    async fn write_dirty_logs(
+        &mut self,
+        config: &NexusConfig,
+        now: &SystemTime,
+    ) -> Result<(), MetaDataError> {
+        let mut metadata = match self.get_metadata().await {
+            Ok(metadata) => metadata,
+            Err(_) => self.create_metadata().await?,
+        };
+
+        // only the most recent logs are of any use
+        let list = self.probe_all_config_objects(&metadata).await?;
+        for (selected, _) in list
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, c)| matches!(c, NexusConfig::DirtyLogs(_)))
+        {
+            self.delete_config_object(&mut metadata, selected as u32)
+                .await?;
+        }
+
+        self.append_config_object(&mut metadata, config, now).await
+    }
+
+    /// Read the dirty logs stored on the "MayaMeta" partition, together with
+    /// the time they were written
+    async fn read_dirty_logs(
+        &self,
+    ) -> Result<Option<(u128, NexusDirtyLogs)>, MetaDataError> {
+        let metadata = self.get_metadata().await?;
+        let list = self.probe_all_config_objects(&metadata).await?;
+
+        Ok(metadata
+            .index
+            .iter()
+            .zip(list)
+            .filter_map(|(entry, config)| match config {
+                NexusConfig::DirtyLogs(logs) => Some((entry.timestamp, logs)),
+                _ => None,
+            })
+            .last())
+    }
+}
+
+impl Nexus {
+    /// Record a write to the given range of the nexus for every child that
+    /// keeps a dirty log and is not part of the IO path.
+    #[inline]
+    pub(crate) fn dirty_log_mark(&self, offset: u64, num_blocks: u64) {
+        self.children
+            .iter()
+            .filter(|c| c.state() != ChildState::Open)
+            .filter_map(|c| c.dirty_log.as_ref())
+            .for_each(|log| log.mark(offset, num_blocks));
+    }
+
+    /// Returns the regions of child `name` that have to be rebuilt, in blocks
+    /// of the child, or None when the child has to be rebuilt as a whole.
+    pub(crate) fn dirty_regions(&self, name: &str) -> Option<Vec<Range<u64>>> {
+        let log = self
+            .children
+            .iter()
+            .find(|c| c.name == name)?
+            .dirty_log
+            .as_ref()?;
+
+        let end = self.bdev.num_blocks() + self.data_ent_offset;
+        Some(
+            log.dirty_ranges()
+                .into_iter()
+                .map(|r| {
+                    r.start + self.data_ent_offset
+                        .. std::cmp::min(r.end + self.data_ent_offset, end)
+                })
+                .filter(|r| r.start < r.end)
+                .collect(),
+        )
+    }
+
+    /// Persist the dirty logs of all children on every healthy child. The
+    /// logs are only flagged as clean when the nexus no longer accepts IO.
+    pub(crate) async fn persist_dirty_logs(&mut self, clean: bool) {
+        let config = NexusConfig::DirtyLogs(NexusDirtyLogs {
+            clean,
+            logs: self
+                .children
+                .iter()
+                .filter_map(|c| {
+                    c.dirty_log.as_ref().map(|l| l.content(&c.name))
+                })
+                .collect(),
+        });
+        let now = SystemTime::now();
+
+        for child in self
+            .children
+            .iter_mut()
+            .filter(|c| c.state() == ChildState::Open)
+        {
+            if let Err(e) = child.write_dirty_logs(&config, &now).await {
+                warn!(
+                    "{}: failed to persist dirty logs on child {}: {}",
+                    self.name, child.name, e
+                );
+            }
+        }
+    }
+
+    /// Restore the dirty logs persisted when this nexus was last destroyed.
+    /// The most recent logs found on the children are used and, if clean,
+    /// are flagged as not clean on disk straight away as from here on they
+    /// are only kept up to date in memory.
+    pub(crate) async fn load_dirty_logs(&mut self) {
+        let mut latest: Option<(u128, NexusDirtyLogs)> = None;
+
+        for child in self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+        {
+            match child.read_dirty_logs().await {
+                Ok(Some((timestamp, logs))) => {
+                    if latest.as_ref().map_or(true, |(t, _)| timestamp > *t) {
+                        latest = Some((timestamp, logs));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    debug!(
+                        "{}: no dirty logs on child {}: {}",
+                        self.name, child.name, e
+                    );
+                }
+            }
+        }
+
+        match latest {
+            Some((_, logs)) if logs.clean => {
+                for content in logs.logs.iter() {
+                    if let Some(child) = self
+                        .children
+                        .iter_mut()
+                        .find(|c| c.name == content.child)
+                    {
+                        info!(
+                            "{}: restored dirty log of child {}",
+                            self.name, child.name
+                        );
+                        child.dirty_log = Some(DirtyLog::from(content));
+                    }
+                }
+                self.persist_dirty_logs(false).await;
+            }
+            Some(_) => {
+                info!(
+                    "{}: ignoring dirty logs as the nexus was not shut down cleanly",
+                    self.name
+                );
+            }
+            None => {}
+        }
+    }
+}
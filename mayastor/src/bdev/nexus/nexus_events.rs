@@ -0,0 +1,155 @@
+//!
+//! Feed of the state transitions of nexuses, their children and rebuilds.
+//!
+//! Every event is given a sequence number, which increases by one for each
+//! event, along with the epoch of the instance that emitted it. The epoch is
+//! picked at random when the instance starts, so that sequence numbers of a
+//! previous instance are not mistaken for those of the current one. The most
+//! recent events are kept in a backlog so that a watcher that lost its
+//! connection can pick up where it left off, provided that it does not fall
+//! behind by more than the size of the backlog.
+use std::{collections::VecDeque, sync::Mutex, time::SystemTime};
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use tokio::sync::broadcast;
+
+use crate::bdev::nexus::{
+    nexus_bdev::{Error, NexusStatus},
+    nexus_child::ChildState,
+};
+
+/// number of events kept for watchers to resume from
+const EVENT_BACKLOG: usize = 1024;
+
+/// the transition an event reports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NexusEventType {
+    /// the state of a child changed
+    ChildState { old: ChildState, new: ChildState },
+    /// the status of the nexus changed
+    NexusStatus { old: NexusStatus, new: NexusStatus },
+    /// a rebuild of a child started
+    RebuildStarted,
+    /// a rebuild of a child completed successfully
+    RebuildCompleted,
+    /// a rebuild of a child was stopped before it completed
+    RebuildStopped,
+    /// a rebuild of a child failed
+    RebuildFailed,
+}
+
+#[derive(Debug, Clone)]
+pub struct NexusEvent {
+    /// epoch of the instance that emitted the event
+    pub epoch: u64,
+    /// sequence number of the event
+    pub seq: u64,
+    /// when the event happened
+    pub timestamp: SystemTime,
+    /// name of the nexus
+    pub nexus: String,
+    /// name of the child, if the event is about one
+    pub child: Option<String>,
+    pub event: NexusEventType,
+}
+
+struct EventLog {
+    /// epoch of this instance, never zero
+    epoch: u64,
+    /// sequence number of the next event
+    next_seq: u64,
+    /// the most recent events, oldest first
+    backlog: VecDeque<NexusEvent>,
+    sender: broadcast::Sender<NexusEvent>,
+}
+
+static EVENTS: Lazy<Mutex<EventLog>> = Lazy::new(|| {
+    Mutex::new(EventLog {
+        epoch: rand::thread_rng().gen_range(1, u64::MAX),
+        next_seq: 1,
+        backlog: VecDeque::with_capacity(EVENT_BACKLOG),
+        sender: broadcast::channel(EVENT_BACKLOG).0,
+    })
+});
+
+/// record an event and hand it to all watchers
+pub(crate) fn emit(nexus: &str, child: Option<&str>, event: NexusEventType) {
+    let mut log = EVENTS.lock().unwrap();
+    let event = NexusEvent {
+        epoch: log.epoch,
+        seq: log.next_seq,
+        timestamp: SystemTime::now(),
+        nexus: nexus.to_string(),
+        child: child.map(String::from),
+        event,
+    };
+    log.next_seq += 1;
+
+    if log.backlog.len() == EVENT_BACKLOG {
+        log.backlog.pop_front();
+    }
+    log.backlog.push_back(event.clone());
+    // there being no watchers is not an error
+    let _ = log.sender.send(event);
+}
+
+/// the epoch of the events emitted by this instance
+pub fn epoch() -> u64 {
+    EVENTS.lock().unwrap().epoch
+}
+
+/// the events handed to a single watcher
+pub struct EventWatch {
+    /// epoch of the events watched
+    pub epoch: u64,
+    /// sequence number of the first event watched
+    pub from_seq: u64,
+    /// the events from the backlog that are to be replayed
+    pub replay: Vec<NexusEvent>,
+    /// receiver for the events which follow the replayed ones
+    pub receiver: broadcast::Receiver<NexusEvent>,
+}
+
+/// Watch the events starting from the given sequence number of the given
+/// epoch, a sequence number of 0 only watches the events that are yet to
+/// happen whatever the epoch.
+///
+/// Fails if the events from the sequence number onwards are not available,
+/// either because they have dropped out of the backlog or because they were
+/// emitted by another instance, in which case the watcher has to resync.
+pub fn watch(epoch: u64, from_seq: u64) -> Result<EventWatch, Error> {
+    let log = EVENTS.lock().unwrap();
+    if from_seq != 0 && epoch != log.epoch {
+        return Err(Error::EventsEpochMismatch {
+            epoch,
+            current: log.epoch,
+        });
+    }
+    let oldest = log.backlog.front().map_or(log.next_seq, |e| e.seq);
+
+    let from_seq = if from_seq == 0 {
+        log.next_seq
+    } else {
+        from_seq
+    };
+    if from_seq < oldest || from_seq > log.next_seq {
+        return Err(Error::EventsUnavailable {
+            seq: from_seq,
+            oldest,
+            next: log.next_seq,
+        });
+    }
+
+    Ok(EventWatch {
+        epoch: log.epoch,
+        from_seq,
+        replay: log
+            .backlog
+            .iter()
+            .filter(|e| e.seq >= from_seq)
+            .cloned()
+            .collect(),
+        receiver: log.sender.subscribe(),
+    })
+}
@@ -98,6 +98,12 @@ impl NexusFnTable {
         // only set the number of IO attempts before the first attempt
         let mut bio = Bio::from(io);
         bio.init();
+
+        // IO exceeding the QoS limits is submitted later by the channel
+        if NexusChannel::inner_from_channel(channel).throttle(&bio) {
+            return;
+        }
+
         Self::io_submit_or_resubmit(channel, &mut bio);
     }
 
@@ -15,6 +15,7 @@ use crate::{
     bdev::nexus::{
         nexus_bdev::{Nexus, NEXUS_PRODUCT_ID},
         nexus_fn_table::NexusFnTable,
+        nexus_stats::elapsed_us,
     },
     core::Bdev,
 };
@@ -50,7 +51,7 @@ pub struct NioCtx {
 /// pool in effect accessing the pointers from rust is to be considered a
 /// mutable borrow.
 ///
-/// 2.  The IO pointers are never accessed from any other thread
+/// 2. The IO pointers are never accessed from any other thread
 /// and care must be taken that you never pass an IO ptr to another core
 #[derive(Clone)]
 pub(crate) struct Bio(NonNull<spdk_bdev_io>);
@@ -104,6 +105,8 @@ pub mod io_status {
 pub mod nvme_admin_opc {
     // Vendor-specific
     pub const CREATE_SNAPSHOT: u8 = 0xc0;
+    pub const REVERT_SNAPSHOT: u8 = 0xc1;
+    pub const BLOCKS_ALLOCATED: u8 = 0xc2;
 }
 
 impl Bio {
@@ -143,6 +146,9 @@ impl Bio {
                 debug!("BIO for nexus marked completed but has outstanding")
             }
         }
+        let attempts = self.ctx_as_mut_ref().io_attempts;
+        let nexus = self.nexus_as_ref();
+        nexus.stats.record(self, attempts < nexus.max_io_attempts);
         unsafe {
             spdk_bdev_io_complete(self.0.as_ptr(), io_status::SUCCESS);
         }
@@ -283,6 +289,12 @@ impl Bio {
     pub(crate) fn as_ptr(&self) -> *mut spdk_bdev_io {
         self.0.as_ptr()
     }
+
+    /// the number of microseconds since the IO was submitted
+    #[inline]
+    pub(crate) fn latency_us(&self) -> u64 {
+        elapsed_us(unsafe { self.0.as_ref().internal.submit_tsc })
+    }
 }
 
 impl Debug for Bio {
@@ -144,6 +144,10 @@ impl Nexus {
     pub const METADATA_PARTITION_TYPE_ID: &'static str =
         "27663382-e5e6-11e9-81b4-ca5ca5ca5ca5";
 
+    /// Offset in bytes of the "MayaData" partition on a child, which follows
+    /// the 4MB "MayaMeta" partition that starts at the first 1MB boundary.
+    pub const DATA_PARTITION_OFFSET: u64 = (1 << 20) + (4 << 20);
+
     /// Generate a new nexus label based on the nexus configuration.
     /// The meta partition is fixed in size and aligned to a 1MB boundary.
     pub(crate) fn generate_label(&mut self) -> NexusLabel {
@@ -368,6 +372,37 @@ impl Nexus {
         Ok(())
     }
 
+    /// Rewrite the labels of all children for a device of `num_blocks`
+    /// blocks, growing the data partition of the current label.
+    pub(crate) async fn grow_labels(
+        &self,
+        num_blocks: u64,
+    ) -> Result<NexusLabel, LabelError> {
+        let mut current = None;
+        for child in &self.children {
+            match child.probe_label().await {
+                Ok(label) => {
+                    current = Some(label);
+                    break;
+                }
+                Err(error) => warn!(
+                    "{}: {}: Error probing label: {}",
+                    self.name, child.name, error
+                ),
+            }
+        }
+
+        let label = current
+            .ok_or(LabelError::ProbeError {})?
+            .grow(self.bdev.block_len(), num_blocks);
+
+        self.write_all_labels(&label).await?;
+
+        info!("{}: label grown to {} blocks", self.name, num_blocks);
+        trace!("{}: grown label:\n{}", self.name, label);
+        Ok(label)
+    }
+
     pub async fn write_all_labels(
         &self,
         label: &NexusLabel,
@@ -658,6 +693,38 @@ impl NexusLabel {
     pub(crate) fn get_block_count(&self) -> u64 {
         self.partitions[1].ent_end - self.partitions[1].ent_start + 1
     }
+
+    /// Returns this label adjusted to a device that has grown to
+    /// `num_blocks` blocks. The backup GPT header and partition table are
+    /// relocated to the new end of the device and the data partition is
+    /// grown to fill the space in between, all GUIDs are kept.
+    pub(crate) fn grow(&self, block_size: u32, num_blocks: u64) -> NexusLabel {
+        let resized = GPTHeader::new(block_size, num_blocks, Uuid::nil());
+
+        let mut mbr = self.mbr;
+        mbr.entries[0].num_sectors = if num_blocks > u32::max_value().into() {
+            u32::max_value()
+        } else {
+            (num_blocks as u32) - 1
+        };
+
+        let mut partitions = self.partitions.clone();
+        partitions[1].ent_end = resized.lba_end;
+
+        let mut primary = self.primary;
+        primary.lba_alt = resized.lba_alt;
+        primary.lba_end = resized.lba_end;
+        primary.table_crc = GptEntry::checksum(&partitions);
+        primary.checksum();
+
+        NexusLabel {
+            status: NexusLabelStatus::Neither,
+            mbr,
+            primary,
+            partitions,
+            secondary: primary.to_backup(),
+        }
+    }
 }
 
 impl Display for NexusLabel {
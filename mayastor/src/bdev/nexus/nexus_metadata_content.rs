@@ -3,7 +3,7 @@
 //! (and testing) purposes at present.
 //! The intent is that these structures will define precisely what
 //! content is to be stored on the "MayaMeta" partition.
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
 use serde::{Deserialize, Serialize};
 
@@ -34,10 +34,44 @@ pub struct NexusConfigVersion3 {
     pub data: Vec<String>,
 }
 
+/// Dirty region log of a single child, see `nexus_dirty_log`
+#[derive(Debug, Deserialize, PartialEq, Default, Serialize, Clone)]
+pub struct DirtyLogContent {
+    pub child: String,
+    pub region_blks: u64,
+    pub num_blocks: u64,
+    pub bitmap: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Default, Serialize, Clone)]
+pub struct NexusDirtyLogs {
+    /// the logs were written after the nexus stopped accepting IO
+    pub clean: bool,
+    pub logs: Vec<DirtyLogContent>,
+}
+
+/// Progress of the rebuild of a child, see `nexus_rebuild_checkpoint`
+#[derive(Debug, Deserialize, PartialEq, Default, Serialize, Clone)]
+pub struct RebuildCheckpoint {
+    /// identifies the rebuild, the same on the source and the destination
+    pub id: String,
+    pub source: String,
+    pub destination: String,
+    /// the regions being copied, in blocks of the children
+    pub regions: Vec<Range<u64>>,
+    /// all blocks of the regions before this one have been copied
+    pub next: u64,
+    /// the rebuild job was stopped whilst the nexus was live, so the writes
+    /// missed since then are only known from the dirty log of the destination
+    pub stopped: bool,
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub enum NexusConfig {
     Version1(NexusConfigVersion1),
     Version2(NexusConfigVersion2),
     Version3(NexusConfigVersion3),
     Version4(HashMap<String, String>),
+    DirtyLogs(NexusDirtyLogs),
+    RebuildCheckpoint(RebuildCheckpoint),
 }
@@ -6,8 +6,8 @@ use snafu::Snafu;
 
 use crate::{
     core::Bdev,
-    subsys::NvmfSubsystem,
-    target::nvmf::{share, unshare},
+    subsys::{NvmfSubsystem, TransportID},
+    target::nvmf::{share_multipath, unshare},
 };
 
 #[derive(Debug, Snafu)]
@@ -20,6 +20,46 @@ pub enum NexusNvmfError {
         err
     ))]
     CreateTargetFailed { dev: String, err: String },
+    #[snafu(display(
+        "Failed to set the ANA state of the nvmf target for bdev uuid {}, error {}",
+        dev,
+        err
+    ))]
+    AnaStateFailed { dev: String, err: String },
+    #[snafu(display(
+        "Failed to add listener {} to the nvmf target for bdev uuid {}, error {}",
+        listener,
+        dev,
+        err
+    ))]
+    AddListenerFailed {
+        dev: String,
+        listener: String,
+        err: String,
+    },
+    #[snafu(display(
+        "Failed to remove listener {} from the nvmf target for bdev uuid {}, error {}",
+        listener,
+        dev,
+        err
+    ))]
+    RemoveListenerFailed {
+        dev: String,
+        listener: String,
+        err: String,
+    },
+    #[snafu(display(
+        "Failed to pause the nvmf target for bdev uuid {}, error {}",
+        dev,
+        err
+    ))]
+    PauseFailed { dev: String, err: String },
+    #[snafu(display(
+        "Failed to resume the nvmf target for bdev uuid {}, error {}",
+        dev,
+        err
+    ))]
+    ResumeFailed { dev: String, err: String },
 }
 
 /// Nvmf target representation.
@@ -28,7 +68,10 @@ pub struct NexusNvmfTarget {
 }
 
 impl NexusNvmfTarget {
-    pub async fn create(my_uuid: &str) -> Result<Self, NexusNvmfError> {
+    pub async fn create(
+        my_uuid: &str,
+        allowed_hosts: &[String],
+    ) -> Result<Self, NexusNvmfError> {
         info!("Creating nvmf nexus target: {}", my_uuid);
         let bdev = match Bdev::lookup_by_name(&my_uuid) {
             None => {
@@ -39,7 +82,10 @@ impl NexusNvmfTarget {
             Some(bd) => bd,
         };
 
-        match share(&my_uuid, &bdev).await {
+        // the nexus is shared with ANA reporting enabled such that a nexus on
+        // another node, published under the same NQN, can serve as another
+        // path
+        match share_multipath(my_uuid, &bdev, allowed_hosts).await {
             Ok(_) => Ok(Self {
                 uuid: my_uuid.to_string(),
             }),
@@ -49,6 +95,94 @@ impl NexusNvmfTarget {
             }),
         }
     }
+
+    /// the listeners of the target, each a path to the nexus with the ANA
+    /// state reported to the hosts that connect through it
+    pub fn listeners(&self) -> Vec<(TransportID, u32)> {
+        NvmfSubsystem::nqn_lookup(&self.uuid)
+            .unwrap()
+            .listener_ana_states()
+    }
+
+    /// change the ANA state reported to hosts for the path to the nexus
+    /// through the given listener
+    pub async fn set_ana_state(
+        &self,
+        trid: &TransportID,
+        ana_state: u32,
+    ) -> Result<(), NexusNvmfError> {
+        NvmfSubsystem::nqn_lookup(&self.uuid)
+            .unwrap()
+            .set_ana_state(trid, ana_state)
+            .await
+            .map_err(|e| NexusNvmfError::AnaStateFailed {
+                dev: self.uuid.clone(),
+                err: e.to_string(),
+            })
+    }
+
+    /// add a listener as another path to the nexus, with the given ANA
+    /// state. The IO of the hosts is paused while the listener is added.
+    pub async fn add_listener(
+        &self,
+        trid: &TransportID,
+        ana_state: u32,
+    ) -> Result<(), NexusNvmfError> {
+        let ss = NvmfSubsystem::nqn_lookup(&self.uuid).unwrap();
+        self.pause().await?;
+        let result = ss.add_listener(trid).await;
+        self.resume().await?;
+        result.map_err(|e| NexusNvmfError::AddListenerFailed {
+            dev: self.uuid.clone(),
+            listener: trid.to_string(),
+            err: e.to_string(),
+        })?;
+
+        self.set_ana_state(trid, ana_state).await
+    }
+
+    /// remove the given listener, hosts connected through it lose that path
+    /// to the nexus
+    pub async fn remove_listener(
+        &self,
+        trid: &TransportID,
+    ) -> Result<(), NexusNvmfError> {
+        let ss = NvmfSubsystem::nqn_lookup(&self.uuid).unwrap();
+        self.pause().await?;
+        let result = ss.remove_listener(trid);
+        self.resume().await?;
+        result.map_err(|e| NexusNvmfError::RemoveListenerFailed {
+            dev: self.uuid.clone(),
+            listener: trid.to_string(),
+            err: e.to_string(),
+        })
+    }
+
+    /// pause the target, waiting for the IO of the hosts in flight to
+    /// complete and queueing any new IO until resumed
+    pub async fn pause(&self) -> Result<(), NexusNvmfError> {
+        NvmfSubsystem::nqn_lookup(&self.uuid)
+            .unwrap()
+            .pause()
+            .await
+            .map_err(|e| NexusNvmfError::PauseFailed {
+                dev: self.uuid.clone(),
+                err: e.to_string(),
+            })
+    }
+
+    /// resume the IO of the hosts after pause
+    pub async fn resume(&self) -> Result<(), NexusNvmfError> {
+        NvmfSubsystem::nqn_lookup(&self.uuid)
+            .unwrap()
+            .resume()
+            .await
+            .map_err(|e| NexusNvmfError::ResumeFailed {
+                dev: self.uuid.clone(),
+                err: e.to_string(),
+            })
+    }
+
     pub async fn destroy(self) {
         info!("Destroying nvmf nexus target");
         match unshare(&self.uuid).await {
@@ -59,6 +193,12 @@ impl NexusNvmfTarget {
         }
     }
 
+    /// the URI hosts connect to for the path through the given listener
+    pub fn listener_uri(&self, trid: &TransportID) -> String {
+        let ss = NvmfSubsystem::nqn_lookup(&self.uuid).unwrap();
+        format!("{}/{}", trid, ss.get_nqn())
+    }
+
     pub fn as_uri(&self) -> String {
         NvmfSubsystem::nqn_lookup(&self.uuid)
             .unwrap()
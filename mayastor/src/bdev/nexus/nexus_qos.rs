@@ -0,0 +1,189 @@
+//!
+//! Quality of service of a nexus. Reads and writes are admitted by token
+//! buckets which are refilled at the configured rate. The buckets are shared
+//! by the IO channels of all cores, IO that is not admitted is held back by
+//! the channel it was submitted on until enough tokens have been added.
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use spdk_sys::{spdk_get_ticks, spdk_get_ticks_hz};
+
+use crate::bdev::nexus::nexus_io::io_type;
+
+/// the bandwidth limits are expressed in MiB/s
+pub(crate) const MIB: u64 = 1024 * 1024;
+
+/// the number of tokens a bucket holds on top of the burst, expressed as a
+/// fraction of the rate. This smooths out the refill without allowing more
+/// than 100ms worth of IO to pass at once.
+const SMOOTHING_DIVISOR: u64 = 10;
+
+/// A single limit, a rate of 0 means that there is no limit
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QosLimit {
+    /// the sustained number of IOs or MiB per second
+    pub rate: u64,
+    /// the number of IOs or MiB that may be issued on top of the rate after
+    /// a period of lower activity
+    pub burst: u64,
+}
+
+/// The limits applied to the IO of a nexus
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NexusQos {
+    pub read_iops: QosLimit,
+    pub write_iops: QosLimit,
+    pub read_mbps: QosLimit,
+    pub write_mbps: QosLimit,
+}
+
+impl NexusQos {
+    /// returns true if none of the limits is set
+    pub fn is_unlimited(&self) -> bool {
+        self.read_iops.rate == 0
+            && self.write_iops.rate == 0
+            && self.read_mbps.rate == 0
+            && self.write_mbps.rate == 0
+    }
+}
+
+/// Token bucket admitting work at a configured rate, also used to limit the
+/// bandwidth of rebuilds
+#[derive(Debug, Default)]
+pub(crate) struct TokenBucket {
+    /// tokens added per second, 0 disables the bucket
+    rate: AtomicU64,
+    /// the maximum number of tokens the bucket can hold
+    capacity: AtomicI64,
+    /// the tokens currently available, this goes negative when an IO takes
+    /// more tokens than there are so that large IOs are never starved
+    tokens: AtomicI64,
+    /// the tick count at which the bucket was last refilled
+    last: AtomicU64,
+}
+
+impl TokenBucket {
+    /// (re)configure the bucket with the limit and the size of its unit in
+    /// tokens, the bucket starts full
+    pub(crate) fn set(&self, limit: QosLimit, unit: u64) {
+        let rate = limit.rate.saturating_mul(unit);
+        // hold at least one unit so that the bucket can always be refilled
+        // up to the point where it admits IO again
+        let capacity = (rate / SMOOTHING_DIVISOR)
+            .saturating_add(limit.burst.saturating_mul(unit))
+            .max(unit);
+        self.rate.store(rate, Ordering::Relaxed);
+        self.capacity.store(capacity as i64, Ordering::Relaxed);
+        self.tokens.store(capacity as i64, Ordering::Relaxed);
+        self.last
+            .store(unsafe { spdk_get_ticks() }, Ordering::Relaxed);
+    }
+
+    /// add the tokens accrued since the last refill, the core that manages
+    /// to move the refill time forward is the one that adds them
+    fn refill(&self, rate: u64) {
+        let now = unsafe { spdk_get_ticks() };
+        let last = self.last.load(Ordering::Relaxed);
+        let elapsed = now.saturating_sub(last) as u128;
+        let accrued = (elapsed * rate as u128
+            / unsafe { spdk_get_ticks_hz() } as u128)
+            as i64;
+
+        if accrued == 0
+            || self
+                .last
+                .compare_exchange(
+                    last,
+                    now,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+        {
+            return;
+        }
+
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let _ = self.tokens.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |tokens| Some(tokens.saturating_add(accrued).min(capacity)),
+        );
+    }
+
+    /// returns true if the bucket has tokens left, or if it is disabled
+    pub(crate) fn available(&self) -> bool {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return true;
+        }
+        self.refill(rate);
+        self.tokens.load(Ordering::Relaxed) > 0
+    }
+
+    pub(crate) fn consume(&self, tokens: u64) {
+        if self.rate.load(Ordering::Relaxed) != 0 {
+            self.tokens.fetch_sub(tokens as i64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The token buckets of a nexus and the number of IOs they held back
+#[derive(Debug, Default)]
+pub(crate) struct QosLimiter {
+    read_iops: TokenBucket,
+    write_iops: TokenBucket,
+    read_bytes: TokenBucket,
+    write_bytes: TokenBucket,
+    throttled_reads: AtomicU64,
+    throttled_writes: AtomicU64,
+}
+
+impl QosLimiter {
+    /// apply new limits, the buckets start out full
+    pub(crate) fn set(&self, qos: &NexusQos) {
+        self.read_iops.set(qos.read_iops, 1);
+        self.write_iops.set(qos.write_iops, 1);
+        self.read_bytes.set(qos.read_mbps, MIB);
+        self.write_bytes.set(qos.write_mbps, MIB);
+    }
+
+    /// take the tokens for an IO of the given type and size if the limits
+    /// allow it to be submitted now. IO other than reads and writes is
+    /// always admitted.
+    pub(crate) fn admit(&self, io: u32, bytes: u64) -> bool {
+        let (iops, bandwidth) = match io {
+            io_type::READ => (&self.read_iops, &self.read_bytes),
+            io_type::WRITE => (&self.write_iops, &self.write_bytes),
+            _ => return true,
+        };
+
+        if iops.available() && bandwidth.available() {
+            iops.consume(1);
+            bandwidth.consume(bytes);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// account for an IO that has been held back
+    pub(crate) fn throttled(&self, io: u32) {
+        match io {
+            io_type::READ => &self.throttled_reads,
+            _ => &self.throttled_writes,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// the number of reads that have been held back
+    pub(crate) fn throttled_reads(&self) -> u64 {
+        self.throttled_reads.load(Ordering::Relaxed)
+    }
+
+    /// the number of writes that have been held back
+    pub(crate) fn throttled_writes(&self) -> u64 {
+        self.throttled_writes.load(Ordering::Relaxed)
+    }
+}
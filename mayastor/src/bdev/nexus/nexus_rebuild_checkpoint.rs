@@ -0,0 +1,371 @@
+//!
+//! Checkpoints of the progress of the rebuilds.
+//!
+//! Whilst a child is being rebuilt its rebuild job periodically reports its
+//! progress, which is persisted in the "MayaMeta" partition of both the source
+//! and the destination of the rebuild. The checkpoints are also written when
+//! the nexus is destroyed, and are removed once a rebuild job has completed
+//! or failed.
+//!
+//! A checkpoint allows the next rebuild of a child to carry on where the
+//! previous one left off, rather than to start over, provided that it copies
+//! from the same source, which has to hold the very same checkpoint. The
+//! destination of a rebuild receives all the writes to the nexus, so whatever
+//! has been copied is in sync with the source for as long as the child stays
+//! open; once the child is closed its checkpoint is dropped.
+//!
+//! A rebuild job which is stopped whilst the nexus is live takes its
+//! destination out of the write path, so from then on the writes it misses
+//! are recorded in its dirty log and copied as well when the rebuild resumes.
+//! As that log is only trusted after a clean shutdown, the checkpoint of a
+//! stopped rebuild is dropped without it.
+
+use std::{
+    cmp::{max, min},
+    ops::Range,
+    time::SystemTime,
+};
+
+use crate::{
+    bdev::nexus::{
+        nexus_bdev::Nexus,
+        nexus_child::{ChildState, NexusChild, Reason},
+        nexus_metadata::{MetaDataError, NexusMetaData},
+        nexus_metadata_content::{NexusConfig, RebuildCheckpoint},
+    },
+    rebuild::RebuildJob,
+};
+
+impl RebuildCheckpoint {
+    /// Start the checkpoints of a new rebuild, the progress is filled in from
+    /// the rebuild job
+    pub(crate) fn new() -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The regions which remain to be copied
+    pub(crate) fn remaining(&self) -> Vec<Range<u64>> {
+        self.regions
+            .iter()
+            .filter(|r| r.end > self.next)
+            .map(|r| max(r.start, self.next) .. r.end)
+            .collect()
+    }
+
+    /// The regions which remain to be copied together with the given ones,
+    /// in order and merged where they overlap
+    pub(crate) fn remaining_with(
+        &self,
+        mut regions: Vec<Range<u64>>,
+    ) -> Vec<Range<u64>> {
+        regions.extend(self.remaining());
+        regions.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<u64>> = Vec::new();
+        for r in regions {
+            match merged.last_mut() {
+                Some(last) if last.end >= r.start => {
+                    last.end = max(last.end, r.end)
+                }
+                _ => merged.push(r),
+            }
+        }
+        merged
+    }
+}
+
+impl NexusChild {
+    /// Remove the checkpoints of the rebuild of `destination` from the
+    /// "MayaMeta" partition
+    async fn remove_rebuild_checkpoints(
+        &mut self,
+        metadata: &mut NexusMetaData,
+        destination: &str,
+    ) -> Result<(), MetaDataError> {
+        let list = self.probe_all_config_objects(metadata).await?;
+        for (selected, _) in
+            list.iter().enumerate().rev().filter(|(_, c)| match c {
+                NexusConfig::RebuildCheckpoint(c) => {
+                    c.destination == destination
+                }
+                _ => false,
+            })
+        {
+            self.delete_config_object(metadata, selected as u32).await?;
+        }
+        Ok(())
+    }
+
+    /// Replace the checkpoint of the same rebuild stored on the "MayaMeta"
+    /// partition, if any, with the given one
+    async fn write_rebuild_checkpoint(
+        &mut self,
+        checkpoint: &RebuildCheckpoint,
+        now: &SystemTime,
+    ) -> Result<(), MetaDataError> {
+        let mut metadata = match self.get_metadata().await {
+            Ok(metadata) => metadata,
+            Err(_) => self.create_metadata().await?,
+        };
+
+        self.remove_rebuild_checkpoints(&mut metadata, &checkpoint.destination)
+            .await?;
+        self.append_config_object(
+            &mut metadata,
+            &NexusConfig::RebuildCheckpoint(checkpoint.clone()),
+            now,
+        )
+        .await
+    }
+
+    /// Remove the checkpoint of the rebuild of `destination` from the
+    /// "MayaMeta" partition
+    async fn delete_rebuild_checkpoint(
+        &mut self,
+        destination: &str,
+    ) -> Result<(), MetaDataError> {
+        let mut metadata = self.get_metadata().await?;
+        self.remove_rebuild_checkpoints(&mut metadata, destination)
+            .await
+    }
+
+    /// Read the checkpoints stored on the "MayaMeta" partition
+    async fn read_rebuild_checkpoints(
+        &self,
+    ) -> Result<Vec<RebuildCheckpoint>, MetaDataError> {
+        let metadata = self.get_metadata().await?;
+        Ok(self
+            .probe_all_config_objects(&metadata)
+            .await?
+            .into_iter()
+            .filter_map(|config| match config {
+                NexusConfig::RebuildCheckpoint(checkpoint) => Some(checkpoint),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+impl Nexus {
+    /// Pick up the checkpoints of the rebuilds of the children, each stored
+    /// on the child itself amongst others
+    pub(crate) async fn load_rebuild_checkpoints(&mut self) {
+        for child in self
+            .children
+            .iter_mut()
+            .filter(|c| c.state() == ChildState::Open)
+        {
+            match child.read_rebuild_checkpoints().await {
+                Ok(list) => {
+                    child.rebuild_checkpoint =
+                        list.into_iter().find(|c| c.destination == child.name);
+                    if let Some(checkpoint) = child.rebuild_checkpoint.as_ref()
+                    {
+                        info!(
+                            "{}: found rebuild checkpoint of child {} at block {}",
+                            self.name, child.name, checkpoint.next
+                        );
+                    }
+                }
+                Err(e) => {
+                    debug!(
+                        "{}: no rebuild checkpoints on child {}: {}",
+                        self.name, child.name, e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns the checkpoint from which the rebuild of child `name` can
+    /// resume, if any. The checkpoint of a child is only used once, by the
+    /// rebuild that follows the opening of the nexus or the stop of the
+    /// previous rebuild, and only when its source holds the same checkpoint
+    /// and is still healthy.
+    pub(crate) async fn resume_checkpoint(
+        &mut self,
+        name: &str,
+    ) -> Option<RebuildCheckpoint> {
+        let child = self.children.iter_mut().find(|c| c.name == name)?;
+        if child.rebuilding() {
+            return None;
+        }
+        let checkpoint = child.rebuild_checkpoint.take()?;
+        if checkpoint.stopped && child.dirty_log.is_none() {
+            info!(
+                "{}: not resuming the stopped rebuild of child {} as the writes it missed are unknown",
+                self.name, name
+            );
+            return None;
+        }
+
+        let source = match self.children.iter().find(|c| {
+            c.name == checkpoint.source && c.state() == ChildState::Open
+        }) {
+            Some(source) => source,
+            None => {
+                info!(
+                    "{}: not resuming the rebuild of child {} as its source {} is not healthy",
+                    self.name, name, checkpoint.source
+                );
+                return None;
+            }
+        };
+
+        // a source which has been replaced or rebuilt itself meanwhile does
+        // not hold the checkpoint
+        let held = source
+            .read_rebuild_checkpoints()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .find(|c| {
+                c.id == checkpoint.id
+                    && c.destination == checkpoint.destination
+                    && c.regions == checkpoint.regions
+            });
+        let held = match held {
+            Some(held) => held,
+            None => {
+                info!(
+                    "{}: not resuming the rebuild of child {} as its source {} has changed",
+                    self.name, name, checkpoint.source
+                );
+                return None;
+            }
+        };
+
+        // the nexus may have been resized
+        let end = self.bdev.num_blocks() + self.data_ent_offset;
+        if checkpoint
+            .regions
+            .iter()
+            .any(|r| r.start < self.data_ent_offset || r.end > end)
+        {
+            return None;
+        }
+
+        // the checkpoints may have been written in between the two children
+        Some(RebuildCheckpoint {
+            next: min(checkpoint.next, held.next),
+            ..checkpoint
+        })
+    }
+
+    /// Update the checkpoint of the rebuild of child `name` with the
+    /// progress of its rebuild job and return it
+    fn update_rebuild_checkpoint(
+        &mut self,
+        name: &str,
+    ) -> Option<RebuildCheckpoint> {
+        let job = RebuildJob::lookup(name).ok()?;
+        if job.state().done() {
+            return None;
+        }
+
+        let checkpoint = self
+            .children
+            .iter_mut()
+            .find(|c| c.name == name)?
+            .rebuild_checkpoint
+            .as_mut()?;
+        checkpoint.source = job.source.clone();
+        checkpoint.destination = job.destination.clone();
+        checkpoint.regions = job.regions().to_vec();
+        checkpoint.next = job.checkpoint();
+        Some(checkpoint.clone())
+    }
+
+    /// Returns the current checkpoints of all the rebuilds of the nexus
+    pub(crate) fn rebuild_checkpoints(&mut self) -> Vec<RebuildCheckpoint> {
+        let names = self
+            .children
+            .iter()
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+        names
+            .iter()
+            .filter_map(|name| self.update_rebuild_checkpoint(name))
+            .collect()
+    }
+
+    /// Persist the checkpoint on both the source and the destination of the
+    /// rebuild
+    pub(crate) async fn write_rebuild_checkpoint(
+        &mut self,
+        checkpoint: &RebuildCheckpoint,
+    ) {
+        let now = SystemTime::now();
+
+        for child in self.children.iter_mut().filter(|c| {
+            c.name == checkpoint.source || c.name == checkpoint.destination
+        }) {
+            if let Err(e) =
+                child.write_rebuild_checkpoint(checkpoint, &now).await
+            {
+                warn!(
+                    "{}: failed to persist rebuild checkpoint on child {}: {}",
+                    self.name, child.name, e
+                );
+            }
+        }
+    }
+
+    /// Checkpoint the progress of the rebuild of child `name`
+    pub(crate) async fn persist_rebuild_checkpoint(&mut self, name: &str) {
+        if let Some(checkpoint) = self.update_rebuild_checkpoint(name) {
+            self.write_rebuild_checkpoint(&checkpoint).await;
+        }
+    }
+
+    /// Keep the checkpoint of the rebuild of child `name`, whose job has been
+    /// stopped whilst the nexus is live, for the next rebuild of the child to
+    /// resume from. The checkpoint is dropped if the child has been closed.
+    pub(crate) async fn keep_rebuild_checkpoint(&mut self, name: &str) {
+        let checkpoint = match self.children.iter_mut().find(|c| c.name == name)
+        {
+            Some(child)
+                if child.state() == ChildState::Faulted(Reason::OutOfSync) =>
+            {
+                // record the writes the child misses from here on, before
+                // it is taken out of the write path
+                child.keep_dirty_log();
+                child.rebuild_checkpoint.as_mut().map(|checkpoint| {
+                    checkpoint.stopped = true;
+                    checkpoint.clone()
+                })
+            }
+            _ => None,
+        };
+
+        match checkpoint {
+            Some(checkpoint) => {
+                self.write_rebuild_checkpoint(&checkpoint).await
+            }
+            None => self.clear_rebuild_checkpoint(name).await,
+        }
+    }
+
+    /// Remove the checkpoint of the rebuild of child `name` from all the
+    /// children, as its rebuild job is done
+    pub(crate) async fn clear_rebuild_checkpoint(&mut self, name: &str) {
+        if let Some(child) = self.children.iter_mut().find(|c| c.name == name) {
+            child.rebuild_checkpoint = None;
+        }
+
+        for child in self.children.iter_mut().filter(|c| {
+            c.state() == ChildState::Open
+                || c.state() == ChildState::Faulted(Reason::OutOfSync)
+        }) {
+            if let Err(e) = child.delete_rebuild_checkpoint(name).await {
+                debug!(
+                    "{}: failed to remove rebuild checkpoint from child {}: {}",
+                    self.name, child.name, e
+                );
+            }
+        }
+    }
+}
@@ -1,21 +1,25 @@
-use std::ffi::CString;
+use std::{ffi::CString, net::Ipv4Addr};
 
 use async_trait::async_trait;
 use futures::channel::oneshot;
 use snafu::ResultExt;
 
-use rpc::mayastor::ShareProtocolNexus;
+use rpc::mayastor::{NexusListener, NvmeAnaState, ShareProtocolNexus};
 use spdk_sys::create_crypto_disk;
 
 use crate::{
     bdev::nexus::{
         nexus_bdev::{
+            AddListener,
             CreateCryptoBdev,
             DestroyCryptoBdev,
             Error,
             Error::AlreadyShared,
             Nexus,
             NexusTarget,
+            RemoveListener,
+            SetAllowedHosts,
+            SetAnaState,
             ShareIscsiNexus,
             ShareNbdNexus,
             ShareNvmfNexus,
@@ -26,6 +30,7 @@ use crate::{
     },
     core::{Bdev, Protocol, Share},
     ffihelper::{cb_arg, done_errno_cb, errno_result_from_i32, ErrnoResult},
+    subsys::TransportID,
 };
 
 /// we are using the multi buffer encryption implementation using CBC as the
@@ -221,9 +226,10 @@ impl Nexus {
                 uri
             }
             ShareProtocolNexus::NexusNvmf => {
-                let nvmf_target = NexusNvmfTarget::create(&name)
-                    .await
-                    .context(ShareNvmfNexus {
+                let nvmf_target =
+                    NexusNvmfTarget::create(&name, &self.allowed_hosts)
+                        .await
+                        .context(ShareNvmfNexus {
                         name: self.name.clone(),
                     })?;
                 let uri = nvmf_target.as_uri();
@@ -283,6 +289,182 @@ impl Nexus {
         Ok(())
     }
 
+    /// Set the host NQNs allowed to connect to the nexus when it is published
+    /// over nvmf, an empty list allows any host. The list is applied right
+    /// away when the nexus is published already.
+    pub async fn set_allowed_hosts(
+        &mut self,
+        allowed_hosts: Vec<String>,
+    ) -> Result<(), Error> {
+        if let Some(NexusTarget::NexusNvmfTarget(_)) = self.nexus_target {
+            let name = self.share_handle.as_ref().unwrap();
+            if let Some(bdev) = Bdev::lookup_by_name(name) {
+                bdev.set_allowed_hosts(&allowed_hosts).await.context(
+                    SetAllowedHosts {
+                        name: self.name.clone(),
+                    },
+                )?;
+            }
+        }
+        self.allowed_hosts = allowed_hosts;
+        Ok(())
+    }
+
+    /// Return the listeners of the nexus, which must be published over nvmf.
+    /// Each listener is a separate path to the nexus with its own ANA state.
+    pub fn listeners(&self) -> Result<Vec<NexusListener>, Error> {
+        let nvmf_target = self.nvmf_target()?;
+        Ok(nvmf_target
+            .listeners()
+            .iter()
+            .map(|(trid, ana_state)| NexusListener {
+                address: trid.address(),
+                port: trid.port() as u32,
+                ana_state: ana_state_from_u32(*ana_state) as i32,
+                uri: nvmf_target.listener_uri(trid),
+            })
+            .collect())
+    }
+
+    /// Add a listener on the given address and port as another path to the
+    /// nexus, hosts are told the given ANA state for it.
+    pub async fn add_listener(
+        &self,
+        address: &str,
+        port: u16,
+        ana_state: NvmeAnaState,
+    ) -> Result<NexusListener, Error> {
+        check_ana_state(ana_state)?;
+        let nvmf_target = self.nvmf_target()?;
+        let trid = listener_trid(address, port)?;
+        if nvmf_target.listeners().iter().any(|(t, _)| *t == trid) {
+            return Err(Error::ListenerExists {
+                listener: trid.to_string(),
+                name: self.name.clone(),
+            });
+        }
+
+        nvmf_target
+            .add_listener(&trid, ana_state as u32)
+            .await
+            .context(AddListener {
+                name: self.name.clone(),
+            })?;
+        Ok(NexusListener {
+            address: trid.address(),
+            port: trid.port() as u32,
+            ana_state: ana_state as i32,
+            uri: nvmf_target.listener_uri(&trid),
+        })
+    }
+
+    /// Remove the listener on the given address and port, a nexus keeps at
+    /// least one listener for as long as it is published.
+    pub async fn remove_listener(
+        &self,
+        address: &str,
+        port: u16,
+    ) -> Result<(), Error> {
+        let nvmf_target = self.nvmf_target()?;
+        let trid = self.find_listener(nvmf_target, address, port)?;
+        if nvmf_target.listeners().len() == 1 {
+            return Err(Error::RemoveLastListener {
+                name: self.name.clone(),
+            });
+        }
+
+        nvmf_target
+            .remove_listener(&trid)
+            .await
+            .context(RemoveListener {
+                name: self.name.clone(),
+            })
+    }
+
+    /// Return the ANA state reported to hosts for the path to the nexus
+    /// through the listener on the given address and port, or through its
+    /// first listener if none is given.
+    pub fn get_ana_state(
+        &self,
+        listener: Option<(&str, u16)>,
+    ) -> Result<NvmeAnaState, Error> {
+        let nvmf_target = self.nvmf_target()?;
+        let listeners = nvmf_target.listeners();
+        let ana_state = match listener {
+            Some((address, port)) => {
+                let trid = self.find_listener(nvmf_target, address, port)?;
+                listeners.iter().find(|(t, _)| *t == trid).map(|(_, s)| *s)
+            }
+            None => listeners.first().map(|(_, s)| *s),
+        };
+        Ok(ana_state
+            .map(ana_state_from_u32)
+            .unwrap_or(NvmeAnaState::NvmeAnaOptimizedState))
+    }
+
+    /// Change the ANA state reported for the path to the nexus through the
+    /// listener on the given address and port, or through every listener if
+    /// none is given. A path through a node that is being drained can be
+    /// made inaccessible to have the multipath driver of the host fail over
+    /// to another path.
+    pub async fn set_ana_state(
+        &self,
+        ana_state: NvmeAnaState,
+        listener: Option<(&str, u16)>,
+    ) -> Result<(), Error> {
+        check_ana_state(ana_state)?;
+        let nvmf_target = self.nvmf_target()?;
+        let trids = match listener {
+            Some((address, port)) => {
+                vec![self.find_listener(nvmf_target, address, port)?]
+            }
+            None => nvmf_target
+                .listeners()
+                .into_iter()
+                .map(|(trid, _)| trid)
+                .collect(),
+        };
+
+        for trid in trids {
+            nvmf_target
+                .set_ana_state(&trid, ana_state as u32)
+                .await
+                .context(SetAnaState {
+                    name: self.name.clone(),
+                })?;
+        }
+        Ok(())
+    }
+
+    fn nvmf_target(&self) -> Result<&NexusNvmfTarget, Error> {
+        match self.nexus_target {
+            Some(NexusTarget::NexusNvmfTarget(ref nvmf_target)) => {
+                Ok(nvmf_target)
+            }
+            _ => Err(Error::NotSharedNvmf {
+                name: self.name.clone(),
+            }),
+        }
+    }
+
+    fn find_listener(
+        &self,
+        nvmf_target: &NexusNvmfTarget,
+        address: &str,
+        port: u16,
+    ) -> Result<TransportID, Error> {
+        let trid = listener_trid(address, port)?;
+        nvmf_target
+            .listeners()
+            .into_iter()
+            .map(|(t, _)| t)
+            .find(|t| *t == trid)
+            .ok_or_else(|| Error::ListenerNotFound {
+                listener: trid.to_string(),
+                name: self.name.clone(),
+            })
+    }
+
     /// Return URI under which the nexus is shared or None if not shared.
     pub fn get_share_uri(&self) -> Option<String> {
         match self.nexus_target {
@@ -297,3 +479,27 @@ impl Nexus {
         }
     }
 }
+
+/// transport ID of a nexus listener on the given IPv4 address and port
+fn listener_trid(address: &str, port: u16) -> Result<TransportID, Error> {
+    if address.parse::<Ipv4Addr>().is_err() || port == 0 {
+        return Err(Error::InvalidListenerAddress {
+            address: format!("{}:{}", address, port),
+        });
+    }
+    Ok(TransportID::with_address(address, port))
+}
+
+fn check_ana_state(ana_state: NvmeAnaState) -> Result<(), Error> {
+    if ana_state == NvmeAnaState::NvmeAnaInvalidState {
+        return Err(Error::InvalidAnaState {
+            value: ana_state as i32,
+        });
+    }
+    Ok(())
+}
+
+fn ana_state_from_u32(ana_state: u32) -> NvmeAnaState {
+    NvmeAnaState::from_i32(ana_state as i32)
+        .unwrap_or(NvmeAnaState::NvmeAnaInvalidState)
+}
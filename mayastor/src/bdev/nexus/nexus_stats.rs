@@ -0,0 +1,151 @@
+//!
+//! IO statistics of a nexus and of each of its children. The counters are
+//! updated on completion of the IO, on whichever core it completes, so they
+//! are kept in atomics rather than per IO channel.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use spdk_sys::{spdk_get_ticks, spdk_get_ticks_hz};
+
+use crate::bdev::nexus::nexus_io::{io_type, Bio};
+
+/// the number of buckets per power of two, as a power of two
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+/// with 8 buckets per power of two, this covers latencies of up to 2^34us
+/// (about 4.7 hours), anything slower ends up in the last bucket
+const NUM_BUCKETS: usize = 256;
+
+/// A log-linear histogram of IO latencies in microseconds. Every power of two
+/// is split into a number of equally sized buckets, which bounds the error of
+/// the reported percentiles to 1/8th of the value.
+pub(crate) struct LatencyHistogram {
+    buckets: Vec<AtomicU64>,
+}
+
+impl std::fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0 .. NUM_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn bucket(us: u64) -> usize {
+        if us < SUB_BUCKETS {
+            return us as usize;
+        }
+        let shift = 63 - us.leading_zeros() - SUB_BUCKET_BITS;
+        let index = (u64::from(shift) + 1) * SUB_BUCKETS
+            + ((us >> shift) & (SUB_BUCKETS - 1));
+        (index as usize).min(NUM_BUCKETS - 1)
+    }
+
+    /// the highest latency that falls into the bucket
+    fn upper_bound(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+        let shift = bucket / SUB_BUCKETS - 1;
+        let mantissa = SUB_BUCKETS + bucket % SUB_BUCKETS;
+        ((mantissa + 1) << shift) - 1
+    }
+
+    fn record(&self, us: u64) {
+        self.buckets[Self::bucket(us)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count(&self) -> u64 {
+        self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).sum()
+    }
+
+    /// returns the latency in microseconds below which the given fraction of
+    /// the IOs completed, or 0 if no IO has completed yet
+    pub(crate) fn percentile(&self, fraction: f64) -> u64 {
+        let counts = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+
+        let target = ((total as f64 * fraction).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Self::upper_bound(bucket);
+            }
+        }
+        Self::upper_bound(NUM_BUCKETS - 1)
+    }
+}
+
+/// IO counters of either the nexus or one of its children
+#[derive(Debug, Default)]
+pub(crate) struct IoStats {
+    pub(crate) num_read_ops: AtomicU64,
+    pub(crate) num_write_ops: AtomicU64,
+    pub(crate) num_unmap_ops: AtomicU64,
+    pub(crate) bytes_read: AtomicU64,
+    pub(crate) bytes_written: AtomicU64,
+    pub(crate) bytes_unmapped: AtomicU64,
+    /// IOs which only succeeded after one or more failed attempts
+    pub(crate) num_retried_ops: AtomicU64,
+    pub(crate) read_latency: LatencyHistogram,
+    pub(crate) write_latency: LatencyHistogram,
+}
+
+impl IoStats {
+    /// account for an IO that completed successfully, `retried` is set if
+    /// the IO was part of a second or later attempt
+    pub(crate) fn record(&self, io: &Bio, retried: bool) {
+        let bytes = io.num_blocks() * io.block_len();
+        let latency = io.latency_us();
+
+        match io.io_type() {
+            io_type::READ => {
+                self.num_read_ops.fetch_add(1, Ordering::Relaxed);
+                self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+                self.read_latency.record(latency);
+            }
+            io_type::WRITE => {
+                self.num_write_ops.fetch_add(1, Ordering::Relaxed);
+                self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+                self.write_latency.record(latency);
+            }
+            io_type::UNMAP => {
+                self.num_unmap_ops.fetch_add(1, Ordering::Relaxed);
+                self.bytes_unmapped.fetch_add(bytes, Ordering::Relaxed);
+            }
+            _ => return,
+        }
+
+        if retried {
+            self.num_retried_ops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// convert a tick count into microseconds
+fn ticks_to_us(ticks: u64) -> u64 {
+    (u128::from(ticks) * 1_000_000 / u128::from(unsafe { spdk_get_ticks_hz() }))
+        as u64
+}
+
+/// the number of microseconds elapsed since the given tick count
+pub(crate) fn elapsed_us(since: u64) -> u64 {
+    ticks_to_us(unsafe { spdk_get_ticks() }.saturating_sub(since))
+}
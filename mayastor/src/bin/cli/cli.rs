@@ -23,6 +23,7 @@ mod perf_cli;
 mod pool_cli;
 mod rebuild_cli;
 mod replica_cli;
+mod scrub_cli;
 mod snapshot_cli;
 
 type MayaClient = MayastorClient<Channel>;
@@ -85,6 +86,7 @@ async fn main() -> Result<(), Status> {
         .subcommand(device_cli::subcommands())
         .subcommand(perf_cli::subcommands())
         .subcommand(rebuild_cli::subcommands())
+        .subcommand(scrub_cli::subcommands())
         .subcommand(snapshot_cli::subcommands())
         .subcommand(jsonrpc_cli::subcommands())
         .get_matches();
@@ -99,6 +101,7 @@ async fn main() -> Result<(), Status> {
         ("pool", Some(args)) => pool_cli::handler(ctx, args).await?,
         ("replica", Some(args)) => replica_cli::handler(ctx, args).await?,
         ("rebuild", Some(args)) => rebuild_cli::handler(ctx, args).await?,
+        ("scrub", Some(args)) => scrub_cli::handler(ctx, args).await?,
         ("snapshot", Some(args)) => snapshot_cli::handler(ctx, args).await?,
         ("jsonrpc", Some(args)) => {
             jsonrpc_cli::json_rpc_call(ctx, args).await?
@@ -4,6 +4,40 @@ use byte_unit::Byte;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use tonic::{Code, Status};
 
+const READ_POLICIES: [&str; 3] =
+    ["round-robin", "least-queue-depth", "prefer-local"];
+
+const ANA_STATES: [&str; 3] = ["optimized", "non-optimized", "inaccessible"];
+
+/// the QoS limits of a nexus, with the options to set their rate and burst
+const QOS_LIMITS: [(&str, &str, &str); 4] = [
+    ("read-iops", "read-iops-burst", "reads per second"),
+    ("write-iops", "write-iops-burst", "writes per second"),
+    ("read-mbps", "read-mbps-burst", "MiB read per second"),
+    ("write-mbps", "write-mbps-burst", "MiB written per second"),
+];
+
+/// add the options to set the QoS limits of a nexus
+fn qos_args<'a, 'b>(mut cmd: App<'a, 'b>) -> App<'a, 'b> {
+    for (rate, burst, help) in QOS_LIMITS.iter() {
+        cmd = cmd
+            .arg(
+                Arg::with_name(rate)
+                    .long(rate)
+                    .value_name("RATE")
+                    .help(help),
+            )
+            .arg(
+                Arg::with_name(burst)
+                    .long(burst)
+                    .value_name("BURST")
+                    .requires(rate)
+                    .help("allowance on top of the rate after a quiet period"),
+            );
+    }
+    cmd
+}
+
 pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
     let create = SubCommand::with_name("create")
         .about("Create a new nexus device")
@@ -25,7 +59,16 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .index(3)
                 .help("list of children to add"),
+        )
+        .arg(
+            Arg::with_name("read-policy")
+                .short("r")
+                .long("read-policy")
+                .value_name("POLICY")
+                .possible_values(&READ_POLICIES)
+                .help("policy used to select the child to read from"),
         );
+    let create = qos_args(create);
 
     let destroy = SubCommand::with_name("destroy")
         .about("destroy the nexus with given name")
@@ -43,7 +86,10 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .arg(Arg::with_name("uuid").required(true).index(1)
             .help("uuid for the nexus"))
         .arg(Arg::with_name("key").required(false).index(2)
-            .help("crypto key to use"));
+            .help("crypto key to use"))
+        .arg(Arg::with_name("allowed-host").short("a").long("allowed-host").value_name("NQN")
+            .multiple(true).number_of_values(1)
+            .help("NQN of a host allowed to connect over nvmf, any host if none is given"));
 
     let unpublish = SubCommand::with_name("unpublish")
         .about("unpublish the nexus")
@@ -90,6 +136,133 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uri of child to remove"),
         );
 
+    let read_policy = SubCommand::with_name("read-policy")
+        .about("set the policy used to select the child to read from")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid for the nexus"),
+        )
+        .arg(
+            Arg::with_name("policy")
+                .required(true)
+                .index(2)
+                .possible_values(&READ_POLICIES)
+                .help("read policy"),
+        );
+
+    let resize = SubCommand::with_name("resize")
+        .about("grow the nexus to the given size")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid for the nexus"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .index(2)
+                .help("new size with optional unit suffix"),
+        );
+
+    let qos = qos_args(
+        SubCommand::with_name("qos")
+            .about("show the QoS limits of the nexus, or replace them if any limit is given")
+            .arg(
+                Arg::with_name("uuid")
+                    .required(true)
+                    .index(1)
+                    .help("uuid for the nexus"),
+            ),
+    );
+
+    let ana_state = SubCommand::with_name("ana-state")
+        .about("get or set the NVMe ANA state of the paths through this node")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid for the nexus"),
+        )
+        .arg(
+            Arg::with_name("state")
+                .required(false)
+                .index(2)
+                .possible_values(&ANA_STATES)
+                .help("ANA state to set"),
+        )
+        .arg(
+            Arg::with_name("listener")
+                .short("l")
+                .long("listener")
+                .value_name("ADDRESS:PORT")
+                .help("listener of the path, the first one to get the state of or all of them to set it"),
+        );
+
+    let listener = SubCommand::with_name("listener")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::ColoredHelp,
+            AppSettings::ColorAlways,
+        ])
+        .about(
+            "manage the listeners, each a path to a nexus published over nvmf",
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("add a listener as another path to the nexus")
+                .arg(
+                    Arg::with_name("uuid")
+                        .required(true)
+                        .index(1)
+                        .help("uuid for the nexus"),
+                )
+                .arg(
+                    Arg::with_name("listener")
+                        .required(true)
+                        .index(2)
+                        .value_name("ADDRESS:PORT")
+                        .help("address and port to listen on"),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .short("s")
+                        .long("ana-state")
+                        .default_value("optimized")
+                        .possible_values(&ANA_STATES)
+                        .help("ANA state of the path"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("remove a listener of the nexus")
+                .arg(
+                    Arg::with_name("uuid")
+                        .required(true)
+                        .index(1)
+                        .help("uuid for the nexus"),
+                )
+                .arg(
+                    Arg::with_name("listener")
+                        .required(true)
+                        .index(2)
+                        .value_name("ADDRESS:PORT")
+                        .help("address and port of the listener"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("list the listeners of the nexus")
+                .arg(
+                    Arg::with_name("uuid")
+                        .required(true)
+                        .index(1)
+                        .help("uuid for the nexus"),
+                ),
+        );
+
     let list = SubCommand::with_name("list")
         .about("list all nexus devices")
         .arg(
@@ -100,6 +273,33 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(false),
         );
 
+    let stats = SubCommand::with_name("stats")
+        .about("IO stats of the nexus and its children")
+        .arg(
+            Arg::with_name("uuid")
+                .required(false)
+                .index(1)
+                .help("uuid of nexus, all nexuses if not given"),
+        );
+
+    let watch = SubCommand::with_name("watch")
+        .about("print nexus, child and rebuild state transitions as they occur")
+        .arg(
+            Arg::with_name("from-seq")
+                .long("from-seq")
+                .value_name("SEQ")
+                .requires("epoch")
+                .help("replay the events from this sequence number onwards"),
+        )
+        .arg(
+            Arg::with_name("epoch")
+                .long("epoch")
+                .value_name("EPOCH")
+                .help(
+                    "epoch of the sequence number, as printed with the events",
+                ),
+        );
+
     let children = SubCommand::with_name("children")
         .about("list nexus children")
         .arg(
@@ -121,9 +321,16 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(publish)
         .subcommand(add)
         .subcommand(remove)
+        .subcommand(read_policy)
+        .subcommand(resize)
+        .subcommand(qos)
+        .subcommand(ana_state)
+        .subcommand(listener)
         .subcommand(unpublish)
         .subcommand(list)
         .subcommand(children)
+        .subcommand(stats)
+        .subcommand(watch)
         .subcommand(nexus_child_cli::subcommands())
 }
 
@@ -136,10 +343,25 @@ pub async fn handler(
         ("destroy", Some(args)) => nexus_destroy(ctx, &args).await,
         ("list", Some(args)) => nexus_list(ctx, &args).await,
         ("children", Some(args)) => nexus_children(ctx, &args).await,
+        ("stats", Some(args)) => nexus_stats(ctx, &args).await,
+        ("watch", Some(args)) => nexus_watch(ctx, &args).await,
         ("publish", Some(args)) => nexus_publish(ctx, &args).await,
         ("unpublish", Some(args)) => nexus_unpublish(ctx, &args).await,
         ("add", Some(args)) => nexus_add(ctx, &args).await,
         ("remove", Some(args)) => nexus_remove(ctx, &args).await,
+        ("read-policy", Some(args)) => nexus_read_policy(ctx, &args).await,
+        ("resize", Some(args)) => nexus_resize(ctx, &args).await,
+        ("qos", Some(args)) => nexus_qos(ctx, &args).await,
+        ("ana-state", Some(args)) => nexus_ana_state(ctx, &args).await,
+        ("listener", Some(args)) => match args.subcommand() {
+            ("add", Some(args)) => nexus_listener_add(ctx, &args).await,
+            ("remove", Some(args)) => nexus_listener_remove(ctx, &args).await,
+            ("list", Some(args)) => nexus_listener_list(ctx, &args).await,
+            (cmd, _) => Err(Status::not_found(format!(
+                "command {} does not exist",
+                cmd
+            ))),
+        },
         ("child", Some(args)) => nexus_child_cli::handler(ctx, args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
@@ -160,6 +382,9 @@ async fn nexus_create(
         .split_whitespace()
         .map(|c| c.to_string())
         .collect::<Vec<String>>();
+    let read_policy =
+        read_policy_from_str(matches.value_of("read-policy").unwrap_or(""))?;
+    let qos = qos_from_matches(matches)?;
 
     ctx.v2(&format!(
         "Creating nexus {} of size {} ",
@@ -173,6 +398,8 @@ async fn nexus_create(
             uuid: uuid.clone(),
             size,
             children,
+            read_policy: read_policy.into(),
+            qos: Some(qos),
         })
         .await?;
     ctx.v1(&format!("Nexus {} created", uuid));
@@ -242,6 +469,127 @@ async fn nexus_list(
     Ok(())
 }
 
+async fn nexus_stats(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap_or_default().to_string();
+
+    ctx.v2("Requesting nexus stats");
+    let resp = ctx
+        .client
+        .stat_nexus(rpc::StatNexusRequest {
+            uuid,
+        })
+        .await?;
+    let nexus = &resp.get_ref().nexus_list;
+    if nexus.is_empty() {
+        ctx.v1("No nexus found");
+        return Ok(());
+    }
+
+    let row = |name: String, stats: &rpc::IoStats| {
+        let read = stats.read_latency.clone().unwrap_or_default();
+        let write = stats.write_latency.clone().unwrap_or_default();
+        vec![
+            name,
+            stats.num_read_ops.to_string(),
+            stats.num_write_ops.to_string(),
+            stats.num_unmap_ops.to_string(),
+            ctx.units(Byte::from_bytes(stats.bytes_read.into())),
+            ctx.units(Byte::from_bytes(stats.bytes_written.into())),
+            stats.num_retried_ops.to_string(),
+            format!("{}/{}/{}", read.p50_us, read.p99_us, read.p999_us),
+            format!("{}/{}/{}", write.p50_us, write.p99_us, write.p999_us),
+        ]
+    };
+
+    let mut table = Vec::new();
+    for n in nexus {
+        table.push(row(n.uuid.clone(), &n.stats.clone().unwrap_or_default()));
+        for c in &n.children {
+            table.push(row(
+                format!("  {}", c.uri),
+                &c.stats.clone().unwrap_or_default(),
+            ));
+        }
+    }
+    ctx.print_list(
+        vec![
+            "NAME",
+            ">RDCNT",
+            ">WRCNT",
+            ">UNMAPCNT",
+            ">RDBYTES",
+            ">WRBYTES",
+            ">RETRIED",
+            ">RD P50/P99/P999 (us)",
+            ">WR P50/P99/P999 (us)",
+        ],
+        table,
+    );
+
+    Ok(())
+}
+
+async fn nexus_watch(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let from_seq = value_t!(matches.value_of("from-seq"), u64).unwrap_or(0);
+    let epoch = value_t!(matches.value_of("epoch"), u64).unwrap_or(0);
+
+    ctx.v2("Watching nexus events");
+    let mut events = ctx
+        .client
+        .watch_events(rpc::WatchEventsRequest {
+            from_seq,
+            epoch,
+        })
+        .await?
+        .into_inner();
+
+    while let Some(event) = events.message().await? {
+        let detail = match rpc::NexusEventType::from_i32(event.event) {
+            Some(rpc::NexusEventType::EventChildState) => format!(
+                "child {} {} -> {} {}",
+                event.uri,
+                child_state_to_str(event.old_child_state),
+                child_state_to_str(event.new_child_state),
+                reason_to_str(event.reason),
+            ),
+            Some(rpc::NexusEventType::EventNexusState) => format!(
+                "nexus {} -> {}",
+                nexus_state_to_str(event.old_nexus_state),
+                nexus_state_to_str(event.new_nexus_state),
+            ),
+            Some(rpc::NexusEventType::EventRebuildStarted) => {
+                format!("rebuild of {} started", event.uri)
+            }
+            Some(rpc::NexusEventType::EventRebuildCompleted) => {
+                format!("rebuild of {} completed", event.uri)
+            }
+            Some(rpc::NexusEventType::EventRebuildStopped) => {
+                format!("rebuild of {} stopped", event.uri)
+            }
+            Some(rpc::NexusEventType::EventRebuildFailed) => {
+                format!("rebuild of {} failed", event.uri)
+            }
+            None => format!("unknown event {}", event.event),
+        };
+        println!(
+            "{} {} {} {} {}",
+            event.epoch,
+            event.seq,
+            event.timestamp_ms,
+            event.uuid,
+            detail.trim_end()
+        );
+    }
+
+    Ok(())
+}
+
 async fn nexus_children(
     mut ctx: Context,
     matches: &ArgMatches<'_>,
@@ -281,6 +629,10 @@ async fn nexus_publish(
 ) -> Result<(), Status> {
     let uuid = matches.value_of("uuid").unwrap().to_string();
     let key = matches.value_of("key").unwrap_or("").to_string();
+    let allowed_hosts = matches
+        .values_of("allowed-host")
+        .map(|hosts| hosts.map(|h| h.to_owned()).collect())
+        .unwrap_or_default();
     let prot = match matches.value_of("protocol") {
         None => rpc::ShareProtocolNexus::NexusNbd,
         Some("nvmf") => rpc::ShareProtocolNexus::NexusNvmf,
@@ -300,6 +652,7 @@ async fn nexus_publish(
             uuid,
             key,
             share: prot.into(),
+            allowed_hosts,
         })
         .await?;
     ctx.v1(&format!("Nexus published at {}", resp.get_ref().device_uri));
@@ -364,6 +717,301 @@ async fn nexus_remove(
     Ok(())
 }
 
+async fn nexus_read_policy(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let read_policy =
+        read_policy_from_str(matches.value_of("policy").unwrap())?;
+
+    ctx.v2(&format!(
+        "Setting read policy of {} to {:?}",
+        uuid, read_policy
+    ));
+    ctx.client
+        .set_nexus_read_policy(rpc::SetNexusReadPolicyRequest {
+            uuid: uuid.clone(),
+            read_policy: read_policy.into(),
+        })
+        .await?;
+    ctx.v1(&format!("Read policy of {} set", uuid));
+    Ok(())
+}
+
+async fn nexus_resize(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let size = parse_size(matches.value_of("size").unwrap())
+        .map_err(|s| Status::invalid_argument(format!("Bad size '{}'", s)))?;
+
+    ctx.v2(&format!("Resizing nexus {} to {}", uuid, ctx.units(size)));
+    ctx.client
+        .resize_nexus(rpc::ResizeNexusRequest {
+            uuid: uuid.clone(),
+            size: size.get_bytes() as u64,
+        })
+        .await?;
+    ctx.v1(&format!("Nexus {} resized", uuid));
+    Ok(())
+}
+
+async fn nexus_ana_state(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let (address, port) = match matches.value_of("listener") {
+        Some(listener) => listener_from_str(listener)?,
+        None => (String::new(), 0),
+    };
+
+    if let Some(state) = matches.value_of("state") {
+        let ana_state = ana_state_from_str(state)?;
+        ctx.v2(&format!("Setting ANA state of nexus {} to {}", uuid, state));
+        ctx.client
+            .set_nvme_ana_state(rpc::SetNvmeAnaStateRequest {
+                uuid: uuid.clone(),
+                ana_state: ana_state.into(),
+                address,
+                port,
+            })
+            .await?;
+        ctx.v1(&format!("Nexus {} ANA state set to {}", uuid, state));
+    } else {
+        ctx.v2(&format!("Getting ANA state of nexus {}", uuid));
+        let resp = ctx
+            .client
+            .get_nvme_ana_state(rpc::GetNvmeAnaStateRequest {
+                uuid,
+                address,
+                port,
+            })
+            .await?;
+        ctx.v1(ana_state_to_str(resp.get_ref().ana_state));
+    }
+    Ok(())
+}
+
+async fn nexus_listener_add(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let (address, port) =
+        listener_from_str(matches.value_of("listener").unwrap())?;
+    let ana_state = ana_state_from_str(matches.value_of("state").unwrap())?;
+
+    ctx.v2(&format!(
+        "Adding listener {}:{} to nexus {}",
+        address, port, uuid
+    ));
+    let resp = ctx
+        .client
+        .add_nexus_listener(rpc::AddNexusListenerRequest {
+            uuid,
+            address,
+            port,
+            ana_state: ana_state.into(),
+        })
+        .await?;
+    ctx.v1(&resp.get_ref().uri);
+    Ok(())
+}
+
+async fn nexus_listener_remove(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let (address, port) =
+        listener_from_str(matches.value_of("listener").unwrap())?;
+
+    ctx.v2(&format!(
+        "Removing listener {}:{} from nexus {}",
+        address, port, uuid
+    ));
+    ctx.client
+        .remove_nexus_listener(rpc::RemoveNexusListenerRequest {
+            uuid: uuid.clone(),
+            address: address.clone(),
+            port,
+        })
+        .await?;
+    ctx.v1(&format!(
+        "Removed listener {}:{} from nexus {}",
+        address, port, uuid
+    ));
+    Ok(())
+}
+
+async fn nexus_listener_list(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    ctx.v2(&format!("Listeners of nexus {}:", uuid));
+    let resp = ctx
+        .client
+        .list_nexus_listeners(rpc::ListNexusListenersRequest {
+            uuid,
+        })
+        .await?;
+
+    let table = resp
+        .get_ref()
+        .listeners
+        .iter()
+        .map(|l| {
+            vec![
+                l.address.clone(),
+                l.port.to_string(),
+                ana_state_to_str(l.ana_state).to_string(),
+                l.uri.clone(),
+            ]
+        })
+        .collect();
+    ctx.print_list(vec!["ADDRESS", "PORT", "ANA_STATE", "URI"], table);
+    Ok(())
+}
+
+fn listener_from_str(listener: &str) -> Result<(String, u32), Status> {
+    let mut parts = listener.rsplitn(2, ':');
+    match (parts.next().map(|p| p.parse::<u16>()), parts.next()) {
+        (Some(Ok(port)), Some(address)) => {
+            Ok((address.to_string(), port as u32))
+        }
+        _ => Err(Status::new(
+            Code::InvalidArgument,
+            format!("Invalid listener '{}', expected ADDRESS:PORT", listener),
+        )),
+    }
+}
+
+async fn nexus_qos(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    if QOS_LIMITS
+        .iter()
+        .any(|(rate, _, _)| matches.is_present(rate))
+    {
+        let qos = qos_from_matches(matches)?;
+        ctx.v2(&format!("Setting QoS limits of nexus {}", uuid));
+        ctx.client
+            .set_nexus_qos(rpc::SetNexusQosRequest {
+                uuid: uuid.clone(),
+                qos: Some(qos),
+            })
+            .await?;
+        ctx.v1(&format!("QoS limits of nexus {} set", uuid));
+        return Ok(());
+    }
+
+    let resp = ctx.client.list_nexus(rpc::Null {}).await?;
+    let nexus = resp
+        .get_ref()
+        .nexus_list
+        .iter()
+        .find(|n| n.uuid == uuid)
+        .ok_or_else(|| {
+            Status::new(
+                Code::InvalidArgument,
+                "Specified nexus not found".to_owned(),
+            )
+        })?;
+
+    let qos = nexus.qos.clone().unwrap_or_default();
+    let table = vec![
+        ("read-iops", qos.read_iops, nexus.throttled_reads),
+        ("write-iops", qos.write_iops, nexus.throttled_writes),
+        ("read-mbps", qos.read_mbps, nexus.throttled_reads),
+        ("write-mbps", qos.write_mbps, nexus.throttled_writes),
+    ]
+    .into_iter()
+    .map(|(name, limit, throttled)| {
+        let limit = limit.unwrap_or_default();
+        vec![
+            name.to_string(),
+            limit.rate.to_string(),
+            limit.burst.to_string(),
+            throttled.to_string(),
+        ]
+    })
+    .collect();
+    ctx.print_list(vec!["LIMIT", ">RATE", ">BURST", ">THROTTLED"], table);
+    Ok(())
+}
+
+/// the QoS limits given on the command line, limits that are not given are
+/// not enforced
+fn qos_from_matches(matches: &ArgMatches<'_>) -> Result<rpc::NexusQos, Status> {
+    let limit = |rate: &str, burst: &str| -> Result<rpc::QosLimit, Status> {
+        let parse = |name: &str| {
+            matches
+                .value_of(name)
+                .unwrap_or("0")
+                .parse::<u64>()
+                .map_err(|_| {
+                    Status::invalid_argument(format!("Bad value of {}", name))
+                })
+        };
+        Ok(rpc::QosLimit {
+            rate: parse(rate)?,
+            burst: parse(burst)?,
+        })
+    };
+
+    Ok(rpc::NexusQos {
+        read_iops: Some(limit("read-iops", "read-iops-burst")?),
+        write_iops: Some(limit("write-iops", "write-iops-burst")?),
+        read_mbps: Some(limit("read-mbps", "read-mbps-burst")?),
+        write_mbps: Some(limit("write-mbps", "write-mbps-burst")?),
+    })
+}
+
+fn ana_state_from_str(state: &str) -> Result<rpc::NvmeAnaState, Status> {
+    match state {
+        "optimized" => Ok(rpc::NvmeAnaState::NvmeAnaOptimizedState),
+        "non-optimized" => Ok(rpc::NvmeAnaState::NvmeAnaNonOptimizedState),
+        "inaccessible" => Ok(rpc::NvmeAnaState::NvmeAnaInaccessibleState),
+        _ => Err(Status::new(
+            Code::InvalidArgument,
+            "Invalid value of ANA state".to_owned(),
+        )),
+    }
+}
+
+fn ana_state_to_str(idx: i32) -> &'static str {
+    match rpc::NvmeAnaState::from_i32(idx) {
+        Some(rpc::NvmeAnaState::NvmeAnaOptimizedState) => "optimized",
+        Some(rpc::NvmeAnaState::NvmeAnaNonOptimizedState) => "non-optimized",
+        Some(rpc::NvmeAnaState::NvmeAnaInaccessibleState) => "inaccessible",
+        Some(rpc::NvmeAnaState::NvmeAnaPersistentLossState) => {
+            "persistent-loss"
+        }
+        Some(rpc::NvmeAnaState::NvmeAnaChangeState) => "change",
+        _ => "invalid",
+    }
+}
+
+fn read_policy_from_str(policy: &str) -> Result<rpc::NexusReadPolicy, Status> {
+    match policy {
+        "" | "round-robin" => Ok(rpc::NexusReadPolicy::ReadRoundRobin),
+        "least-queue-depth" => Ok(rpc::NexusReadPolicy::ReadLeastQueueDepth),
+        "prefer-local" => Ok(rpc::NexusReadPolicy::ReadPreferLocal),
+        _ => Err(Status::new(
+            Code::InvalidArgument,
+            "Invalid value of read policy".to_owned(),
+        )),
+    }
+}
+
 fn nexus_state_to_str(idx: i32) -> &'static str {
     match rpc::NexusState::from_i32(idx).unwrap() {
         rpc::NexusState::NexusUnknown => "unknown",
@@ -381,3 +1029,15 @@ fn child_state_to_str(idx: i32) -> &'static str {
         rpc::ChildState::ChildFaulted => "faulted",
     }
 }
+
+fn reason_to_str(idx: i32) -> &'static str {
+    match rpc::ChildStateReason::from_i32(idx).unwrap() {
+        rpc::ChildStateReason::ReasonNone => "",
+        rpc::ChildStateReason::ReasonOutOfSync => "(out of sync)",
+        rpc::ChildStateReason::ReasonCantOpen => "(cannot open)",
+        rpc::ChildStateReason::ReasonRebuildFailed => "(rebuild failed)",
+        rpc::ChildStateReason::ReasonIoError => "(IO errors)",
+        rpc::ChildStateReason::ReasonRpc => "(faulted by rpc)",
+        rpc::ChildStateReason::ReasonSnapshotMissing => "(snapshot missing)",
+    }
+}
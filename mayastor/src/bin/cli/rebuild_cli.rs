@@ -17,6 +17,8 @@ pub async fn handler(
         ("resume", Some(args)) => resume(ctx, &args).await,
         ("state", Some(args)) => state(ctx, &args).await,
         ("progress", Some(args)) => progress(ctx, &args).await,
+        ("set-rate", Some(args)) => set_rate(ctx, &args).await,
+        ("set-global-rate", Some(args)) => set_global_rate(ctx, &args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
         }
@@ -114,6 +116,36 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uri of child to get the rebuild progress from"),
         );
 
+    let set_rate = SubCommand::with_name("set-rate")
+        .about("limits the bandwidth of a rebuild")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .required(true)
+                .index(2)
+                .help("uri of child being rebuilt"),
+        )
+        .arg(
+            Arg::with_name("rate")
+                .required(true)
+                .index(3)
+                .help("bandwidth limit in MiB/s, 0 for no limit"),
+        );
+
+    let set_global_rate = SubCommand::with_name("set-global-rate")
+        .about("limits the bandwidth of all rebuilds combined")
+        .arg(
+            Arg::with_name("rate")
+                .required(true)
+                .index(1)
+                .help("bandwidth limit in MiB/s, 0 for no limit"),
+        );
+
     SubCommand::with_name("rebuild")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -127,6 +159,8 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(resume)
         .subcommand(state)
         .subcommand(progress)
+        .subcommand(set_rate)
+        .subcommand(set_global_rate)
 }
 
 async fn start(
@@ -254,3 +288,50 @@ async fn progress(
     println!("{}% complete", response.progress);
     Ok(())
 }
+
+async fn set_rate(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let uri = matches.value_of("uri").unwrap().to_string();
+    let rate = matches
+        .value_of("rate")
+        .unwrap()
+        .parse::<u64>()
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+    ctx.client
+        .set_rebuild_rate(rpc::SetRebuildRateRequest {
+            uuid: uuid.clone(),
+            uri: uri.clone(),
+            rate,
+        })
+        .await?;
+    ctx.v1(&format!(
+        "Limiting rebuild of child {} on nexus {} to {} MiB/s",
+        uri, uuid, rate
+    ));
+    Ok(())
+}
+
+async fn set_global_rate(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let rate = matches
+        .value_of("rate")
+        .unwrap()
+        .parse::<u64>()
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+    ctx.client
+        .set_rebuild_rate(rpc::SetRebuildRateRequest {
+            uuid: String::new(),
+            uri: String::new(),
+            rate,
+        })
+        .await?;
+    ctx.v1(&format!("Limiting all rebuilds to {} MiB/s", rate));
+    Ok(())
+}
@@ -51,6 +51,21 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("Replica uuid"),
         );
 
+    let resize = SubCommand::with_name("resize")
+        .about("Grow or shrink replica")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .index(2)
+                .help("New size with optional unit suffix"),
+        );
+
     let share = SubCommand::with_name("share").about("Share or unshare replica")
         .arg(
             Arg::with_name("uuid")
@@ -61,7 +76,15 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("protocol")
                 .required(true)
                 .index(2)
-                .help("Name of a protocol (nvmf, iscsi) used for sharing or \"none\" to unshare the replica"));
+                .help("Name of a protocol (nvmf, iscsi) used for sharing or \"none\" to unshare the replica"))
+        .arg(
+            Arg::with_name("allowed-host")
+                .short("a")
+                .long("allowed-host")
+                .value_name("NQN")
+                .multiple(true)
+                .number_of_values(1)
+                .help("NQN of a host allowed to connect, any host if none is given"));
 
     SubCommand::with_name("replica")
         .settings(&[
@@ -72,6 +95,7 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .about("Replica management")
         .subcommand(create)
         .subcommand(destroy)
+        .subcommand(resize)
         .subcommand(share)
         .subcommand(SubCommand::with_name("list").about("List replicas"))
         .subcommand(
@@ -87,6 +111,7 @@ pub async fn handler(
         ("create", Some(args)) => replica_create(ctx, &args).await,
         ("destroy", Some(args)) => replica_destroy(ctx, &args).await,
         ("list", Some(args)) => replica_list(ctx, &args).await,
+        ("resize", Some(args)) => replica_resize(ctx, &args).await,
         ("share", Some(args)) => replica_share(ctx, &args).await,
         ("stats", Some(args)) => replica_stat(ctx, &args).await,
         (cmd, _) => {
@@ -134,6 +159,30 @@ async fn replica_destroy(
     Ok(())
 }
 
+async fn replica_resize(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_owned();
+    let size = parse_size(matches.value_of("size").unwrap())
+        .map_err(|s| Status::invalid_argument(format!("Bad size '{}'", s)))?;
+
+    ctx.v2(&format!("Resizing replica {} to {}", uuid, ctx.units(size)));
+    let resp = ctx
+        .client
+        .resize_replica(rpc::ResizeReplicaRequest {
+            uuid,
+            size: size.get_bytes() as u64,
+        })
+        .await?;
+    ctx.v1(&format!(
+        "Resized {} to {}",
+        resp.get_ref().uuid,
+        ctx.units(Byte::from_bytes(resp.get_ref().size.into()))
+    ));
+    Ok(())
+}
+
 async fn replica_list(
     mut ctx: Context,
     _matches: &ArgMatches<'_>,
@@ -178,6 +227,10 @@ async fn replica_share(
 ) -> Result<(), Status> {
     let uuid = matches.value_of("uuid").unwrap().to_owned();
     let share = parse_replica_protocol(matches.value_of("protocol"))?;
+    let allowed_hosts = matches
+        .values_of("allowed-host")
+        .map(|hosts| hosts.map(|h| h.to_owned()).collect())
+        .unwrap_or_default();
 
     ctx.v2(&format!("Sharing replica {} on {}", uuid, share));
 
@@ -186,6 +239,7 @@ async fn replica_share(
         .share_replica(rpc::ShareReplicaRequest {
             uuid,
             share,
+            allowed_hosts,
         })
         .await?;
     ctx.v1(&format!("Shared {}", resp.get_ref().uri));
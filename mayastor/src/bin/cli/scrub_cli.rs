@@ -0,0 +1,131 @@
+//!
+//! methods to interact with the scrub process
+
+use crate::context::Context;
+use ::rpc::mayastor as rpc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use tonic::Status;
+
+pub async fn handler(
+    ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    match matches.subcommand() {
+        ("start", Some(args)) => start(ctx, &args).await,
+        ("stop", Some(args)) => stop(ctx, &args).await,
+        ("progress", Some(args)) => progress(ctx, &args).await,
+        (cmd, _) => {
+            Err(Status::not_found(format!("command {} does not exist", cmd)))
+        }
+    }
+}
+
+pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
+    let start = SubCommand::with_name("start")
+        .about("starts comparing the data of the healthy children")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("authority")
+                .short("r")
+                .long("repair-from")
+                .value_name("URI")
+                .help("uri of child to repair the divergent children from"),
+        );
+
+    let stop = SubCommand::with_name("stop").about("stops a scrub").arg(
+        Arg::with_name("uuid")
+            .required(true)
+            .index(1)
+            .help("uuid of the nexus"),
+    );
+
+    let progress = SubCommand::with_name("progress")
+        .about("shows the progress and the findings of a scrub")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        );
+
+    SubCommand::with_name("scrub")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::ColoredHelp,
+            AppSettings::ColorAlways,
+        ])
+        .about("Scrub management")
+        .subcommand(start)
+        .subcommand(stop)
+        .subcommand(progress)
+}
+
+async fn start(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let authority = matches.value_of("authority").unwrap_or("").to_string();
+
+    ctx.client
+        .start_scrub(rpc::StartScrubRequest {
+            uuid: uuid.clone(),
+            authority: authority.clone(),
+        })
+        .await?;
+    if authority.is_empty() {
+        ctx.v1(&format!("Starting scrub of nexus {}", uuid));
+    } else {
+        ctx.v1(&format!(
+            "Starting scrub of nexus {} repairing from child {}",
+            uuid, authority
+        ));
+    }
+    Ok(())
+}
+
+async fn stop(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    ctx.client
+        .stop_scrub(rpc::StopScrubRequest {
+            uuid: uuid.clone(),
+        })
+        .await?;
+    ctx.v1(&format!("Stopping scrub of nexus {}", uuid));
+    Ok(())
+}
+
+async fn progress(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    ctx.v2(&format!("Getting the scrub progress of nexus {}", uuid));
+    let response = ctx
+        .client
+        .get_scrub_progress(rpc::ScrubProgressRequest {
+            uuid: uuid.clone(),
+        })
+        .await?
+        .into_inner();
+    println!("{}, {}% complete", response.state, response.progress);
+    for mismatch in response.mismatches {
+        println!(
+            "{} block(s) at offset {} differ{}",
+            mismatch.num_blocks,
+            mismatch.offset,
+            if mismatch.repaired { ", repaired" } else { "" }
+        );
+    }
+    Ok(())
+}
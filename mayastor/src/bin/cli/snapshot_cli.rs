@@ -3,6 +3,7 @@
 
 use crate::context::Context;
 use ::rpc::mayastor as rpc;
+use byte_unit::Byte;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use tonic::Status;
 
@@ -12,6 +13,10 @@ pub async fn handler(
 ) -> Result<(), Status> {
     match matches.subcommand() {
         ("create", Some(args)) => create(ctx, &args).await,
+        ("list", Some(args)) => list(ctx, &args).await,
+        ("destroy", Some(args)) => destroy(ctx, &args).await,
+        ("clone", Some(args)) => clone(ctx, &args).await,
+        ("revert", Some(args)) => revert(ctx, &args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
         }
@@ -28,6 +33,53 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uuid of the nexus"),
         );
 
+    let destroy = SubCommand::with_name("destroy")
+        .about("destroy a snapshot")
+        .arg(
+            Arg::with_name("name")
+                .required(true)
+                .index(1)
+                .help("name of the snapshot"),
+        );
+
+    let clone = SubCommand::with_name("clone")
+        .about("create a replica from a snapshot")
+        .arg(
+            Arg::with_name("snapshot")
+                .required(true)
+                .index(1)
+                .help("name of the snapshot"),
+        )
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(2)
+                .help("uuid of the new replica"),
+        )
+        .arg(
+            Arg::with_name("protocol")
+                .short("p")
+                .long("protocol")
+                .value_name("PROTOCOL")
+                .possible_values(&["none", "nvmf"])
+                .help("protocol to share the new replica over"),
+        );
+
+    let revert = SubCommand::with_name("revert")
+        .about("revert a nexus to a snapshot")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("timestamp")
+                .required(true)
+                .index(2)
+                .help("timestamp of the snapshot"),
+        );
+
     SubCommand::with_name("snapshot")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -36,6 +88,10 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         ])
         .about("Snapshot management")
         .subcommand(create)
+        .subcommand(SubCommand::with_name("list").about("list snapshots"))
+        .subcommand(destroy)
+        .subcommand(clone)
+        .subcommand(revert)
 }
 
 async fn create(
@@ -44,11 +100,128 @@ async fn create(
 ) -> Result<(), Status> {
     let uuid = matches.value_of("uuid").unwrap().to_string();
 
-    ctx.client
+    let resp = ctx
+        .client
         .create_snapshot(rpc::CreateSnapshotRequest {
             uuid: uuid.clone(),
         })
         .await?;
-    ctx.v1(&format!("Creating snapshot on nexus {}", uuid));
+    let reply = resp.get_ref();
+    ctx.v1(&format!(
+        "Created snapshot {} at {} on nexus {}",
+        reply.name, reply.timestamp, uuid
+    ));
+    if !reply.complete {
+        ctx.v1("Not all children have been snapshotted");
+    }
+
+    let table = reply
+        .children
+        .iter()
+        .map(|c| vec![c.uri.clone(), c.name.clone(), c.created.to_string()])
+        .collect();
+    ctx.print_list(vec!["CHILD", "SNAPSHOT", ">CREATED"], table);
+    Ok(())
+}
+
+async fn list(
+    mut ctx: Context,
+    _matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let resp = ctx.client.list_snapshots(rpc::Null {}).await?;
+    let snapshots = &resp.get_ref().snapshots;
+    if snapshots.is_empty() {
+        ctx.v1("No snapshots found");
+        return Ok(());
+    }
+
+    let table = snapshots
+        .iter()
+        .map(|s| {
+            vec![
+                s.pool.clone(),
+                s.name.clone(),
+                s.replica.clone(),
+                s.timestamp.to_string(),
+                ctx.units(Byte::from_bytes(s.size.into())),
+                ctx.units(Byte::from_bytes(s.used.into())),
+            ]
+        })
+        .collect();
+    ctx.print_list(
+        vec!["POOL", "NAME", "REPLICA", ">TIMESTAMP", ">SIZE", ">USED"],
+        table,
+    );
+    Ok(())
+}
+
+async fn destroy(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let name = matches.value_of("name").unwrap().to_string();
+
+    ctx.v2(&format!("Destroying snapshot {}", name));
+    ctx.client
+        .destroy_snapshot(rpc::DestroySnapshotRequest {
+            name: name.clone(),
+        })
+        .await?;
+    ctx.v1(&format!("Snapshot {} destroyed", name));
+    Ok(())
+}
+
+async fn clone(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let snapshot = matches.value_of("snapshot").unwrap().to_string();
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let share = match matches.value_of("protocol") {
+        Some("nvmf") => rpc::ShareProtocolReplica::ReplicaNvmf,
+        _ => rpc::ShareProtocolReplica::ReplicaNone,
+    };
+
+    ctx.v2(&format!("Cloning snapshot {} to {}", snapshot, uuid));
+    let resp = ctx
+        .client
+        .create_clone(rpc::CreateCloneRequest {
+            snapshot,
+            uuid,
+            share: share as i32,
+        })
+        .await?;
+    let reply = resp.get_ref();
+    ctx.v1(&format!(
+        "Created {} from the snapshot of {} at {}",
+        reply.replica.as_ref().map_or("", |r| r.uri.as_str()),
+        reply.parent,
+        reply.timestamp
+    ));
+    Ok(())
+}
+
+async fn revert(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let timestamp = matches
+        .value_of("timestamp")
+        .unwrap()
+        .parse::<u64>()
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+    ctx.v2(&format!(
+        "Reverting nexus {} to snapshot {}",
+        uuid, timestamp
+    ));
+    ctx.client
+        .revert_nexus_to_snapshot(rpc::RevertNexusToSnapshotRequest {
+            uuid: uuid.clone(),
+            timestamp,
+        })
+        .await?;
+    ctx.v1(&format!("Nexus {} reverted", uuid));
     Ok(())
 }
@@ -7,6 +7,7 @@ use mayastor::{
     core::{MayastorCliArgs, MayastorEnvironment, Reactors},
     grpc,
     logger,
+    metrics,
     subsys,
 };
 use std::path::Path;
@@ -52,6 +53,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let grpc_endpoint = grpc::endpoint(args.grpc_endpoint.clone());
     let rpc_address = args.rpc_address.clone();
+    let metrics_endpoint = args.metrics_endpoint.clone().map(metrics::endpoint);
 
     let ms = rt.enter(|| MayastorEnvironment::new(args).init());
 
@@ -61,9 +63,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     futures.push(master.boxed_local());
     futures.push(subsys::Registration::run().boxed_local());
+    futures.push(subsys::EventPublisher::run().boxed_local());
     futures.push(
         grpc::MayastorGrpcServer::run(grpc_endpoint, rpc_address).boxed_local(),
     );
+    if let Some(endpoint) = metrics_endpoint {
+        metrics::MetricsServer::start(endpoint);
+        futures.push(metrics::run_collector().boxed_local());
+    }
 
     rt.block_on(futures::future::try_join_all(futures))
         .expect_err("reactor exit in abnormal state");
@@ -25,6 +25,7 @@ use spdk_sys::{
     spdk_bdev_io_stat,
     spdk_bdev_io_type_supported,
     spdk_bdev_next,
+    spdk_bdev_notify_blockcnt_change,
     spdk_bdev_open,
     spdk_uuid_generate,
 };
@@ -38,7 +39,7 @@ use crate::{
         CoreError::{ShareIscsi, ShareNvmf},
         Descriptor,
     },
-    ffihelper::{cb_arg, AsStr},
+    ffihelper::{cb_arg, errno_result_from_i32, AsStr, ErrnoResult},
     subsys::NvmfSubsystem,
     target::{iscsi, nvmf, Side},
 };
@@ -76,18 +77,7 @@ impl Share for Bdev {
 
     /// share the bdev over NVMe-OF TCP
     async fn share_nvmf(&self) -> Result<Self::Output, Self::Error> {
-        let ss = NvmfSubsystem::try_from(self.clone()).map_err(|source| {
-            ShareNvmf {
-                source,
-            }
-        })?;
-
-        let shared_as = ss.start().await.map_err(|source| ShareNvmf {
-            source,
-        })?;
-
-        info!("shared {}", shared_as);
-        Ok(shared_as)
+        self.share_nvmf_with_hosts(&[]).await
     }
 
     /// unshare the bdev regardless of current active share
@@ -151,6 +141,57 @@ impl Share for Bdev {
 }
 
 impl Bdev {
+    /// share the bdev over NVMe-OF TCP, only the given host NQNs are allowed
+    /// to connect unless the list is empty
+    pub async fn share_nvmf_with_hosts(
+        &self,
+        allowed_hosts: &[String],
+    ) -> Result<String, CoreError> {
+        let ss = NvmfSubsystem::try_from(self.clone()).map_err(|source| {
+            ShareNvmf {
+                source,
+            }
+        })?;
+
+        if let Err(source) = ss.set_allowed_hosts(allowed_hosts) {
+            ss.destroy();
+            return Err(ShareNvmf {
+                source,
+            });
+        }
+
+        let shared_as = ss.start().await.map_err(|source| ShareNvmf {
+            source,
+        })?;
+
+        info!("shared {}", shared_as);
+        Ok(shared_as)
+    }
+
+    /// change the hosts allowed to connect to the bdev when it is shared over
+    /// NVMe-OF TCP, this is a no-op when it is not
+    pub async fn set_allowed_hosts(
+        &self,
+        allowed_hosts: &[String],
+    ) -> Result<(), CoreError> {
+        if let Some(ss) = NvmfSubsystem::nqn_lookup(&self.name()) {
+            ss.update_allowed_hosts(allowed_hosts)
+                .await
+                .map_err(|source| ShareNvmf {
+                    source,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// the hosts allowed to connect to the bdev when it is shared over
+    /// NVMe-OF TCP, an empty list means any host is
+    pub fn allowed_hosts(&self) -> Vec<String> {
+        NvmfSubsystem::nqn_lookup(&self.name())
+            .map(|ss| ss.allowed_hosts())
+            .unwrap_or_default()
+    }
+
     /// bdevs are created and destroyed in order, adding a bdev to the nexus
     /// does interferes with this order. There we traverse all nexuses
     /// looking for our a child and then close it when found.
@@ -264,6 +305,15 @@ impl Bdev {
         }
     }
 
+    /// change the block count of this device once it has been registered,
+    /// the change is announced to all descriptors that are open on it.
+    /// Shrinking is refused whilst the device is open.
+    pub fn notify_block_count_change(&self, count: u64) -> ErrnoResult<()> {
+        let errno =
+            unsafe { spdk_bdev_notify_blockcnt_change(self.0.as_ptr(), count) };
+        errno_result_from_i32((), errno)
+    }
+
     /// set the block length of the device in bytes
     pub fn set_block_len(&mut self, len: u32) {
         unsafe {
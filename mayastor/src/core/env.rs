@@ -118,6 +118,10 @@ pub struct MayastorCliArgs {
     #[structopt(long = "env-context")]
     /// pass additional arguments to the EAL environment
     pub env_context: Option<String>,
+    #[structopt(long = "metrics-endpoint")]
+    /// IP address and port (optional) for the Prometheus metrics HTTP server,
+    /// no metrics are exported if not given
+    pub metrics_endpoint: Option<String>,
 }
 
 /// Defaults are redefined here in case of using it during tests
@@ -136,6 +140,7 @@ impl Default for MayastorCliArgs {
             mayastor_config: None,
             child_status_config: None,
             hugedir: None,
+            metrics_endpoint: None,
         }
     }
 }
@@ -354,6 +359,13 @@ impl MayastorEnvironment {
         }
     }
 
+    /// The NQN this node uses as host when connecting to NVMe-oF targets.
+    /// It is derived from the node name so that it is stable across restarts
+    /// and replicas can be restricted to the node of their nexus.
+    pub fn host_nqn(&self) -> String {
+        format!("nqn.2019-05.io.openebs:node-name:{}", self.node_name)
+    }
+
     /// configure signal handling
     fn install_signal_handlers(&self) -> Result<()> {
         unsafe {
@@ -727,6 +739,7 @@ impl MayastorEnvironment {
                 )));
             }
             futures.push(Box::pin(subsys::Registration::run()));
+            futures.push(Box::pin(subsys::EventPublisher::run()));
             futures.push(Box::pin(master));
             let _out = future::try_join_all(futures).await;
             info!("reactors stopped");
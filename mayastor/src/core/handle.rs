@@ -14,10 +14,12 @@ use spdk_sys::{
     spdk_bdev_desc,
     spdk_bdev_free_io,
     spdk_bdev_io,
+    spdk_bdev_io_get_nvme_status,
     spdk_bdev_nvme_admin_passthru_ro,
     spdk_bdev_read,
     spdk_bdev_reset,
     spdk_bdev_write,
+    spdk_bdev_write_zeroes,
     spdk_io_channel,
 };
 
@@ -145,6 +147,43 @@ impl BdevHandle {
         }
     }
 
+    /// write zeroes to `len` bytes at the given offset, which the bdev layer
+    /// emulates with regular writes for the bdevs which do not support it
+    pub async fn write_zeroes_at(
+        &self,
+        offset: u64,
+        len: u64,
+    ) -> Result<u64, CoreError> {
+        let (s, r) = oneshot::channel::<bool>();
+        let errno = unsafe {
+            spdk_bdev_write_zeroes(
+                self.desc.as_ptr(),
+                self.channel.as_ptr(),
+                offset,
+                len,
+                Some(Self::io_completion_cb),
+                cb_arg(s),
+            )
+        };
+
+        if errno != 0 {
+            return Err(CoreError::WriteDispatch {
+                source: Errno::from_i32(errno),
+                offset,
+                len,
+            });
+        }
+
+        if r.await.expect("Failed awaiting write zeroes IO") {
+            Ok(len)
+        } else {
+            Err(CoreError::WriteFailed {
+                offset,
+                len,
+            })
+        }
+    }
+
     /// read at given offset into the ['DmaBuf']
     pub async fn read_at(
         &self,
@@ -217,6 +256,32 @@ impl BdevHandle {
         Ok(now as u64)
     }
 
+    /// create a snapshot named after the given snapshot time, in seconds
+    /// since Unix epoch
+    pub async fn create_snapshot_at(
+        &self,
+        snapshot_time: u64,
+    ) -> Result<(), CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::CREATE_SNAPSHOT.into());
+        subsys::encode_snapshot_time(&mut cmd, snapshot_time);
+        self.nvme_admin(&cmd).await?;
+        Ok(())
+    }
+
+    /// revert to the snapshot named after the given snapshot time, in seconds
+    /// since Unix epoch
+    pub async fn revert_snapshot_at(
+        &self,
+        snapshot_time: u64,
+    ) -> Result<(), CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::REVERT_SNAPSHOT.into());
+        subsys::encode_snapshot_time(&mut cmd, snapshot_time);
+        self.nvme_admin(&cmd).await?;
+        Ok(())
+    }
+
     /// sends an NVMe Admin command with a custom opcode to all children
     pub async fn nvme_admin_custom(
         &self,
@@ -227,13 +292,36 @@ impl BdevHandle {
         self.nvme_admin(&cmd).await
     }
 
+    /// whether any of the `len` blocks from block `blk` is allocated on the
+    /// replica that this handle is connected to over nvmf, which only a
+    /// mayastor target is able to tell
+    pub async fn blocks_allocated(
+        &self,
+        blk: u64,
+        len: u32,
+    ) -> Result<bool, CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::BLOCKS_ALLOCATED.into());
+        subsys::encode_block_range(&mut cmd, blk, len);
+        Ok(self.nvme_admin_cdw0(&cmd).await? != 0)
+    }
+
     /// sends the specified NVMe Admin command to all children
     pub async fn nvme_admin(
         &self,
         nvme_cmd: &spdk_sys::spdk_nvme_cmd,
     ) -> Result<usize, CoreError> {
+        self.nvme_admin_cdw0(nvme_cmd).await.map(|_| 0)
+    }
+
+    /// sends the specified NVMe Admin command and returns the command
+    /// specific dword 0 of its completion
+    async fn nvme_admin_cdw0(
+        &self,
+        nvme_cmd: &spdk_sys::spdk_nvme_cmd,
+    ) -> Result<u32, CoreError> {
         trace!("Sending nvme_admin {}", nvme_cmd.opc());
-        let (s, r) = oneshot::channel::<bool>();
+        let (s, r) = oneshot::channel::<(bool, u32)>();
         // Use the spdk-sys variant spdk_bdev_nvme_admin_passthru that
         // assumes read commands
         let errno = unsafe {
@@ -243,7 +331,7 @@ impl BdevHandle {
                 &*nvme_cmd,
                 std::ptr::null_mut(),
                 0,
-                Some(Self::io_completion_cb),
+                Some(Self::nvme_admin_completion_cb),
                 cb_arg(s),
             )
         };
@@ -255,13 +343,32 @@ impl BdevHandle {
             });
         }
 
-        if r.await.expect("Failed awaiting NVMe Admin IO") {
-            Ok(0)
-        } else {
-            Err(CoreError::NvmeAdminFailed {
+        match r.await.expect("Failed awaiting NVMe Admin IO") {
+            (true, cdw0) => Ok(cdw0),
+            (false, _) => Err(CoreError::NvmeAdminFailed {
                 opcode: (*nvme_cmd).opc(),
-            })
+            }),
+        }
+    }
+
+    /// private completion callback of the NVMe Admin commands that sends back
+    /// the success status of the command along with dword 0 of its completion
+    extern "C" fn nvme_admin_completion_cb(
+        io: *mut spdk_bdev_io,
+        success: bool,
+        arg: *mut c_void,
+    ) {
+        let sender = unsafe {
+            Box::from_raw(arg as *const _ as *mut oneshot::Sender<(bool, u32)>)
+        };
+
+        let (mut cdw0, mut sct, mut sc) = (0, 0, 0);
+        unsafe {
+            spdk_bdev_io_get_nvme_status(io, &mut cdw0, &mut sct, &mut sc);
+            spdk_bdev_free_io(io);
         }
+
+        sender.send((success, cdw0)).expect("io completion error");
     }
 }
 
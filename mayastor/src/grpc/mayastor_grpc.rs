@@ -17,20 +17,26 @@ use crate::{
     bdev::{
         nexus::{instances, nexus_bdev},
         nexus_create,
+        NexusQos,
         Reason,
     },
     grpc::{
         nexus_grpc::{
             nexus_add_child,
             nexus_destroy,
+            nexus_listener,
             nexus_lookup,
+            nvme_ana_state,
+            read_policy,
             uuid_to_name,
+            watch_events,
         },
         pool_grpc,
         sync_config,
         GrpcResult,
     },
     host::{blk_device, resource},
+    rebuild::RebuildJob,
 };
 
 #[derive(Debug)]
@@ -41,6 +47,9 @@ pub struct MayastorSvc;
 
 #[tonic::async_trait]
 impl mayastor_server::Mayastor for MayastorSvc {
+    type WatchEventsStream =
+        tokio::sync::mpsc::Receiver<Result<NexusEvent, Status>>;
+
     #[instrument(level = "debug", err)]
 
     async fn create_pool(
@@ -91,6 +100,15 @@ impl mayastor_server::Mayastor for MayastorSvc {
         sync_config(pool_grpc::destroy_replica(args)).await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn resize_replica(
+        &self,
+        request: Request<ResizeReplicaRequest>,
+    ) -> GrpcResult<Replica> {
+        let args = request.into_inner();
+        sync_config(pool_grpc::resize_replica(args)).await
+    }
+
     #[instrument(level = "debug", err)]
     async fn list_replicas(
         &self,
@@ -126,11 +144,15 @@ impl mayastor_server::Mayastor for MayastorSvc {
             let args = request.into_inner();
             let uuid = args.uuid.clone();
             let name = uuid_to_name(&args.uuid)?;
+            let policy = read_policy(args.read_policy)?;
+            let qos = args.qos.clone().map(NexusQos::from).unwrap_or_default();
             locally! { async move {
                 nexus_create(&name, args.size, Some(&args.uuid), &args.children).await
             }}
             ;
             let nexus = nexus_lookup(&uuid)?;
+            nexus.set_read_policy(policy);
+            nexus.set_qos(qos);
             info!("Created nexus {}", uuid);
             Ok(Response::new(nexus.to_grpc()))
         }).await
@@ -169,6 +191,26 @@ impl mayastor_server::Mayastor for MayastorSvc {
         Ok(Response::new(reply))
     }
 
+    #[instrument(level = "debug", err)]
+    async fn stat_nexus(
+        &self,
+        request: Request<StatNexusRequest>,
+    ) -> GrpcResult<StatNexusReply> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        let nexus_list = if args.uuid.is_empty() {
+            instances()
+                .iter()
+                .map(|n| n.stats_to_grpc())
+                .collect::<Vec<_>>()
+        } else {
+            vec![nexus_lookup(&args.uuid)?.stats_to_grpc()]
+        };
+        Ok(Response::new(StatNexusReply {
+            nexus_list,
+        }))
+    }
+
     #[instrument(level = "debug", err)]
     async fn add_child_nexus(
         &self,
@@ -227,6 +269,65 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn set_nexus_read_policy(
+        &self,
+        request: Request<SetNexusReadPolicyRequest>,
+    ) -> GrpcResult<Null> {
+        sync_config(async {
+            let args = request.into_inner();
+            trace!("{:?}", args);
+            let policy = read_policy(args.read_policy)?;
+            nexus_lookup(&args.uuid)?.set_read_policy(policy);
+            Ok(Response::new(Null {}))
+        })
+        .await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn set_nexus_qos(
+        &self,
+        request: Request<SetNexusQosRequest>,
+    ) -> GrpcResult<Null> {
+        sync_config(async {
+            let args = request.into_inner();
+            trace!("{:?}", args);
+            let qos = args.qos.map(NexusQos::from).unwrap_or_default();
+            nexus_lookup(&args.uuid)?.set_qos(qos);
+            Ok(Response::new(Null {}))
+        })
+        .await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn resize_nexus(
+        &self,
+        request: Request<ResizeNexusRequest>,
+    ) -> GrpcResult<Null> {
+        sync_config(async {
+            let args = request.into_inner();
+            trace!("{:?}", args);
+            let uuid = args.uuid.clone();
+            debug!("Resizing nexus {} to {} bytes", uuid, args.size);
+            locally! { async move {
+                nexus_lookup(&args.uuid)?.resize(args.size).await
+            }};
+            info!("Resized nexus {}", uuid);
+            Ok(Response::new(Null {}))
+        })
+        .await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn watch_events(
+        &self,
+        request: Request<WatchEventsRequest>,
+    ) -> GrpcResult<Self::WatchEventsStream> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        Ok(Response::new(watch_events(args.epoch, args.from_seq)?))
+    }
+
     #[instrument(level = "debug", err)]
     async fn publish_nexus(
         &self,
@@ -260,7 +361,9 @@ impl mayastor_server::Mayastor for MayastorSvc {
             };
 
             let device_uri = locally! { async move {
-                nexus_lookup(&args.uuid)?.share(share_protocol, key).await
+                let nexus = nexus_lookup(&args.uuid)?;
+                nexus.set_allowed_hosts(args.allowed_hosts).await?;
+                nexus.share(share_protocol, key).await
             }};
 
             info!("Published nexus {} under {}", uuid, device_uri);
@@ -290,6 +393,115 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn get_nvme_ana_state(
+        &self,
+        request: Request<GetNvmeAnaStateRequest>,
+    ) -> GrpcResult<GetNvmeAnaStateReply> {
+        let args = request.into_inner();
+        let uuid = args.uuid.clone();
+        debug!("Getting NVMe ANA state for nexus {} ...", uuid);
+
+        let listener = nexus_listener(&args.address, args.port)?;
+        let ana_state = locally! { async move {
+            nexus_lookup(&args.uuid)?.get_ana_state(
+                listener.as_ref().map(|(a, p)| (a.as_str(), *p))
+            )
+        }};
+
+        info!("Got nexus {} NVMe ANA state {:?}", uuid, ana_state);
+        Ok(Response::new(GetNvmeAnaStateReply {
+            ana_state: ana_state as i32,
+        }))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn set_nvme_ana_state(
+        &self,
+        request: Request<SetNvmeAnaStateRequest>,
+    ) -> GrpcResult<Null> {
+        let args = request.into_inner();
+        let uuid = args.uuid.clone();
+        debug!("Setting NVMe ANA state for nexus {} ...", uuid);
+
+        let ana_state = nvme_ana_state(args.ana_state)?;
+        let listener = nexus_listener(&args.address, args.port)?;
+        locally! { async move {
+            nexus_lookup(&args.uuid)?.set_ana_state(
+                ana_state,
+                listener.as_ref().map(|(a, p)| (a.as_str(), *p)),
+            ).await
+        }};
+
+        info!("Set nexus {} NVMe ANA state {:?}", uuid, ana_state);
+        Ok(Response::new(Null {}))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn add_nexus_listener(
+        &self,
+        request: Request<AddNexusListenerRequest>,
+    ) -> GrpcResult<NexusListener> {
+        let args = request.into_inner();
+        let uuid = args.uuid.clone();
+        debug!("Adding listener to nexus {} ...", uuid);
+
+        let ana_state = nvme_ana_state(args.ana_state)?;
+        let (address, port) = nexus_listener(&args.address, args.port)?
+            .ok_or_else(|| nexus_bdev::Error::InvalidListenerAddress {
+                address: format!("{}:{}", args.address, args.port),
+            })?;
+        let listener = locally! { async move {
+            nexus_lookup(&args.uuid)?
+                .add_listener(&address, port, ana_state)
+                .await
+        }};
+
+        info!("Added listener {} to nexus {}", listener.uri, uuid);
+        Ok(Response::new(listener))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn remove_nexus_listener(
+        &self,
+        request: Request<RemoveNexusListenerRequest>,
+    ) -> GrpcResult<Null> {
+        let args = request.into_inner();
+        let uuid = args.uuid.clone();
+        debug!("Removing listener from nexus {} ...", uuid);
+
+        let (address, port) = nexus_listener(&args.address, args.port)?
+            .ok_or_else(|| nexus_bdev::Error::InvalidListenerAddress {
+                address: format!("{}:{}", args.address, args.port),
+            })?;
+        locally! { async move {
+            nexus_lookup(&args.uuid)?.remove_listener(&address, port).await
+        }};
+
+        info!(
+            "Removed listener {}:{} from nexus {}",
+            args.address, args.port, uuid
+        );
+        Ok(Response::new(Null {}))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn list_nexus_listeners(
+        &self,
+        request: Request<ListNexusListenersRequest>,
+    ) -> GrpcResult<ListNexusListenersReply> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+
+        let listeners = locally! { async move {
+            nexus_lookup(&args.uuid)?.listeners()
+        }};
+
+        Ok(Response::new(ListNexusListenersReply {
+            listeners,
+        }))
+    }
+
     #[instrument(level = "debug", err)]
     async fn child_operation(
         &self,
@@ -373,6 +585,23 @@ impl mayastor_server::Mayastor for MayastorSvc {
         Ok(Response::new(Null {}))
     }
 
+    #[instrument(level = "debug", err)]
+    async fn set_rebuild_rate(
+        &self,
+        request: Request<SetRebuildRateRequest>,
+    ) -> GrpcResult<Null> {
+        let msg = request.into_inner();
+        if msg.uuid.is_empty() {
+            RebuildJob::set_global_rate(msg.rate);
+        } else {
+            locally! { async move {
+              nexus_lookup(&msg.uuid)?.set_rebuild_rate(&msg.uri, msg.rate).await
+            }};
+        }
+
+        Ok(Response::new(Null {}))
+    }
+
     #[instrument(level = "debug", err)]
     async fn get_rebuild_state(
         &self,
@@ -397,6 +626,51 @@ impl mayastor_server::Mayastor for MayastorSvc {
         }}))
     }
 
+    #[instrument(level = "debug", err)]
+    async fn start_scrub(
+        &self,
+        request: Request<StartScrubRequest>,
+    ) -> GrpcResult<Null> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        locally! { async move {
+            let authority = if args.authority.is_empty() {
+                None
+            } else {
+                Some(args.authority.as_str())
+            };
+            nexus_lookup(&args.uuid)?.start_scrub(authority).await.map(|_|{})
+        }};
+
+        Ok(Response::new(Null {}))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn stop_scrub(
+        &self,
+        request: Request<StopScrubRequest>,
+    ) -> GrpcResult<Null> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        locally! { async move {
+            nexus_lookup(&args.uuid)?.stop_scrub().await
+        }};
+
+        Ok(Response::new(Null {}))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn get_scrub_progress(
+        &self,
+        request: Request<ScrubProgressRequest>,
+    ) -> GrpcResult<ScrubProgressReply> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        Ok(Response::new(locally! { async move {
+            nexus_lookup(&args.uuid)?.get_scrub_progress()
+        }}))
+    }
+
     #[instrument(level = "debug", err)]
     async fn create_snapshot(
         &self,
@@ -416,6 +690,53 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn revert_nexus_to_snapshot(
+        &self,
+        request: Request<RevertNexusToSnapshotRequest>,
+    ) -> GrpcResult<Null> {
+        sync_config(async {
+            let args = request.into_inner();
+            let uuid = args.uuid.clone();
+            debug!(
+                "Reverting nexus {} to snapshot {} ...",
+                uuid, args.timestamp
+            );
+            locally! { async move {
+                nexus_lookup(&args.uuid)?.revert_to_snapshot(args.timestamp).await
+            }};
+            info!("Reverted nexus {}", uuid);
+            Ok(Response::new(Null {}))
+        })
+        .await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn list_snapshots(
+        &self,
+        _request: Request<Null>,
+    ) -> GrpcResult<ListSnapshotsReply> {
+        pool_grpc::list_snapshots()
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn destroy_snapshot(
+        &self,
+        request: Request<DestroySnapshotRequest>,
+    ) -> GrpcResult<Null> {
+        let args = request.into_inner();
+        sync_config(pool_grpc::destroy_snapshot(args)).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn create_clone(
+        &self,
+        request: Request<CreateCloneRequest>,
+    ) -> GrpcResult<CreateCloneReply> {
+        let args = request.into_inner();
+        sync_config(pool_grpc::create_clone(args)).await
+    }
+
     #[instrument(level = "debug", err)]
     async fn list_block_devices(
         &self,
@@ -5,6 +5,7 @@ use std::error::Error;
 use futures::Future;
 use tonic::{Response, Status};
 
+pub(crate) use nexus_grpc::name_to_uuid;
 pub use server::MayastorGrpcServer;
 
 use crate::{
@@ -1,14 +1,23 @@
 //! Helpers related to nexus grpc methods.
 
 use ::rpc::mayastor as rpc;
-use std::convert::From;
+use std::{
+    convert::{From, TryFrom},
+    sync::atomic::Ordering,
+    time::UNIX_EPOCH,
+};
+use tokio::sync::{broadcast::RecvError, mpsc};
+use tonic::Status;
 use uuid::Uuid;
 
 use crate::{
     bdev::nexus::{
         instances,
-        nexus_bdev::{Error, Nexus, NexusStatus},
+        nexus_bdev::{Error, Nexus, NexusStatus, ReadPolicy},
         nexus_child::{ChildState, NexusChild, Reason},
+        nexus_events::{self, NexusEvent, NexusEventType},
+        nexus_qos::{NexusQos, QosLimit},
+        nexus_stats::{IoStats, LatencyHistogram},
     },
     rebuild::RebuildJob,
 };
@@ -39,6 +48,197 @@ impl From<NexusStatus> for rpc::NexusState {
     }
 }
 
+impl From<Reason> for rpc::ChildStateReason {
+    fn from(reason: Reason) -> Self {
+        match reason {
+            Reason::Unknown => rpc::ChildStateReason::ReasonNone,
+            Reason::OutOfSync => rpc::ChildStateReason::ReasonOutOfSync,
+            Reason::CantOpen => rpc::ChildStateReason::ReasonCantOpen,
+            Reason::RebuildFailed => rpc::ChildStateReason::ReasonRebuildFailed,
+            Reason::IoError => rpc::ChildStateReason::ReasonIoError,
+            Reason::Rpc => rpc::ChildStateReason::ReasonRpc,
+            Reason::SnapshotMissing => {
+                rpc::ChildStateReason::ReasonSnapshotMissing
+            }
+        }
+    }
+}
+
+impl From<&NexusEvent> for rpc::NexusEvent {
+    fn from(event: &NexusEvent) -> Self {
+        let mut reply = rpc::NexusEvent {
+            epoch: event.epoch,
+            seq: event.seq,
+            timestamp_ms: event
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            uuid: name_to_uuid(&event.nexus).to_string(),
+            uri: event.child.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+        match event.event {
+            NexusEventType::ChildState {
+                old,
+                new,
+            } => {
+                reply.event = rpc::NexusEventType::EventChildState as i32;
+                reply.old_child_state = rpc::ChildState::from(old) as i32;
+                reply.new_child_state = rpc::ChildState::from(new) as i32;
+                if let ChildState::Faulted(reason) = new {
+                    reply.reason = rpc::ChildStateReason::from(reason) as i32;
+                }
+            }
+            NexusEventType::NexusStatus {
+                old,
+                new,
+            } => {
+                reply.event = rpc::NexusEventType::EventNexusState as i32;
+                reply.old_nexus_state = rpc::NexusState::from(old) as i32;
+                reply.new_nexus_state = rpc::NexusState::from(new) as i32;
+            }
+            NexusEventType::RebuildStarted => {
+                reply.event = rpc::NexusEventType::EventRebuildStarted as i32;
+            }
+            NexusEventType::RebuildCompleted => {
+                reply.event = rpc::NexusEventType::EventRebuildCompleted as i32;
+            }
+            NexusEventType::RebuildStopped => {
+                reply.event = rpc::NexusEventType::EventRebuildStopped as i32;
+            }
+            NexusEventType::RebuildFailed => {
+                reply.event = rpc::NexusEventType::EventRebuildFailed as i32;
+            }
+        }
+        reply
+    }
+}
+
+impl From<ReadPolicy> for rpc::NexusReadPolicy {
+    fn from(policy: ReadPolicy) -> Self {
+        match policy {
+            ReadPolicy::RoundRobin => rpc::NexusReadPolicy::ReadRoundRobin,
+            ReadPolicy::LeastQueueDepth => {
+                rpc::NexusReadPolicy::ReadLeastQueueDepth
+            }
+            ReadPolicy::PreferLocal => rpc::NexusReadPolicy::ReadPreferLocal,
+        }
+    }
+}
+
+/// Convert the read policy of a grpc request into the nexus read policy.
+/// Return error if the value is not a known policy.
+pub fn read_policy(value: i32) -> Result<ReadPolicy, Error> {
+    match rpc::NexusReadPolicy::from_i32(value) {
+        Some(rpc::NexusReadPolicy::ReadRoundRobin) => {
+            Ok(ReadPolicy::RoundRobin)
+        }
+        Some(rpc::NexusReadPolicy::ReadLeastQueueDepth) => {
+            Ok(ReadPolicy::LeastQueueDepth)
+        }
+        Some(rpc::NexusReadPolicy::ReadPreferLocal) => {
+            Ok(ReadPolicy::PreferLocal)
+        }
+        None => Err(Error::InvalidReadPolicy {
+            value,
+        }),
+    }
+}
+
+/// Convert the ANA state of a grpc request into the NVMe ANA state.
+/// Return error if the value is not a known state.
+pub fn nvme_ana_state(value: i32) -> Result<rpc::NvmeAnaState, Error> {
+    rpc::NvmeAnaState::from_i32(value).ok_or(Error::InvalidAnaState {
+        value,
+    })
+}
+
+/// Convert the listener of a grpc request into the address and port of a
+/// nexus listener, an empty address does not select any listener.
+pub fn nexus_listener(
+    address: &str,
+    port: u32,
+) -> Result<Option<(String, u16)>, Error> {
+    if address.is_empty() {
+        return Ok(None);
+    }
+    match u16::try_from(port) {
+        Ok(port) => Ok(Some((address.to_string(), port))),
+        Err(_) => Err(Error::InvalidListenerAddress {
+            address: format!("{}:{}", address, port),
+        }),
+    }
+}
+
+impl From<QosLimit> for rpc::QosLimit {
+    fn from(limit: QosLimit) -> Self {
+        rpc::QosLimit {
+            rate: limit.rate,
+            burst: limit.burst,
+        }
+    }
+}
+
+impl From<rpc::QosLimit> for QosLimit {
+    fn from(limit: rpc::QosLimit) -> Self {
+        QosLimit {
+            rate: limit.rate,
+            burst: limit.burst,
+        }
+    }
+}
+
+impl From<NexusQos> for rpc::NexusQos {
+    fn from(qos: NexusQos) -> Self {
+        rpc::NexusQos {
+            read_iops: Some(qos.read_iops.into()),
+            write_iops: Some(qos.write_iops.into()),
+            read_mbps: Some(qos.read_mbps.into()),
+            write_mbps: Some(qos.write_mbps.into()),
+        }
+    }
+}
+
+/// Convert the QoS limits of a grpc request into the nexus limits, missing
+/// limits are not enforced.
+impl From<rpc::NexusQos> for NexusQos {
+    fn from(qos: rpc::NexusQos) -> Self {
+        NexusQos {
+            read_iops: qos.read_iops.map(QosLimit::from).unwrap_or_default(),
+            write_iops: qos.write_iops.map(QosLimit::from).unwrap_or_default(),
+            read_mbps: qos.read_mbps.map(QosLimit::from).unwrap_or_default(),
+            write_mbps: qos.write_mbps.map(QosLimit::from).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&LatencyHistogram> for rpc::LatencyStats {
+    fn from(histogram: &LatencyHistogram) -> Self {
+        rpc::LatencyStats {
+            p50_us: histogram.percentile(0.5),
+            p99_us: histogram.percentile(0.99),
+            p999_us: histogram.percentile(0.999),
+        }
+    }
+}
+
+impl From<&IoStats> for rpc::IoStats {
+    fn from(stats: &IoStats) -> Self {
+        rpc::IoStats {
+            num_read_ops: stats.num_read_ops.load(Ordering::Relaxed),
+            num_write_ops: stats.num_write_ops.load(Ordering::Relaxed),
+            num_unmap_ops: stats.num_unmap_ops.load(Ordering::Relaxed),
+            bytes_read: stats.bytes_read.load(Ordering::Relaxed),
+            bytes_written: stats.bytes_written.load(Ordering::Relaxed),
+            bytes_unmapped: stats.bytes_unmapped.load(Ordering::Relaxed),
+            num_retried_ops: stats.num_retried_ops.load(Ordering::Relaxed),
+            read_latency: Some((&stats.read_latency).into()),
+            write_latency: Some((&stats.write_latency).into()),
+        }
+    }
+}
+
 impl NexusChild {
     /// Convert nexus child object to grpc representation.
     ///
@@ -51,6 +251,14 @@ impl NexusChild {
             rebuild_progress: self.get_rebuild_progress(),
         }
     }
+
+    /// Convert the IO stats of the child to grpc representation.
+    pub fn stats_to_grpc(&self) -> rpc::ChildStats {
+        rpc::ChildStats {
+            uri: self.name.clone(),
+            stats: Some((&self.stats).into()),
+        }
+    }
 }
 
 impl Nexus {
@@ -70,6 +278,26 @@ impl Nexus {
                 .map(|ch| ch.to_grpc())
                 .collect::<Vec<_>>(),
             rebuilds: RebuildJob::count() as u32,
+            read_policy: rpc::NexusReadPolicy::from(self.read_policy) as i32,
+            qos: Some(self.qos.into()),
+            throttled_reads: self.qos_limiter.throttled_reads(),
+            throttled_writes: self.qos_limiter.throttled_writes(),
+        }
+    }
+
+    /// Convert the IO stats of the nexus and its children to grpc
+    /// representation.
+    pub fn stats_to_grpc(&self) -> rpc::NexusStats {
+        rpc::NexusStats {
+            uuid: name_to_uuid(&self.name).to_string(),
+            stats: Some((&self.stats).into()),
+            children: self
+                .children
+                .iter()
+                .map(|ch| ch.stats_to_grpc())
+                .collect::<Vec<_>>(),
+            throttled_reads: self.qos_limiter.throttled_reads(),
+            throttled_writes: self.qos_limiter.throttled_writes(),
         }
     }
 }
@@ -79,7 +307,7 @@ impl Nexus {
 /// This function never fails which means that if there is a nexus with
 /// unconventional name that likely means it was not created using nexus
 /// rpc api, we return the whole name without modifications as it is.
-fn name_to_uuid(name: &str) -> &str {
+pub(crate) fn name_to_uuid(name: &str) -> &str {
     if let Some(stripped) = name.strip_prefix("nexus-") {
         stripped
     } else {
@@ -112,6 +340,48 @@ pub fn nexus_lookup(uuid: &str) -> Result<&mut Nexus, Error> {
     }
 }
 
+/// Stream the nexus events from the given sequence number of the given epoch
+/// onwards. A watcher that cannot keep up is sent an OUT_OF_RANGE error
+/// telling it where to resume from.
+pub fn watch_events(
+    epoch: u64,
+    from_seq: u64,
+) -> Result<mpsc::Receiver<Result<rpc::NexusEvent, Status>>, Error> {
+    let mut watch = nexus_events::watch(epoch, from_seq)?;
+    let (mut sender, receiver) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let mut next_seq = watch.from_seq;
+        for event in watch.replay {
+            next_seq = event.seq + 1;
+            if sender.send(Ok((&event).into())).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            let reply = match watch.receiver.recv().await {
+                Ok(event) => {
+                    next_seq = event.seq + 1;
+                    Ok((&event).into())
+                }
+                Err(RecvError::Lagged(_)) => Err(Status::out_of_range(format!(
+                    "watcher fell behind, resume from sequence number {} of epoch {}",
+                    next_seq, watch.epoch
+                ))),
+                Err(RecvError::Closed) => return,
+            };
+            let failed = reply.is_err();
+            // the watcher has gone away
+            if sender.send(reply).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
 /// Add child to nexus. Normally this would have been part of grpc method
 /// implementation, however it is not allowed to use '?' in `locally` macro.
 /// So we implement it as a separate function.
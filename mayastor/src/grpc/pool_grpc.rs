@@ -8,19 +8,25 @@ use tonic::{Response, Status};
 use tracing::instrument;
 
 use rpc::mayastor::{
+    CreateCloneReply,
+    CreateCloneRequest,
     CreatePoolRequest,
     CreateReplicaRequest,
     DestroyPoolRequest,
     DestroyReplicaRequest,
+    DestroySnapshotRequest,
     ListPoolsReply,
     ListReplicasReply,
+    ListSnapshotsReply,
     Null,
     Pool,
     PoolState,
     Replica,
     ReplicaStats,
+    ResizeReplicaRequest,
     ShareReplicaReply,
     ShareReplicaRequest,
+    Snapshot,
     StatReplicasReply,
     Stats,
 };
@@ -44,6 +50,18 @@ impl From<LvsError> for Status {
             Error::Invalid {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            Error::RepShrinkTooSmall {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::RepShrinkInUse {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::NotASnapshot {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::RepResizeNoSpace {
+                ..
+            } => Status::resource_exhausted(e.to_string()),
             _ => Status::internal(e.to_string()),
         }
     }
@@ -71,6 +89,40 @@ impl From<Lvs> for Pool {
     }
 }
 
+impl From<Lvol> for Snapshot {
+    fn from(l: Lvol) -> Self {
+        let name = l.name();
+        let (replica, timestamp) = Lvol::parse_snapshot_name(&name)
+            .map_or((String::new(), 0), |(r, t)| (r.to_string(), t));
+        Self {
+            name: l.name(),
+            pool: l.pool(),
+            replica,
+            timestamp,
+            size: l.size(),
+            used: l.used(),
+        }
+    }
+}
+
+impl From<Lvol> for CreateCloneReply {
+    fn from(l: Lvol) -> Self {
+        let (parent, timestamp) = l
+            .parent_snapshot()
+            .and_then(|s| {
+                Lvol::parse_snapshot_name(&s.name())
+                    .map(|(r, t)| (r.to_string(), t))
+            })
+            .unwrap_or_default();
+        Self {
+            used: l.used(),
+            replica: Some(l.into()),
+            parent,
+            timestamp,
+        }
+    }
+}
+
 impl From<BdevStats> for Stats {
     fn from(b: BdevStats) -> Self {
         Self {
@@ -186,6 +238,116 @@ pub async fn destroy_replica(args: DestroyReplicaRequest) -> GrpcResult<Null> {
     })
 }
 
+/// grow or shrink the replica to the given size, returning the replica with
+/// its new size
+#[instrument(level = "debug", err)]
+pub async fn resize_replica(args: ResizeReplicaRequest) -> GrpcResult<Replica> {
+    rpc_call(async move {
+        match Bdev::lookup_by_name(&args.uuid) {
+            Some(b) => {
+                let lvol = Lvol::try_from(b)?;
+                lvol.resize(args.size).await.map(|_| lvol)
+            }
+            None => Err(LvsError::InvalidBdev {
+                source: NexusBdevError::BdevNotFound {
+                    name: args.uuid.clone(),
+                },
+                name: args.uuid,
+            }),
+        }
+    })
+}
+
+/// list all the snapshots
+#[instrument(level = "debug", err)]
+pub fn list_snapshots() -> GrpcResult<ListSnapshotsReply> {
+    let mut snapshots = Vec::new();
+    if let Some(bdev) = Bdev::bdev_first() {
+        snapshots = bdev
+            .into_iter()
+            .filter(|b| b.driver() == "lvol")
+            .map(|b| Lvol::try_from(b).unwrap())
+            .filter(|l| l.is_snapshot())
+            .map(Snapshot::from)
+            .collect::<Vec<_>>();
+    }
+
+    Ok(Response::new(ListSnapshotsReply {
+        snapshots,
+    }))
+}
+
+/// destroy the snapshot with the given name, returning OK if the snapshot
+/// was not found
+#[instrument(level = "debug", err)]
+pub async fn destroy_snapshot(
+    args: DestroySnapshotRequest,
+) -> GrpcResult<Null> {
+    rpc_call(async move {
+        match Bdev::lookup_by_name(&args.name) {
+            Some(b) => {
+                let lvol = Lvol::try_from(b)?;
+                if !lvol.is_snapshot() {
+                    return Err(LvsError::NotASnapshot {
+                        source: Errno::EINVAL,
+                        name: args.name,
+                    });
+                }
+                lvol.destroy().await.map(|_r| Null {})
+            }
+            None => Ok(Null {}),
+        }
+    })
+}
+
+/// create a replica from the given snapshot returns an OK if the replica
+/// already exists. If the replica fails to share, it will be destroyed prior
+/// to returning an error.
+#[instrument(level = "debug", err)]
+pub async fn create_clone(
+    args: CreateCloneRequest,
+) -> GrpcResult<CreateCloneReply> {
+    if let Some(b) = Bdev::lookup_by_name(&args.uuid) {
+        let lvol = Lvol::try_from(b)?;
+        return Ok(Response::new(CreateCloneReply::from(lvol)));
+    }
+
+    if !matches!(Protocol::from(args.share), Protocol::Off | Protocol::Nvmf) {
+        return Err(Status::invalid_argument(format!(
+            "invalid protocol {}",
+            args.share
+        )));
+    }
+
+    rpc_call(async move {
+        let snapshot = match Bdev::lookup_by_name(&args.snapshot) {
+            Some(b) => Lvol::try_from(b)?,
+            None => {
+                return Err(LvsError::InvalidBdev {
+                    source: NexusBdevError::BdevNotFound {
+                        name: args.snapshot.clone(),
+                    },
+                    name: args.snapshot,
+                })
+            }
+        };
+
+        let lvol = snapshot.create_clone(&args.uuid).await?;
+        if Protocol::from(args.share) == Protocol::Nvmf {
+            if let Err(e) = lvol.share_nvmf().await {
+                debug!(
+                    "failed to share created clone {}: {} .. destroying",
+                    lvol,
+                    e.to_string()
+                );
+                let _ = lvol.destroy().await;
+                return Err(e);
+            }
+        }
+        Ok(lvol)
+    })
+}
+
 /// list all the replicas
 #[instrument(level = "debug", err)]
 pub fn list_replicas() -> GrpcResult<ListReplicasReply> {
@@ -204,7 +366,7 @@ pub fn list_replicas() -> GrpcResult<ListReplicasReply> {
 }
 
 /// shares the replica over nvmf -- replicas are always shared over nvmf if
-/// already shared returns OK.
+/// already shared returns OK. The allowed hosts are updated either way.
 ///
 /// There is no unshare RPC in mayastor_svc
 #[instrument(level = "debug", err)]
@@ -215,6 +377,10 @@ pub async fn share_replica(
         if let Some(b) = Bdev::lookup_by_name(&args.uuid) {
             let lvol = Lvol::try_from(b)?;
 
+            if Protocol::from(args.share) == Protocol::Nvmf {
+                lvol.set_allowed_hosts(&args.allowed_hosts).await?;
+            }
+
             // if we are already shared return OK
             if lvol.shared() == Some(Protocol::from(args.share)) {
                 return Ok(ShareReplicaReply {
@@ -18,6 +18,7 @@ pub mod host;
 pub mod jsonrpc;
 pub mod logger;
 pub mod lvs;
+pub mod metrics;
 pub mod nexus_uri;
 pub mod pool;
 pub mod rebuild;
@@ -38,6 +38,86 @@ pub enum Error {
     #[snafu(display("failed to destroy lvol {}", name))]
     RepDestroy { source: Errno, name: String },
 
+    #[snafu(display("failed to resize lvol {}", name))]
+    RepResize { source: Errno, name: String },
+
+    #[snafu(display(
+        "not enough space in pool {} to resize lvol {} to {} bytes",
+        pool,
+        name,
+        size
+    ))]
+    RepResizeNoSpace {
+        source: Errno,
+        name: String,
+        pool: String,
+        size: u64,
+    },
+
+    #[snafu(display(
+        "lvol {} cannot be shrunk to {} bytes, the labels and data of a nexus need at least {} bytes",
+        name,
+        size,
+        minimum
+    ))]
+    RepShrinkTooSmall {
+        source: Errno,
+        name: String,
+        size: u64,
+        minimum: u64,
+    },
+
+    #[snafu(display(
+        "lvol {} cannot be shrunk to {} bytes, nexus {} needs at least {} bytes of it",
+        name,
+        size,
+        nexus,
+        minimum
+    ))]
+    RepShrinkInUse {
+        source: Errno,
+        name: String,
+        size: u64,
+        nexus: String,
+        minimum: u64,
+    },
+
+    #[snafu(display(
+        "failed to create snapshot {} of lvol {}",
+        snapshot,
+        name
+    ))]
+    RepSnapshot {
+        source: Errno,
+        snapshot: String,
+        name: String,
+    },
+
+    #[snafu(display(
+        "failed to create clone {} of snapshot {}",
+        name,
+        snapshot
+    ))]
+    RepClone {
+        source: Errno,
+        snapshot: String,
+        name: String,
+    },
+
+    #[snafu(display(
+        "failed to revert lvol {} to snapshot {}",
+        name,
+        snapshot
+    ))]
+    RepRevert {
+        source: Errno,
+        snapshot: String,
+        name: String,
+    },
+
+    #[snafu(display("lvol {} is not a snapshot", name))]
+    NotASnapshot { source: Errno, name: String },
+
     #[snafu(display("bdev {} is not a lvol", name))]
     NotALvol { source: Errno, name: String },
 
@@ -13,19 +13,26 @@ use pin_utils::core_reexport::fmt::Formatter;
 use tracing::instrument;
 
 use spdk_sys::{
+    blob_num_allocated_clusters,
+    blob_range_allocated,
+    blob_revert,
+    spdk_blob_get_id,
+    spdk_blob_get_parent_snapshot,
     spdk_blob_get_xattr_value,
     spdk_blob_is_read_only,
     spdk_blob_is_snapshot,
     spdk_blob_set_xattr,
     spdk_blob_sync_md,
     spdk_lvol,
+    vbdev_lvol_create_clone,
     vbdev_lvol_create_snapshot,
     vbdev_lvol_destroy,
     vbdev_lvol_get_from_bdev,
+    vbdev_lvol_resize,
 };
 
 use crate::{
-    bdev::nexus::nexus_bdev::Nexus,
+    bdev::nexus::{instances, nexus_bdev::Nexus},
     core::{Bdev, CoreError, Mthread, Protocol, Share},
     ffihelper::{
         cb_arg,
@@ -41,22 +48,26 @@ use crate::{
 
 /// properties we allow for being set on the lvol, this information is stored on
 /// disk
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub enum PropValue {
     Shared(bool),
+    /// host NQNs allowed to connect when shared, empty allows any host
+    AllowedHosts(Vec<String>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[non_exhaustive]
 pub enum PropName {
     Shared,
+    AllowedHosts,
 }
 
-impl From<PropValue> for PropName {
-    fn from(v: PropValue) -> Self {
+impl From<&PropValue> for PropName {
+    fn from(v: &PropValue) -> Self {
         match v {
             PropValue::Shared(_) => Self::Shared,
+            PropValue::AllowedHosts(_) => Self::AllowedHosts,
         }
     }
 }
@@ -65,6 +76,7 @@ impl Display for PropName {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let name = match self {
             PropName::Shared => "shared",
+            PropName::AllowedHosts => "allowed_hosts",
         };
         write!(f, "{}", name)
     }
@@ -120,15 +132,19 @@ impl Share for Lvol {
         })
     }
 
-    /// share the lvol as a nvmf target
+    /// share the lvol as a nvmf target, accessible by the hosts stored in
+    /// its allowed hosts property
     #[instrument(level = "debug", err)]
     async fn share_nvmf(&self) -> Result<Self::Output, Self::Error> {
-        let share = self.as_bdev().share_nvmf().await.map_err(|e| {
-            Error::LvolShare {
+        let allowed_hosts = self.allowed_hosts().await;
+        let share = self
+            .as_bdev()
+            .share_nvmf_with_hosts(&allowed_hosts)
+            .await
+            .map_err(|e| Error::LvolShare {
                 source: e,
                 name: self.name(),
-            }
-        })?;
+            })?;
 
         self.set(PropValue::Shared(true)).await?;
         info!("shared {}", self);
@@ -208,11 +224,12 @@ impl Lvol {
 
     /// returns the pool of the lvol
     pub fn pool(&self) -> String {
-        unsafe {
-            Lvs(NonNull::new_unchecked(self.0.as_ref().lvol_store))
-                .name()
-                .to_string()
-        }
+        self.lvs().name().to_string()
+    }
+
+    /// returns the store the lvol is allocated from
+    pub fn lvs(&self) -> Lvs {
+        unsafe { Lvs(NonNull::new_unchecked(self.0.as_ref().lvol_store)) }
     }
 
     /// returns a boolean indicating if the lvol is thin provisioned
@@ -230,6 +247,39 @@ impl Lvol {
         unsafe { spdk_blob_is_snapshot(self.0.as_ref().blob) }
     }
 
+    /// returns a boolean indicating if any of the clusters backing the given
+    /// blocks is allocated, unallocated blocks read as zeroes unless the lvol
+    /// is a clone
+    pub fn is_allocated(&self, offset_blocks: u64, num_blocks: u64) -> bool {
+        unsafe {
+            blob_range_allocated(
+                self.0.as_ref().blob,
+                offset_blocks,
+                num_blocks,
+            )
+        }
+    }
+
+    /// returns the number of bytes allocated to the lvol in the pool
+    pub fn used(&self) -> u64 {
+        let clusters =
+            unsafe { blob_num_allocated_clusters(self.0.as_ref().blob) };
+        clusters * self.lvs().cluster_size()
+    }
+
+    /// returns the snapshot the lvol is a clone of, if any
+    pub fn parent_snapshot(&self) -> Option<Lvol> {
+        let parent = unsafe {
+            spdk_blob_get_parent_snapshot(
+                self.lvs().0.as_ref().blobstore,
+                spdk_blob_get_id(self.0.as_ref().blob),
+            )
+        };
+        self.lvs()
+            .lvols()?
+            .find(|l| unsafe { spdk_blob_get_id(l.0.as_ref().blob) } == parent)
+    }
+
     /// destroy the lvol
     #[instrument(level = "debug", err)]
     pub async fn destroy(self) -> Result<String, Error> {
@@ -260,6 +310,118 @@ impl Lvol {
         Ok(name)
     }
 
+    /// the number of bytes the pool allocates when the lvol is grown to the
+    /// given size, thin lvols allocate their clusters on first write instead
+    pub fn grow_allocation(&self, size: u64) -> u64 {
+        let current = self.size();
+        if self.is_thin() || size <= current {
+            return 0;
+        }
+        let cluster_size = self.lvs().cluster_size();
+        let clusters = |bytes: u64| (bytes + cluster_size - 1) / cluster_size;
+        (clusters(size) - clusters(current)) * cluster_size
+    }
+
+    /// the name of the nexus that has the lvol as a child, if any, along with
+    /// the number of bytes the nexus needs of the lvol
+    fn nexus_minimum(&self, block_len: u64) -> Option<(String, u64)> {
+        let name = self.name();
+        instances()
+            .iter()
+            .find(|n| {
+                n.children.iter().any(
+                    |c| matches!(c.bdev.as_ref(), Some(b) if b.name() == name),
+                )
+            })
+            .map(|n| {
+                let blocks = Nexus::child_blocks(
+                    n.data_ent_offset,
+                    n.size / block_len,
+                    block_len,
+                );
+                (n.name.clone(), blocks * block_len)
+            })
+    }
+
+    /// resize the lvol to the given size in bytes, which is rounded up to a
+    /// multiple of the cluster size of the pool. Thick lvols allocate the
+    /// clusters they grow by straight away so the pool must have room for
+    /// them. An lvol is never shrunk to less than it takes to hold the labels
+    /// of a nexus along with some data, nor to less than a nexus that has the
+    /// lvol as a child needs of it.
+    #[instrument(level = "debug", err)]
+    pub async fn resize(&self, size: u64) -> Result<(), Error> {
+        extern "C" fn resize_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).unwrap();
+        }
+
+        let current = self.size();
+
+        if size < current {
+            let block_len = u64::from(self.as_bdev().block_len());
+            let minimum = Nexus::child_blocks(
+                Nexus::DATA_PARTITION_OFFSET / block_len,
+                1,
+                block_len,
+            ) * block_len;
+            if size < minimum {
+                return Err(Error::RepShrinkTooSmall {
+                    source: Errno::EINVAL,
+                    name: self.name(),
+                    size,
+                    minimum,
+                });
+            }
+
+            if let Some((nexus, minimum)) = self.nexus_minimum(block_len) {
+                if size < minimum {
+                    return Err(Error::RepShrinkInUse {
+                        source: Errno::EBUSY,
+                        name: self.name(),
+                        size,
+                        nexus,
+                        minimum,
+                    });
+                }
+            }
+        }
+
+        let lvs = self.lvs();
+        if self.grow_allocation(size) > lvs.available() {
+            return Err(Error::RepResizeNoSpace {
+                source: Errno::ENOSPC,
+                name: self.name(),
+                pool: lvs.name().to_string(),
+                size,
+            });
+        }
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            vbdev_lvol_resize(self.0.as_ptr(), size, Some(resize_cb), cb_arg(s))
+        };
+
+        r.await
+            .expect("lvol resize callback is gone")
+            .to_result(|e| match Errno::from_i32(e) {
+                Errno::ENOSPC => Error::RepResizeNoSpace {
+                    source: Errno::ENOSPC,
+                    name: self.name(),
+                    pool: lvs.name().to_string(),
+                    size,
+                },
+                errno => Error::RepResize {
+                    source: errno,
+                    name: self.name(),
+                },
+            })?;
+
+        info!("Resized {} to {} bytes", self, self.size());
+        Ok(())
+    }
+
     /// callback executed after synchronizing the lvols metadata
     extern "C" fn blob_sync_cb(sender_ptr: *mut c_void, errno: i32) {
         let sender =
@@ -281,25 +443,24 @@ impl Lvol {
         if self.is_read_only() {
             warn!("{} is read-only", self.name());
         }
-        match prop {
-            PropValue::Shared(val) => {
-                let name = PropName::from(prop).to_string().into_cstring();
-                let value = if val { "true" } else { "false" }.into_cstring();
-                unsafe {
-                    spdk_blob_set_xattr(
-                        blob,
-                        name.as_ptr(),
-                        value.as_bytes_with_nul().as_ptr() as *const _,
-                        value.as_bytes_with_nul().len() as u16,
-                    )
-                }
-                .to_result(|e| Error::SetProperty {
-                    source: Errno::from_i32(e),
-                    prop: prop.into(),
-                    name: self.name(),
-                })?;
-            }
+        let value = match &prop {
+            PropValue::Shared(val) => val.to_string().into_cstring(),
+            PropValue::AllowedHosts(hosts) => hosts.join(",").into_cstring(),
         };
+        let name = PropName::from(&prop).to_string().into_cstring();
+        unsafe {
+            spdk_blob_set_xattr(
+                blob,
+                name.as_ptr(),
+                value.as_bytes_with_nul().as_ptr() as *const _,
+                value.as_bytes_with_nul().len() as u16,
+            )
+        }
+        .to_result(|e| Error::SetProperty {
+            source: Errno::from_i32(e),
+            prop: PropName::from(&prop),
+            name: self.name(),
+        })?;
 
         let (s, r) = pair::<i32>();
         unsafe {
@@ -322,43 +483,199 @@ impl Lvol {
         let blob = unsafe { self.0.as_ref().blob };
         assert_ne!(blob.is_null(), true);
 
+        let name = prop.to_string().into_cstring();
+        let mut value: *const libc::c_char = std::ptr::null::<libc::c_char>();
+        let mut value_len: u64 = 0;
+        unsafe {
+            spdk_blob_get_xattr_value(
+                blob,
+                name.as_ptr(),
+                &mut value as *mut *const c_char as *mut *const c_void,
+                &mut value_len,
+            )
+        }
+        .to_result(|e| Error::GetProperty {
+            source: Errno::from_i32(e),
+            prop: prop.clone(),
+            name: self.name(),
+        })?;
+        let value = unsafe { CStr::from_ptr(value).to_str() };
+
         match prop {
-            PropName::Shared => {
-                let name = prop.to_string().into_cstring();
-                let mut value: *const libc::c_char =
-                    std::ptr::null::<libc::c_char>();
-                let mut value_len: u64 = 0;
-                unsafe {
-                    spdk_blob_get_xattr_value(
-                        blob,
-                        name.as_ptr(),
-                        &mut value as *mut *const c_char as *mut *const c_void,
-                        &mut value_len,
-                    )
-                }
-                .to_result(|e| Error::GetProperty {
-                    source: Errno::from_i32(e),
-                    prop,
+            PropName::Shared => match value {
+                Ok("true") => Ok(PropValue::Shared(true)),
+                Ok("false") => Ok(PropValue::Shared(false)),
+                _ => Err(Error::Property {
+                    source: Errno::EINVAL,
                     name: self.name(),
-                })?;
-                match unsafe { CStr::from_ptr(value).to_str() } {
-                    Ok("true") => Ok(PropValue::Shared(true)),
-                    Ok("false") => Ok(PropValue::Shared(false)),
-                    _ => Err(Error::Property {
-                        source: Errno::EINVAL,
-                        name: self.name(),
-                    }),
-                }
-            }
+                }),
+            },
+            PropName::AllowedHosts => match value {
+                Ok(hosts) => Ok(PropValue::AllowedHosts(
+                    hosts
+                        .split(',')
+                        .filter(|h| !h.is_empty())
+                        .map(String::from)
+                        .collect(),
+                )),
+                _ => Err(Error::Property {
+                    source: Errno::EINVAL,
+                    name: self.name(),
+                }),
+            },
         }
     }
 
+    /// the hosts allowed to connect when the lvol is shared, lvols that
+    /// never had the property set allow any host
+    pub async fn allowed_hosts(&self) -> Vec<String> {
+        match self.get(PropName::AllowedHosts).await {
+            Ok(PropValue::AllowedHosts(hosts)) => hosts,
+            _ => Vec::new(),
+        }
+    }
+
+    /// store the hosts allowed to connect to the lvol and apply them to its
+    /// share when shared already
+    #[instrument(level = "debug", err)]
+    pub async fn set_allowed_hosts(
+        &self,
+        allowed_hosts: &[String],
+    ) -> Result<(), Error> {
+        self.set(PropValue::AllowedHosts(allowed_hosts.to_vec()))
+            .await?;
+        self.as_bdev()
+            .set_allowed_hosts(allowed_hosts)
+            .await
+            .map_err(|e| Error::LvolShare {
+                source: e,
+                name: self.name(),
+            })
+    }
+
     /// Format snapshot name
     /// base_name is the nexus or replica UUID
     pub fn format_snapshot_name(base_name: &str, snapshot_time: u64) -> String {
         format!("{}-snap-{}", base_name, snapshot_time)
     }
 
+    /// Split a snapshot name into the base name and the snapshot time,
+    /// returns None if the name was not created by format_snapshot_name
+    pub fn parse_snapshot_name(name: &str) -> Option<(&str, u64)> {
+        let mut parts = name.rsplitn(2, "-snap-");
+        let snapshot_time = parts.next()?.parse::<u64>().ok()?;
+        let base_name = parts.next()?;
+        Some((base_name, snapshot_time))
+    }
+
+    /// Revert the lvol to the given snapshot by copying the data of the
+    /// snapshot over that of the lvol, where it differs. The lvol keeps its
+    /// size and provisioning, and a failed revert can be retried. The caller
+    /// must make sure that there is no I/O to the lvol meanwhile.
+    #[instrument(level = "debug", err)]
+    pub async fn revert(&self, snapshot: &Lvol) -> Result<(), Error> {
+        if !snapshot.is_snapshot() {
+            return Err(Error::NotASnapshot {
+                source: Errno::EINVAL,
+                name: snapshot.name(),
+            });
+        }
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            blob_revert(
+                self.lvs().0.as_ref().blobstore,
+                self.0.as_ref().blob,
+                snapshot.0.as_ref().blob,
+                Some(Self::blob_sync_cb),
+                cb_arg(s),
+            );
+        }
+
+        r.await.expect("revert callback is gone").to_result(|e| {
+            Error::RepRevert {
+                source: Errno::from_i32(e),
+                snapshot: snapshot.name(),
+                name: self.name(),
+            }
+        })?;
+
+        info!("Reverted {} to snapshot {}", self, snapshot.name());
+        Ok(())
+    }
+
+    /// Create a writable thin provisioned clone of this snapshot
+    #[instrument(level = "debug", err)]
+    pub async fn create_clone(&self, clone_name: &str) -> Result<Lvol, Error> {
+        if !self.is_snapshot() {
+            return Err(Error::NotASnapshot {
+                source: Errno::EINVAL,
+                name: self.name(),
+            });
+        }
+
+        if Bdev::lookup_by_name(clone_name).is_some() {
+            return Err(Error::RepExists {
+                source: Errno::EEXIST,
+                name: clone_name.to_string(),
+            });
+        }
+
+        let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
+
+        let c_clone_name = clone_name.into_cstring();
+        unsafe {
+            vbdev_lvol_create_clone(
+                self.0.as_ptr(),
+                c_clone_name.as_ptr(),
+                Some(Lvol::lvol_cb),
+                cb_arg(s),
+            )
+        };
+
+        let clone = r
+            .await
+            .expect("lvol clone callback is gone")
+            .map_err(|e| Error::RepClone {
+                source: e,
+                snapshot: self.name(),
+                name: clone_name.to_string(),
+            })
+            .map(|lvol| Lvol(NonNull::new(lvol).unwrap()))?;
+
+        info!("Created clone {} of {}", clone, self.name());
+        Ok(clone)
+    }
+
+    /// Create a snapshot and wait for it to complete, returning the snapshot
+    #[instrument(level = "debug", err)]
+    pub async fn snapshot(&self, snapshot_name: &str) -> Result<Lvol, Error> {
+        let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
+
+        let c_snapshot_name = snapshot_name.into_cstring();
+        unsafe {
+            vbdev_lvol_create_snapshot(
+                self.0.as_ptr(),
+                c_snapshot_name.as_ptr(),
+                Some(Lvol::lvol_cb),
+                cb_arg(s),
+            )
+        };
+
+        let snapshot = r
+            .await
+            .expect("lvol snapshot callback is gone")
+            .map_err(|e| Error::RepSnapshot {
+                source: e,
+                snapshot: snapshot_name.to_string(),
+                name: self.name(),
+            })
+            .map(|lvol| Lvol(NonNull::new(lvol).unwrap()))?;
+
+        info!("Created snapshot {} of {}", snapshot_name, self);
+        Ok(snapshot)
+    }
+
     /// Create a snapshot
     pub async fn create_snapshot(
         &self,
@@ -163,6 +163,12 @@ impl Lvs {
         }
     }
 
+    /// returns the size of a cluster, the unit of allocation of the store
+    pub fn cluster_size(&self) -> u64 {
+        let blobs = unsafe { self.0.as_ref().blobstore };
+        unsafe { spdk_bs_get_cluster_size(blobs) }
+    }
+
     /// returns the used capacity
     pub fn used(&self) -> u64 {
         self.capacity() - self.available()
@@ -429,7 +435,7 @@ impl Lvs {
     }
 
     /// share all lvols who have the shared property set, this is implicitly
-    /// shared over nvmf
+    /// shared over nvmf to the hosts they were allowed to be accessed by
     async fn share_all(&self) {
         if let Some(lvols) = self.lvols() {
             for l in lvols {
@@ -444,7 +450,7 @@ impl Lvs {
                                 );
                             }
                         }
-                        PropValue::Shared(false) => {
+                        _ => {
                             debug!("{} not shared on disk", l.name())
                         }
                     }
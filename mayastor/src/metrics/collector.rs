@@ -0,0 +1,352 @@
+//! Collection of the metrics. This walks the pools, replicas and nexuses so it
+//! runs on the master reactor, the result is rendered to text right away.
+use std::{collections::BTreeMap, convert::TryFrom, ffi::c_void};
+
+use ::rpc::mayastor as rpc;
+use futures::channel::oneshot;
+
+use spdk_sys::{
+    spdk_for_each_thread,
+    spdk_get_ticks_hz,
+    spdk_thread_get_stats,
+    spdk_thread_stats,
+};
+
+use crate::{
+    bdev::nexus::{
+        instances,
+        nexus_child_error_store::{NexusErrStore, QueryType},
+    },
+    core::{Bdev, BdevStats, Cores},
+    lvs::{Lvol, Lvs},
+    metrics::text::Exposition,
+    rebuild::{ClientOperations, RebuildJob},
+};
+
+type Labels = Vec<(&'static str, String)>;
+
+/// render all metrics in the Prometheus text format
+pub(super) async fn collect() -> String {
+    let mut out = Exposition::default();
+    pools(&mut out);
+    replicas(&mut out).await;
+    nexuses(&mut out);
+    reactors(&mut out).await;
+    out.into_string()
+}
+
+fn pools(out: &mut Exposition) {
+    let pools = Lvs::iter()
+        .map(|p| (vec![("pool", p.name().to_string())], p.capacity(), p.used()))
+        .collect::<Vec<_>>();
+
+    out.family(
+        "mayastor_pool_capacity_bytes",
+        "gauge",
+        "Capacity of the pool",
+        pools.iter().map(|(l, capacity, _)| (l.clone(), *capacity)),
+    );
+    out.family(
+        "mayastor_pool_used_bytes",
+        "gauge",
+        "Space of the pool allocated to replicas",
+        pools.iter().map(|(l, _, used)| (l.clone(), *used)),
+    );
+}
+
+async fn replicas(out: &mut Exposition) {
+    let mut lvols = Vec::new();
+    if let Some(bdev) = Bdev::bdev_first() {
+        bdev.into_iter()
+            .filter(|b| b.driver() == "lvol")
+            .for_each(|b| lvols.push(Lvol::try_from(b).unwrap()))
+    }
+
+    let mut replicas = Vec::new();
+    for l in lvols {
+        match l.as_bdev().stats().await {
+            Ok(stats) => replicas
+                .push((vec![("replica", l.name()), ("pool", l.pool())], stats)),
+            Err(_) => error!("failed to get stats for lvol: {}", l),
+        }
+    }
+
+    let families: [(&str, &str, fn(&BdevStats) -> u64); 4] = [
+        (
+            "mayastor_replica_read_ops_total",
+            "Reads completed by the replica",
+            |s| s.num_read_ops,
+        ),
+        (
+            "mayastor_replica_write_ops_total",
+            "Writes completed by the replica",
+            |s| s.num_write_ops,
+        ),
+        (
+            "mayastor_replica_read_bytes_total",
+            "Bytes read from the replica",
+            |s| s.bytes_read,
+        ),
+        (
+            "mayastor_replica_written_bytes_total",
+            "Bytes written to the replica",
+            |s| s.bytes_written,
+        ),
+    ];
+    for (name, help, value) in families.iter() {
+        out.family(
+            name,
+            "counter",
+            help,
+            replicas.iter().map(|(l, s)| (l.clone(), value(s))),
+        );
+    }
+}
+
+/// the IO counters and latencies shared by nexuses and children
+fn io_stats(
+    out: &mut Exposition,
+    prefix: &str,
+    samples: &[(Labels, rpc::IoStats)],
+) {
+    let counters: [(&str, &str, fn(&rpc::IoStats) -> u64); 7] = [
+        ("read_ops_total", "Reads completed", |s| s.num_read_ops),
+        ("write_ops_total", "Writes completed", |s| s.num_write_ops),
+        ("unmap_ops_total", "Unmaps completed", |s| s.num_unmap_ops),
+        ("read_bytes_total", "Bytes read", |s| s.bytes_read),
+        ("written_bytes_total", "Bytes written", |s| s.bytes_written),
+        ("unmapped_bytes_total", "Bytes unmapped", |s| {
+            s.bytes_unmapped
+        }),
+        (
+            "retried_ops_total",
+            "IOs completed after failed attempts",
+            |s| s.num_retried_ops,
+        ),
+    ];
+    for (name, help, value) in counters.iter() {
+        out.family(
+            &format!("{}_{}", prefix, name),
+            "counter",
+            help,
+            samples.iter().map(|(l, s)| (l.clone(), value(s))),
+        );
+    }
+
+    let latencies: [(
+        &str,
+        &str,
+        fn(&rpc::IoStats) -> Option<rpc::LatencyStats>,
+    ); 2] = [
+        ("read_latency_microseconds", "Latency of the reads", |s| {
+            s.read_latency.clone()
+        }),
+        ("write_latency_microseconds", "Latency of the writes", |s| {
+            s.write_latency.clone()
+        }),
+    ];
+    for (name, help, value) in latencies.iter() {
+        out.family(
+            &format!("{}_{}", prefix, name),
+            "gauge",
+            help,
+            samples.iter().flat_map(|(l, s)| {
+                let latency = value(s).unwrap_or_default();
+                vec![
+                    ("0.5", latency.p50_us),
+                    ("0.99", latency.p99_us),
+                    ("0.999", latency.p999_us),
+                ]
+                .into_iter()
+                .map(move |(quantile, us)| {
+                    let mut labels = l.clone();
+                    labels.push(("quantile", quantile.to_string()));
+                    (labels, us)
+                })
+            }),
+        );
+    }
+}
+
+fn nexuses(out: &mut Exposition) {
+    let mut nexus_io = Vec::new();
+    let mut child_io = Vec::new();
+    let mut throttled = Vec::new();
+    let mut states = Vec::new();
+    let mut errors = Vec::new();
+    let mut rebuilds = Vec::new();
+
+    for nexus in instances().iter() {
+        let stats = nexus.stats_to_grpc();
+        let labels = vec![("nexus", stats.uuid.clone())];
+        throttled.push((
+            labels.clone(),
+            stats.throttled_reads,
+            stats.throttled_writes,
+        ));
+        nexus_io.push((labels, stats.stats.clone().unwrap_or_default()));
+
+        for (child, child_stats) in nexus.children.iter().zip(stats.children) {
+            let labels = vec![
+                ("nexus", stats.uuid.clone()),
+                ("child", child.name.clone()),
+            ];
+            child_io
+                .push((labels.clone(), child_stats.stats.unwrap_or_default()));
+            states.push((labels.clone(), rpc::ChildState::from(child.state())));
+
+            if let Some(store) = child.err_store.as_ref() {
+                let count = store.query(
+                    NexusErrStore::READ_FLAG
+                        | NexusErrStore::WRITE_FLAG
+                        | NexusErrStore::UNMAP_FLAG
+                        | NexusErrStore::FLUSH_FLAG
+                        | NexusErrStore::RESET_FLAG,
+                    NexusErrStore::IO_FAILED_FLAG,
+                    None,
+                    QueryType::Total,
+                );
+                errors.push((labels.clone(), count));
+            }
+
+            if let Ok(job) = RebuildJob::lookup(&child.name) {
+                rebuilds.push((labels, job.as_client().stats()));
+            }
+        }
+    }
+
+    io_stats(out, "mayastor_nexus", &nexus_io);
+    out.family(
+        "mayastor_nexus_throttled_reads_total",
+        "counter",
+        "Reads held back by the QoS limits",
+        throttled.iter().map(|(l, reads, _)| (l.clone(), *reads)),
+    );
+    out.family(
+        "mayastor_nexus_throttled_writes_total",
+        "counter",
+        "Writes held back by the QoS limits",
+        throttled.iter().map(|(l, _, writes)| (l.clone(), *writes)),
+    );
+
+    io_stats(out, "mayastor_nexus_child", &child_io);
+    out.family(
+        "mayastor_nexus_child_state",
+        "gauge",
+        "State of the child, 1 for the current state",
+        states.iter().flat_map(|(l, current)| {
+            vec![
+                ("online", rpc::ChildState::ChildOnline),
+                ("degraded", rpc::ChildState::ChildDegraded),
+                ("faulted", rpc::ChildState::ChildFaulted),
+            ]
+            .into_iter()
+            .map(move |(name, state)| {
+                let mut labels = l.clone();
+                labels.push(("state", name.to_string()));
+                (labels, (state == *current) as u8)
+            })
+        }),
+    );
+    out.family(
+        "mayastor_nexus_child_errors",
+        "gauge",
+        "Failed IOs held in the error store of the child",
+        errors.into_iter(),
+    );
+    out.family(
+        "mayastor_nexus_child_rebuild_progress_percent",
+        "gauge",
+        "Progress of the rebuild of the child",
+        rebuilds.iter().map(|(l, s)| (l.clone(), s.progress)),
+    );
+    out.family(
+        "mayastor_nexus_child_rebuild_blocks_recovered",
+        "gauge",
+        "Blocks copied to the child by the rebuild",
+        rebuilds
+            .iter()
+            .map(|(l, s)| (l.clone(), s.blocks_recovered)),
+    );
+    out.family(
+        "mayastor_nexus_child_rebuild_blocks_total",
+        "gauge",
+        "Blocks to copy to the child by the rebuild",
+        rebuilds.iter().map(|(l, s)| (l.clone(), s.blocks_total)),
+    );
+}
+
+/// busy and idle time of an SPDK thread
+struct ThreadStats {
+    core: u32,
+    busy_tsc: u64,
+    idle_tsc: u64,
+}
+
+struct ThreadStatsCtx {
+    stats: Vec<ThreadStats>,
+    sender: Option<oneshot::Sender<Vec<ThreadStats>>>,
+}
+
+/// called on each of the SPDK threads in turn
+extern "C" fn get_thread_stats(ctx: *mut c_void) {
+    let ctx = unsafe { &mut *(ctx as *mut ThreadStatsCtx) };
+    let mut stats = spdk_thread_stats::default();
+    if unsafe { spdk_thread_get_stats(&mut stats) } == 0 {
+        ctx.stats.push(ThreadStats {
+            core: Cores::current(),
+            busy_tsc: stats.busy_tsc,
+            idle_tsc: stats.idle_tsc,
+        });
+    }
+}
+
+/// called on the originating thread once all threads have been visited
+extern "C" fn thread_stats_done(ctx: *mut c_void) {
+    let mut ctx = unsafe { Box::from_raw(ctx as *mut ThreadStatsCtx) };
+    let stats = std::mem::take(&mut ctx.stats);
+    if let Some(sender) = ctx.sender.take() {
+        let _ = sender.send(stats);
+    }
+}
+
+async fn reactors(out: &mut Exposition) {
+    let (sender, receiver) = oneshot::channel();
+    let ctx = Box::new(ThreadStatsCtx {
+        stats: Vec::new(),
+        sender: Some(sender),
+    });
+    unsafe {
+        spdk_for_each_thread(
+            Some(get_thread_stats),
+            Box::into_raw(ctx).cast(),
+            Some(thread_stats_done),
+        )
+    };
+
+    // the threads of a core are polled by its reactor one after the other
+    let mut cores = BTreeMap::new();
+    for stats in receiver.await.unwrap_or_default() {
+        let core = cores.entry(stats.core).or_insert((0, 0));
+        core.0 += stats.busy_tsc;
+        core.1 += stats.idle_tsc;
+    }
+
+    let hz = unsafe { spdk_get_ticks_hz() } as f64;
+    out.family(
+        "mayastor_reactor_busy_seconds_total",
+        "counter",
+        "Time the reactor spent polling threads that had work to do",
+        cores.iter().map(|(core, (busy, _))| {
+            (vec![("core", core.to_string())], *busy as f64 / hz)
+        }),
+    );
+    out.family(
+        "mayastor_reactor_idle_seconds_total",
+        "counter",
+        "Time the reactor spent polling threads that had nothing to do",
+        cores.iter().map(|(core, (_, idle))| {
+            (vec![("core", core.to_string())], *idle as f64 / hz)
+        }),
+    );
+}
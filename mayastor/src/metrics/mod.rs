@@ -0,0 +1,57 @@
+//!
+//! Prometheus metrics of pools, replicas, nexuses and reactors.
+//!
+//! Gathering the metrics has to happen on the master reactor, so a collector
+//! running there renders them periodically into a cache. The HTTP server runs
+//! on a thread of its own, away from the reactor cores, and only ever hands
+//! out the cached text. A slow or stuck scraper can therefore never hold up
+//! the reactors and the IO they are doing.
+use std::{net::SocketAddr, sync::RwLock, time::Duration};
+
+use once_cell::sync::Lazy;
+
+mod collector;
+mod server;
+mod text;
+
+pub use server::MetricsServer;
+
+/// default port of the metrics HTTP server
+pub const DEFAULT_PORT: u16 = 9502;
+
+/// how often the metrics are collected
+const COLLECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the most recently collected metrics
+static METRICS: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(String::new()));
+
+/// collect the metrics in the Prometheus text format, must be called from
+/// the master reactor
+pub async fn collect() -> String {
+    collector::collect().await
+}
+
+/// the metrics as of the last collection
+pub(crate) fn scrape() -> String {
+    METRICS.read().unwrap().clone()
+}
+
+/// periodically collect the metrics for the HTTP server to hand out
+pub async fn run_collector() -> Result<(), ()> {
+    loop {
+        let metrics = collect().await;
+        *METRICS.write().unwrap() = metrics;
+        tokio::time::delay_for(COLLECT_INTERVAL).await;
+    }
+}
+
+/// If endpoint is missing a port number then add the default one.
+pub fn endpoint(endpoint: String) -> SocketAddr {
+    (if endpoint.contains(':') {
+        endpoint
+    } else {
+        format!("{}:{}", endpoint, DEFAULT_PORT)
+    })
+    .parse()
+    .expect("Invalid metrics endpoint")
+}
@@ -0,0 +1,66 @@
+//! HTTP server handing out the collected metrics.
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body,
+    Method,
+    Request,
+    Response,
+    Server,
+    StatusCode,
+};
+
+use crate::{core::Mthread, metrics::scrape};
+
+/// content type of version 0.0.4 of the Prometheus text format
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4";
+
+pub struct MetricsServer;
+
+impl MetricsServer {
+    /// start serving the metrics on the given endpoint, on a thread which is
+    /// not pinned to any of the reactor cores
+    pub fn start(endpoint: SocketAddr) -> std::thread::JoinHandle<()> {
+        Mthread::spawn_unaffinitized(move || {
+            let mut rt = tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build()
+                .unwrap();
+
+            rt.block_on(async {
+                let service = make_service_fn(|_| async {
+                    Ok::<_, Infallible>(service_fn(Self::serve))
+                });
+                match Server::try_bind(&endpoint) {
+                    Ok(server) => {
+                        info!("metrics server listening on {}", endpoint);
+                        if let Err(e) = server.serve(service).await {
+                            error!("metrics server failed: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        error!(
+                            "failed to bind metrics server on {}: {}",
+                            endpoint, e
+                        )
+                    }
+                }
+            })
+        })
+    }
+
+    async fn serve(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let response = match (req.method(), req.uri().path()) {
+            (&Method::GET, "/metrics") => Response::builder()
+                .header(CONTENT_TYPE, TEXT_FORMAT)
+                .body(Body::from(scrape())),
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty()),
+        };
+        Ok(response.unwrap())
+    }
+}
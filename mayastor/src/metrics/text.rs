@@ -0,0 +1,48 @@
+//! Minimal writer of the Prometheus text exposition format.
+use std::fmt::{Display, Write};
+
+/// Prometheus text, one metric family at a time
+#[derive(Default)]
+pub(super) struct Exposition(String);
+
+impl Exposition {
+    /// write a metric family and all of its samples, each sample is a list
+    /// of label names and values together with the value of the sample
+    pub(super) fn family<V, I>(
+        &mut self,
+        name: &str,
+        kind: &str,
+        help: &str,
+        samples: I,
+    ) where
+        V: Display,
+        I: IntoIterator<Item = (Vec<(&'static str, String)>, V)>,
+    {
+        let _ = writeln!(self.0, "# HELP {} {}", name, help);
+        let _ = writeln!(self.0, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            if labels.is_empty() {
+                let _ = writeln!(self.0, "{} {}", name, value);
+                continue;
+            }
+            let labels = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = writeln!(self.0, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+
+    pub(super) fn into_string(self) -> String {
+        self.0
+    }
+}
+
+/// escape a label value as required by the exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,136 @@
+#![warn(missing_docs)]
+
+//! Building blocks shared by the rebuild and the scrub jobs, which both work
+//! through the data partition of a nexus one segment at a time.
+
+use std::{cell::UnsafeCell, collections::HashMap};
+
+use once_cell::sync::OnceCell;
+use snafu::{ResultExt, Snafu};
+
+use spdk_sys::spdk_get_thread;
+
+use crate::core::{Descriptor, IoChannel, RangeContext};
+
+#[derive(Debug, Snafu, Clone)]
+#[snafu(visibility = "pub(crate)")]
+#[allow(missing_docs)]
+/// Errors when locking the LBA range of a segment on the nexus
+pub enum SegmentLockError {
+    #[snafu(display("Failed to get an IO channel for nexus {}", nexus))]
+    NoNexusChannel { nexus: String },
+    #[snafu(display(
+        "Failed to lock LBA range for blk {}, len {}, with error: {}",
+        blk,
+        len,
+        source,
+    ))]
+    LockRange {
+        blk: u64,
+        len: u64,
+        source: nix::errno::Errno,
+    },
+    #[snafu(display(
+        "Failed to unlock LBA range for blk {}, len {}, with error: {}",
+        blk,
+        len,
+        source,
+    ))]
+    UnlockRange {
+        blk: u64,
+        len: u64,
+        source: nix::errno::Errno,
+    },
+}
+
+/// Global list of the jobs of one kind, keyed by name, using a static
+/// OnceCell
+pub(super) struct JobInstances<T> {
+    inner: UnsafeCell<HashMap<String, Box<T>>>,
+}
+
+unsafe impl<T> Sync for JobInstances<T> {}
+unsafe impl<T> Send for JobInstances<T> {}
+
+impl<T> JobInstances<T> {
+    /// Get the job instances container, we ensure that this can only ever be
+    /// called on a properly allocated thread
+    pub(super) fn get(
+        instances: &'static OnceCell<Self>,
+    ) -> &'static mut HashMap<String, Box<T>> {
+        let thread = unsafe { spdk_get_thread() };
+        if thread.is_null() {
+            panic!("not called from SPDK thread")
+        }
+
+        let global_instances = instances.get_or_init(|| Self {
+            inner: UnsafeCell::new(HashMap::new()),
+        });
+
+        unsafe { &mut *global_instances.inner.get() }
+    }
+}
+
+/// Lock on the LBA range of a segment of the nexus, which prevents front end
+/// IO to the range whilst the segment is being worked on.
+///
+/// # Safety
+///
+/// The range is unlocked with the very same RangeContext it was locked with,
+/// so the context is boxed to keep its address across moves of the lock.
+pub(super) struct SegmentLock {
+    /// first block of the segment on the children
+    blk: u64,
+    ctx: Box<RangeContext>,
+    ch: IoChannel,
+}
+
+impl SegmentLock {
+    /// Waits for the LBA range of the segment of `len` blocks starting at
+    /// child block `blk` to be locked on the nexus. The nexus children have
+    /// metadata and data partitions, whereas the nexus has a data partition
+    /// only, which starts at child block `data_start`.
+    pub(super) async fn lock(
+        nexus: &Descriptor,
+        data_start: u64,
+        blk: u64,
+        len: u64,
+    ) -> Result<Self, SegmentLockError> {
+        let ch = nexus.get_channel().ok_or_else(|| {
+            SegmentLockError::NoNexusChannel {
+                nexus: nexus.get_bdev().name(),
+            }
+        })?;
+
+        let mut ctx = Box::new(RangeContext::new(blk - data_start, len));
+        nexus
+            .lock_lba_range(&mut ctx, &ch)
+            .await
+            .context(LockRange {
+                blk,
+                len,
+            })?;
+
+        Ok(Self {
+            blk,
+            ctx,
+            ch,
+        })
+    }
+
+    /// Waits for the LBA range to be unlocked, which allows front end IO to
+    /// the range once again
+    pub(super) async fn unlock(
+        mut self,
+        nexus: &Descriptor,
+    ) -> Result<(), SegmentLockError> {
+        let (blk, len) = (self.blk, self.ctx.len);
+        nexus
+            .unlock_lba_range(&mut self.ctx, &self.ch)
+            .await
+            .context(UnlockRange {
+                blk,
+                len,
+            })
+    }
+}
@@ -1,8 +1,14 @@
+/// Building blocks shared by the rebuild and the scrub jobs
+mod common;
 /// Rebuild api module
 mod rebuild_api;
 /// Rebuild implementation module
 pub mod rebuild_impl;
+/// Scrub job module
+mod scrub_job;
 
+pub use common::SegmentLockError;
 pub use rebuild_api::*;
 // for the tests only
 pub use rebuild_impl::SEGMENT_SIZE;
+pub use scrub_job::*;
@@ -1,18 +1,21 @@
 #![warn(missing_docs)]
 
-use std::fmt;
+use std::{fmt, time::Instant};
 
 use crossbeam::channel::{Receiver, Sender};
 use futures::channel::oneshot;
 use snafu::Snafu;
 
 use crate::{
-    bdev::VerboseError,
+    bdev::{
+        nexus::nexus_qos::{QosLimit, TokenBucket, MIB},
+        VerboseError,
+    },
     core::{BdevHandle, CoreError, Descriptor, DmaError},
     nexus_uri::NexusBdevError,
 };
 
-use super::rebuild_impl::*;
+use super::{common::SegmentLockError, rebuild_impl::*};
 
 #[derive(Debug, Snafu, Clone)]
 #[snafu(visibility = "pub(crate)")]
@@ -48,30 +51,12 @@ pub enum RebuildError {
     OpError { operation: String, state: String },
     #[snafu(display("Existing pending state {}", state,))]
     StatePending { state: String },
-    #[snafu(display(
-        "Failed to lock LBA range for blk {}, len {}, with error: {}",
-        blk,
-        len,
-        source,
-    ))]
-    RangeLockError {
-        blk: u64,
-        len: u64,
-        source: nix::errno::Errno,
-    },
-    #[snafu(display(
-        "Failed to unlock LBA range for blk {}, len {}, with error: {}",
-        blk,
-        len,
-        source,
-    ))]
-    RangeUnLockError {
-        blk: u64,
-        len: u64,
-        source: nix::errno::Errno,
-    },
+    #[snafu(display("Failed to lock or unlock the LBA range of a segment"))]
+    RangeLockError { source: SegmentLockError },
     #[snafu(display("Failed to get bdev name from URI {}", uri))]
     BdevInvalidURI { source: NexusBdevError, uri: String },
+    #[snafu(display("All the sources of the rebuild have failed"))]
+    NoRebuildSource {},
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -106,8 +91,34 @@ impl fmt::Display for RebuildState {
     }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// Priority class of a rebuild job. When the global rebuild bandwidth is
+/// limited the jobs of high priority are given the bandwidth first.
+pub enum RebuildPriority {
+    /// the source is the only healthy child of the nexus
+    High,
+    /// the nexus has healthy children besides the source
+    Normal,
+}
+
+impl Default for RebuildPriority {
+    fn default() -> Self {
+        RebuildPriority::Normal
+    }
+}
+
+impl fmt::Display for RebuildPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RebuildPriority::High => write!(f, "high"),
+            RebuildPriority::Normal => write!(f, "normal"),
+        }
+    }
+}
+
 /// A rebuild job is responsible for managing a rebuild (copy) which reads
-/// from source_hdl and writes into destination_hdl from specified start to end
+/// from the source_hdls and writes into destination_hdl from specified start
+/// to end
 #[derive(Debug)]
 pub struct RebuildJob {
     /// name of the nexus associated with the rebuild job
@@ -116,16 +127,34 @@ pub struct RebuildJob {
     pub(super) nexus_descriptor: Descriptor,
     /// source URI of the healthy child to rebuild from
     pub source: String,
-    pub(super) source_hdl: BdevHandle,
+    /// URIs of all the healthy children the segments are read from, starting
+    /// with the source
+    pub sources: Vec<String>,
+    pub(super) source_hdls: Vec<BdevHandle>,
+    /// sources which failed a read and are no longer read from
+    pub(super) failed_sources: Vec<bool>,
     /// target URI of the out of sync child in need of a rebuild
     pub destination: String,
     pub(super) destination_hdl: BdevHandle,
     pub(super) block_size: u64,
     pub(super) range: std::ops::Range<u64>,
+    /// regions within the range which are to be copied
+    pub(super) regions: Vec<std::ops::Range<u64>>,
+    /// index of the region currently being copied
+    pub(super) region: usize,
     pub(super) next: u64,
     pub(super) segment_size_blks: u64,
+    /// blocks which were not copied as the sources, all lvols, have
+    /// not allocated them
+    pub(super) blocks_skipped: u64,
     pub(super) task_pool: RebuildTasks,
+    /// bandwidth limit of the job
+    pub(super) rate: TokenBucket,
+    /// priority class of the job
+    pub(super) priority: RebuildPriority,
     pub(super) notify_fn: fn(String, String) -> (),
+    /// when the notify fn was last called whilst the job was running
+    pub(super) last_checkpoint: Instant,
     /// channel used to signal rebuild update
     pub notify_chan: (Sender<RebuildState>, Receiver<RebuildState>),
     /// current state of the rebuild job
@@ -142,6 +171,9 @@ pub struct RebuildStats {
     pub blocks_total: u64,
     /// number of blocks recovered
     pub blocks_recovered: u64,
+    /// number of blocks recovered without being copied, as the sources have
+    /// not allocated them, which only lvol sources are able to tell
+    pub blocks_skipped: u64,
     /// rebuild progress in % (0-100)
     pub progress: u64,
     /// granularity of each recovery copy in blocks
@@ -176,8 +208,9 @@ pub trait ClientOperations {
 impl RebuildJob {
     /// Creates a new RebuildJob which rebuilds from source URI to target URI
     /// from start to end (of the data partition); notify_fn callback is called
-    /// when the rebuild state is updated - with the nexus and destination
-    /// URI as arguments
+    /// when the rebuild state is updated, and periodically whilst the job is
+    /// running so that its progress can be checkpointed - with the nexus and
+    /// destination URI as arguments
     pub fn create<'a>(
         nexus: &str,
         source: &str,
@@ -190,6 +223,105 @@ impl RebuildJob {
         Ok(Self::lookup(destination)?)
     }
 
+    /// Restricts the rebuild to the given regions of the range, eg the regions
+    /// written to whilst the destination was out of the IO path, rather than
+    /// copying the whole range. Only allowed before the job is started.
+    pub fn set_regions(
+        &mut self,
+        regions: Vec<std::ops::Range<u64>>,
+    ) -> Result<(), RebuildError> {
+        if self.state() != RebuildState::Init {
+            return Err(RebuildError::OpError {
+                operation: "SetRegions".to_string(),
+                state: self.states.to_string(),
+            });
+        }
+
+        if regions.iter().any(|r| !r.within(self.range.clone())) {
+            return Err(RebuildError::InvalidParameters {});
+        }
+
+        self.next = regions.first().map_or(self.range.end, |r| r.start);
+        self.regions = regions;
+        self.region = 0;
+        Ok(())
+    }
+
+    /// Reads the segments from the given healthy children as well, in turn
+    /// with the source. Only allowed before the job is started.
+    pub fn add_sources(
+        &mut self,
+        sources: &[String],
+    ) -> Result<(), RebuildError> {
+        if self.state() != RebuildState::Init {
+            return Err(RebuildError::OpError {
+                operation: "AddSources".to_string(),
+                state: self.states.to_string(),
+            });
+        }
+
+        for source in sources.iter().filter(|s| !self.sources.contains(s)) {
+            let hdl = Self::open_source(source)?;
+            if !Self::validate(
+                &hdl.get_bdev(),
+                &self.destination_hdl.get_bdev(),
+                &self.range,
+            ) {
+                return Err(RebuildError::InvalidParameters {});
+            }
+            self.sources.push(source.clone());
+            self.source_hdls.push(hdl);
+            self.failed_sources.push(false);
+        }
+        Ok(())
+    }
+
+    /// Regions of the range which are to be copied
+    pub fn regions(&self) -> &[std::ops::Range<u64>] {
+        &self.regions
+    }
+
+    /// The block from which the job would have to be restarted: all blocks of
+    /// its regions before it have been copied. Unlike the next block to be
+    /// copied this excludes the segments which are still being copied.
+    pub fn checkpoint(&self) -> u64 {
+        self.task_pool.copying().unwrap_or(self.next)
+    }
+
+    /// Limits the bandwidth of the job to `rate` MiB/s, 0 removes the limit.
+    /// The limit can be changed whilst the job is running.
+    pub fn set_rate(&mut self, rate: u64) {
+        self.rate.set(
+            QosLimit {
+                rate,
+                burst: 0,
+            },
+            MIB,
+        );
+    }
+
+    /// Limits the bandwidth of all the rebuild jobs combined to `rate` MiB/s,
+    /// 0 removes the limit
+    pub fn set_global_rate(rate: u64) {
+        GLOBAL_RATE.set(
+            QosLimit {
+                rate,
+                burst: 0,
+            },
+            MIB,
+        );
+    }
+
+    /// Sets the priority class of the job
+    pub fn set_priority(&mut self, priority: RebuildPriority) {
+        self.priority = priority;
+    }
+
+    /// Priority class of the job
+    pub fn priority(&self) -> RebuildPriority {
+        self.priority
+    }
+
     /// Lookup a rebuild job by its destination uri and return it
     pub fn lookup(name: &str) -> Result<&mut Self, RebuildError> {
         if let Some(job) = Self::get_instances().get_mut(name) {
@@ -201,11 +333,11 @@ impl RebuildJob {
         }
     }
 
-    /// Lookup all rebuilds jobs with name as its source
+    /// Lookup all rebuilds jobs with name as one of their sources
     pub fn lookup_src(name: &str) -> Vec<&mut Self> {
         Self::get_instances()
             .iter_mut()
-            .filter(|j| j.1.source == name)
+            .filter(|j| j.1.sources.iter().any(|s| s == name))
             .map(|j| j.1.as_mut())
             .collect::<Vec<_>>()
     }
@@ -230,6 +362,16 @@ impl RebuildJob {
         self.states.current
     }
 
+    /// URIs of the sources which failed a read and are no longer read from
+    pub fn failed_sources(&self) -> Vec<String> {
+        self.sources
+            .iter()
+            .zip(self.failed_sources.iter())
+            .filter(|(_, failed)| **failed)
+            .map(|(source, _)| source.clone())
+            .collect()
+    }
+
     /// Error description
     pub fn error_desc(&self) -> String {
         match self.error.as_ref() {
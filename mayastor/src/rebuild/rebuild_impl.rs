@@ -1,33 +1,40 @@
 #![warn(missing_docs)]
 #![allow(clippy::unknown_clippy_lints)]
 
-use std::{cell::UnsafeCell, collections::HashMap};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    ffi::c_void,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
 use crossbeam::channel::unbounded;
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 use snafu::ResultExt;
 
-use spdk_sys::{spdk_get_thread, SPDK_BDEV_LARGE_BUF_MAX_SIZE};
+use spdk_sys::{
+    spdk_poller,
+    spdk_poller_register,
+    spdk_poller_unregister,
+    SPDK_BDEV_LARGE_BUF_MAX_SIZE,
+};
 
 use crate::{
-    bdev::VerboseError,
-    core::{Bdev, BdevHandle, DmaBuf, RangeContext, Reactors},
+    bdev::{nexus::nexus_qos::TokenBucket, VerboseError},
+    core::{Bdev, BdevHandle, DmaBuf, Reactors},
+    lvs::Lvol,
     nexus_uri::bdev_get_name,
 };
 
-use super::rebuild_api::*;
-
-/// Global list of rebuild jobs using a static OnceCell
-pub(super) struct RebuildInstances {
-    inner: UnsafeCell<HashMap<String, Box<RebuildJob>>>,
-}
-
-unsafe impl Sync for RebuildInstances {}
-unsafe impl Send for RebuildInstances {}
+use super::{
+    common::{JobInstances, SegmentLock},
+    rebuild_api::*,
+};
 
 /// Result returned by each segment task worker
 /// used to communicate with the management task indicating that the
@@ -47,6 +54,49 @@ const SEGMENT_TASKS: usize = 16;
 /// Size of each segment used by the copy task
 pub const SEGMENT_SIZE: u64 = SPDK_BDEV_LARGE_BUF_MAX_SIZE as u64;
 
+/// Period in microseconds at which a throttled job checks whether it may copy
+/// the next segment
+const THROTTLE_POLL_PERIOD_US: u64 = 1000;
+
+/// Period at which a running job calls its notify fn, so that its progress can
+/// be checkpointed
+const CHECKPOINT_PERIOD: Duration = Duration::from_secs(10);
+
+/// Bandwidth limit of all the rebuild jobs combined
+pub(super) static GLOBAL_RATE: Lazy<TokenBucket> = Lazy::new(Default::default);
+
+/// Number of high priority jobs which are held back by the global bandwidth
+/// limit, the jobs of normal priority wait for as long as there are any
+static HIGH_PRIORITY_WAITING: AtomicUsize = AtomicUsize::new(0);
+
+/// Resolves once `period_us` microseconds have elapsed, the wait is driven by
+/// a poller on the current SPDK thread
+async fn delay(period_us: u64) {
+    struct Delay {
+        poller: *mut spdk_poller,
+        sender: oneshot::Sender<()>,
+    }
+
+    extern "C" fn expired(ctx: *mut c_void) -> i32 {
+        let mut delay = unsafe { Box::from_raw(ctx as *mut Delay) };
+        unsafe { spdk_poller_unregister(&mut delay.poller) };
+        let _ = delay.sender.send(());
+        1
+    }
+
+    let (sender, receiver) = oneshot::channel();
+    let delay = Box::into_raw(Box::new(Delay {
+        poller: std::ptr::null_mut(),
+        sender,
+    }));
+    // the poller cannot expire before the future yields to the reactor
+    unsafe {
+        (*delay).poller =
+            spdk_poller_register(Some(expired), delay as *mut c_void, period_us)
+    };
+    let _ = receiver.await;
+}
+
 /// Each rebuild task needs a unique buffer to read/write from source to target
 /// A mpsc channel is used to communicate with the management task
 #[derive(Debug)]
@@ -54,6 +104,8 @@ struct RebuildTask {
     buffer: DmaBuf,
     sender: mpsc::Sender<TaskResult>,
     error: Option<TaskResult>,
+    /// block of the segment being copied, or which failed to be copied
+    copying: Option<u64>,
 }
 
 /// Pool of rebuild tasks and progress tracking
@@ -70,6 +122,14 @@ pub(super) struct RebuildTasks {
     segments_done: u64,
 }
 
+impl RebuildTasks {
+    /// The lowest block of the segments which have not been copied (yet) by
+    /// the tasks, if any
+    pub(super) fn copying(&self) -> Option<u64> {
+        self.tasks.iter().filter_map(|t| t.copying).min()
+    }
+}
+
 /// Checks whether a range is contained within another range
 pub trait Within<T> {
     /// True if `self` is contained within `right`, otherwise false
@@ -111,16 +171,7 @@ impl RebuildJob {
         range: std::ops::Range<u64>,
         notify_fn: fn(String, String) -> (),
     ) -> Result<Self, RebuildError> {
-        let source_hdl = BdevHandle::open(
-            &bdev_get_name(source).context(BdevInvalidURI {
-                uri: source.to_string(),
-            })?,
-            false,
-            false,
-        )
-        .context(NoBdevHandle {
-            bdev: source,
-        })?;
+        let source_hdl = Self::open_source(source)?;
         let destination_hdl = BdevHandle::open(
             &bdev_get_name(destination).context(BdevInvalidURI {
                 uri: destination.to_string(),
@@ -162,6 +213,7 @@ impl RebuildJob {
                 buffer: copy_buffer,
                 sender: tasks.channel.0.clone(),
                 error: None,
+                copying: None,
             });
         }
 
@@ -179,16 +231,24 @@ impl RebuildJob {
         Ok(Self {
             nexus,
             nexus_descriptor,
+            sources: vec![source.clone()],
             source,
-            source_hdl,
+            source_hdls: vec![source_hdl],
+            failed_sources: vec![false],
             destination,
             destination_hdl,
             next: range.start,
+            regions: vec![range.clone()],
+            region: 0,
             range,
             block_size,
             segment_size_blks,
+            blocks_skipped: 0,
             task_pool: tasks,
+            rate: Default::default(),
+            priority: Default::default(),
             notify_fn,
+            last_checkpoint: Instant::now(),
             notify_chan: unbounded::<RebuildState>(),
             states: Default::default(),
             complete_chan: Vec::new(),
@@ -196,6 +256,22 @@ impl RebuildJob {
         })
     }
 
+    /// Opens a handle to read from the source URI
+    pub(super) fn open_source(
+        source: &str,
+    ) -> Result<BdevHandle, RebuildError> {
+        BdevHandle::open(
+            &bdev_get_name(source).context(BdevInvalidURI {
+                uri: source.to_string(),
+            })?,
+            false,
+            false,
+        )
+        .context(NoBdevHandle {
+            bdev: source,
+        })
+    }
+
     // Runs the management async task that kicks off N rebuild copy tasks and
     // awaits each completion. When any task completes it kicks off another
     // until the bdev is fully rebuilt
@@ -205,6 +281,8 @@ impl RebuildJob {
             match self.await_one_task().await {
                 Some(r) => match r.error {
                     None => {
+                        self.throttle().await;
+                        self.notify_progress();
                         match self.states.pending {
                             None | Some(RebuildState::Running) => {
                                 self.start_task_by_id(r.id);
@@ -236,67 +314,94 @@ impl RebuildJob {
         self.reconcile();
     }
 
-    /// Return the size of the segment to be copied.
-    fn get_segment_size_blks(&self, blk: u64) -> u64 {
-        // Adjust the segments size for the last segment
-        if (blk + self.segment_size_blks) > self.range.end {
-            return self.range.end - blk;
+    /// Waits until both the bandwidth limit of the job and the global one
+    /// allow another segment to be copied, or until a state change is
+    /// pending. Jobs of normal priority also wait whilst high priority jobs
+    /// are held back by the global limit, so that these are served first.
+    async fn throttle(&mut self) {
+        let high = self.priority == RebuildPriority::High;
+        let mut waiting = false;
+
+        loop {
+            let stopping = self
+                .states
+                .pending
+                .map_or(false, |s| s != RebuildState::Running);
+            if stopping {
+                break;
+            }
+
+            let own = self.rate.available();
+            let global = GLOBAL_RATE.available();
+
+            let held = high && own && !global;
+            if held != waiting {
+                if held {
+                    HIGH_PRIORITY_WAITING.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    HIGH_PRIORITY_WAITING.fetch_sub(1, Ordering::Relaxed);
+                }
+                waiting = held;
+            }
+
+            if own
+                && global
+                && (high || HIGH_PRIORITY_WAITING.load(Ordering::Relaxed) == 0)
+            {
+                break;
+            }
+
+            delay(THROTTLE_POLL_PERIOD_US).await;
+        }
+
+        if waiting {
+            HIGH_PRIORITY_WAITING.fetch_sub(1, Ordering::Relaxed);
         }
-        self.segment_size_blks
+    }
+
+    /// Return the next segment to be copied as a (block, length) pair and
+    /// move past it, onto the next region once the current one is exhausted.
+    fn next_segment(&mut self) -> Option<(u64, u64)> {
+        while let Some(region) = self.regions.get(self.region) {
+            if self.next < region.start {
+                self.next = region.start;
+            }
+            if self.next < region.end {
+                let blk = self.next;
+                // Adjust the segment size for the last segment of the region
+                self.next =
+                    std::cmp::min(blk + self.segment_size_blks, region.end);
+                return Some((blk, self.next - blk));
+            }
+            self.region += 1;
+        }
+        None
     }
 
     /// Copies one segment worth of data from source into destination. During
     /// this time the LBA range being copied is locked so that there cannot be
     /// front end I/O to the same LBA range.
-    ///
-    /// # Safety
-    ///
-    /// The lock and unlock functions internally reference the RangeContext as a
-    /// raw pointer, so rust cannot correctly manage its lifetime. The
-    /// RangeContext MUST NOT be dropped until after the lock and unlock have
-    /// completed.
-    ///
-    /// The use of RangeContext here is safe because it is stored on the stack
-    /// for the duration of the calls to lock and unlock.
     async fn locked_copy_one(
         &mut self,
         id: usize,
         blk: u64,
+        len: u64,
     ) -> Result<(), RebuildError> {
-        let len = self.get_segment_size_blks(blk);
-        // The nexus children have metadata and data partitions, whereas the
-        // nexus has a data partition only. Because we are locking the range on
-        // the nexus, we need to calculate the offset from the start of the data
-        // partition.
-        let mut ctx = RangeContext::new(blk - self.range.start, len);
-        let ch = self
-            .nexus_descriptor
-            .get_channel()
-            .expect("Failed to get nexus channel");
-
-        // Wait for LBA range to be locked.
-        // This prevents other I/Os being issued to this LBA range whilst it is
-        // being rebuilt.
-        self.nexus_descriptor
-            .lock_lba_range(&mut ctx, &ch)
-            .await
-            .context(RangeLockError {
-                blk,
-                len,
-            })?;
+        let lock = SegmentLock::lock(
+            &self.nexus_descriptor,
+            self.range.start,
+            blk,
+            len,
+        )
+        .await
+        .context(RangeLockError {})?;
 
         // Perform the copy
-        let result = self.copy_one(id, blk).await;
+        let result = self.copy_one(id, blk, len).await;
 
-        // Wait for the LBA range to be unlocked.
-        // This allows others I/Os to be issued to this LBA range once again.
-        self.nexus_descriptor
-            .unlock_lba_range(&mut ctx, &ch)
+        lock.unlock(&self.nexus_descriptor)
             .await
-            .context(RangeUnLockError {
-                blk,
-                len,
-            })?;
+            .context(RangeLockError {})?;
 
         result
     }
@@ -306,35 +411,82 @@ impl RebuildJob {
         &mut self,
         id: usize,
         blk: u64,
+        len: u64,
     ) -> Result<(), RebuildError> {
+        // What the sources have never written to reads as zeroes, so rather
+        // than being copied, and allocated on a thin provisioned destination,
+        // it only needs zeroing on a destination which has written to it.
+        // Only lvols can tell, be they local or shared by another node, so
+        // segments are skipped only when all the healthy sources are lvols.
+        if !self.source_allocated(blk, len).await {
+            if Self::allocated(&self.destination_hdl, blk, len).await {
+                self.destination_hdl
+                    .write_zeroes_at(
+                        blk * self.block_size,
+                        len * self.block_size,
+                    )
+                    .await
+                    .context(WriteIoError {
+                        bdev: &self.destination,
+                    })?;
+            }
+            self.blocks_skipped += len;
+            return Ok(());
+        }
+
         let mut copy_buffer: DmaBuf;
 
-        let copy_buffer = if self.get_segment_size_blks(blk)
-            == self.segment_size_blks
-        {
+        let copy_buffer = if len == self.segment_size_blks {
             &mut self.task_pool.tasks[id].buffer
         } else {
-            let segment_size_blks = self.range.end - blk;
-
             trace!(
                     "Adjusting last segment size from {} to {}. offset: {}, range: {:?}",
-                    self.segment_size_blks, segment_size_blks, blk, self.range,
+                    self.segment_size_blks, len, blk, self.range,
                 );
 
             copy_buffer = self
                 .destination_hdl
-                .dma_malloc(segment_size_blks * self.block_size)
+                .dma_malloc(len * self.block_size)
                 .context(NoCopyBuffer {})?;
 
             &mut copy_buffer
         };
 
-        self.source_hdl
-            .read_at(blk * self.block_size, copy_buffer)
-            .await
-            .context(ReadIoError {
-                bdev: &self.source,
-            })?;
+        // The segments are read from each of the sources in turn. A source
+        // which fails a read is no longer read from and the read is retried
+        // on the next source, the copy only fails when none is left. The
+        // sources that failed a read which another source served are left to
+        // the nexus to fault once the job is done.
+        let count = self.source_hdls.len();
+        let first =
+            ((blk - self.range.start) / self.segment_size_blks) as usize;
+        let mut result = Err(RebuildError::NoRebuildSource {});
+        for i in (0 .. count).map(|n| (first + n) % count) {
+            if self.failed_sources[i] {
+                continue;
+            }
+            match self.source_hdls[i]
+                .read_at(blk * self.block_size, copy_buffer)
+                .await
+            {
+                Ok(_) => {
+                    result = Ok(());
+                    break;
+                }
+                Err(source) => {
+                    warn!(
+                        "Rebuild job {}: failed to read block {} from source {}, no longer reading from it",
+                        self.destination, blk, self.sources[i]
+                    );
+                    self.failed_sources[i] = true;
+                    result = Err(RebuildError::ReadIoError {
+                        source,
+                        bdev: self.sources[i].clone(),
+                    });
+                }
+            }
+        }
+        result?;
 
         self.destination_hdl
             .write_at(blk * self.block_size, copy_buffer)
@@ -346,11 +498,61 @@ impl RebuildJob {
         Ok(())
     }
 
+    /// Whether the blocks may hold data on the sources, which is the case
+    /// unless every healthy source knows them to be unallocated
+    async fn source_allocated(&self, blk: u64, len: u64) -> bool {
+        let mut healthy = self
+            .source_hdls
+            .iter()
+            .zip(self.failed_sources.iter())
+            .filter(|(_, failed)| !**failed)
+            .map(|(hdl, _)| hdl)
+            .peekable();
+        // without any healthy source the copy itself reports the failure
+        if healthy.peek().is_none() {
+            return true;
+        }
+        for hdl in healthy {
+            if Self::allocated(hdl, blk, len).await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether the blocks are allocated on the bdev. The lvols of a local
+    /// pool tell directly and the replicas of other nodes are asked over nvmf
+    /// with a vendor admin command, whereas the blocks of any other bdev,
+    /// the iscsi children included, are assumed to be allocated, as are
+    /// those of nvmf targets which fail the command.
+    async fn allocated(hdl: &BdevHandle, blk: u64, len: u64) -> bool {
+        let bdev = hdl.get_bdev();
+        if let Ok(lvol) = Lvol::try_from(bdev) {
+            return lvol.is_allocated(blk, len);
+        }
+        if bdev.driver() != "nvme" {
+            return true;
+        }
+        match u32::try_from(len) {
+            Ok(len) => hdl.blocks_allocated(blk, len).await.unwrap_or(true),
+            Err(_) => true,
+        }
+    }
+
     fn notify(&mut self) {
         self.stats();
         self.send_notify();
     }
 
+    /// Calls the job's registered notify fn callback once every
+    /// `CHECKPOINT_PERIOD`, without a change of state
+    fn notify_progress(&mut self) {
+        if self.last_checkpoint.elapsed() >= CHECKPOINT_PERIOD {
+            self.last_checkpoint = Instant::now();
+            (self.notify_fn)(self.nexus.clone(), self.destination.clone());
+        }
+    }
+
     /// Calls the job's registered notify fn callback and notify sender channel
     fn send_notify(&mut self) {
         // should this return a status before we notify the sender channel?
@@ -362,7 +564,7 @@ impl RebuildJob {
 
     /// Check if the source and destination block devices are compatible for
     /// rebuild
-    fn validate(
+    pub(super) fn validate(
         source: &Bdev,
         destination: &Bdev,
         range: &std::ops::Range<u64>,
@@ -424,19 +626,9 @@ impl RebuildJob {
     /// Get the rebuild job instances container, we ensure that this can only
     /// ever be called on a properly allocated thread
     pub(super) fn get_instances() -> &'static mut HashMap<String, Box<Self>> {
-        let thread = unsafe { spdk_get_thread() };
-        if thread.is_null() {
-            panic!("not called from SPDK thread")
-        }
-
-        static REBUILD_INSTANCES: OnceCell<RebuildInstances> = OnceCell::new();
-
-        let global_instances =
-            REBUILD_INSTANCES.get_or_init(|| RebuildInstances {
-                inner: UnsafeCell::new(HashMap::new()),
-            });
-
-        unsafe { &mut *global_instances.inner.get() }
+        static REBUILD_INSTANCES: OnceCell<JobInstances<RebuildJob>> =
+            OnceCell::new();
+        JobInstances::get(&REBUILD_INSTANCES)
     }
 }
 
@@ -469,7 +661,8 @@ impl std::fmt::Display for RebuildOperation {
 
 impl ClientOperations for RebuildJob {
     fn stats(&self) -> RebuildStats {
-        let blocks_total = self.range.end - self.range.start;
+        let blocks_total =
+            self.regions.iter().map(|r| r.end - r.start).sum::<u64>();
 
         // segment size may not be aligned to the total size
         let blocks_recovered = std::cmp::min(
@@ -477,11 +670,17 @@ impl ClientOperations for RebuildJob {
             blocks_total,
         );
 
-        let progress = (blocks_recovered * 100) / blocks_total;
+        // there may be nothing to copy at all
+        let progress = if blocks_total == 0 {
+            100
+        } else {
+            (blocks_recovered * 100) / blocks_total
+        };
 
         info!(
             "State: {}, Src: {}, Dst: {}, range: {:?}, next: {}, \
-             block_size: {}, segment_sz: {}, recovered_blks: {}, progress: {}%",
+             block_size: {}, segment_sz: {}, recovered_blks: {}, \
+             skipped_blks: {}, progress: {}%",
             self.state(),
             self.source,
             self.destination,
@@ -490,12 +689,14 @@ impl ClientOperations for RebuildJob {
             self.block_size,
             self.segment_size_blks,
             blocks_recovered,
+            self.blocks_skipped,
             progress,
         );
 
         RebuildStats {
             blocks_total,
             blocks_recovered,
+            blocks_skipped: self.blocks_skipped,
             progress,
             segment_size_blks: self.segment_size_blks,
             block_size: self.block_size,
@@ -558,22 +759,23 @@ impl RebuildJob {
         );
 
         for n in 0 .. self.task_pool.total {
-            self.next = match self.send_segment_task(n) {
-                Some(next) => {
-                    self.task_pool.active += 1;
-                    next
-                }
+            match self.send_segment_task(n) {
+                Some(_) => self.task_pool.active += 1,
                 None => break, /* we've already got enough tasks to rebuild
                                 * the bdev */
             };
         }
+
+        // none of the regions needed copying
+        if self.task_pool.active == 0 {
+            self.complete();
+        }
     }
 
     fn start_task_by_id(&mut self, id: usize) {
         match self.send_segment_task(id) {
-            Some(next) => {
+            Some(_) => {
                 self.task_pool.active += 1;
-                self.next = next;
             }
             None => {
                 if self.task_pool.active == 0 {
@@ -588,6 +790,7 @@ impl RebuildJob {
             self.task_pool.active -= 1;
             if f.error.is_none() {
                 self.task_pool.segments_done += 1;
+                self.task_pool.tasks[f.id].copying = None;
             } else {
                 self.task_pool.tasks[f.id].error = Some(f.clone());
             }
@@ -604,35 +807,32 @@ impl RebuildJob {
     }
 
     /// Sends one segment worth of data in a reactor future and notifies the
-    /// management channel. Returns the next segment offset to rebuild, if any
-    fn send_segment_task(&self, id: usize) -> Option<u64> {
-        if self.next >= self.range.end {
-            None
-        } else {
-            let blk = self.next;
-            let next = std::cmp::min(
-                self.next + self.segment_size_blks,
-                self.range.end,
-            );
-            let name = self.destination.clone();
+    /// management channel. Returns the segment offset being rebuilt, if any
+    fn send_segment_task(&mut self, id: usize) -> Option<u64> {
+        let (blk, len) = self.next_segment()?;
+        let name = self.destination.clone();
 
-            Reactors::current().send_future(async move {
-                let job = Self::lookup(&name).unwrap();
+        let bytes = len * self.block_size;
+        self.rate.consume(bytes);
+        GLOBAL_RATE.consume(bytes);
+        self.task_pool.tasks[id].copying = Some(blk);
 
-                let r = TaskResult {
-                    blk,
-                    id,
-                    error: job.locked_copy_one(id, blk).await.err(),
-                };
+        Reactors::current().send_future(async move {
+            let job = Self::lookup(&name).unwrap();
 
-                let task = &mut job.task_pool.tasks[id];
-                if let Err(e) = task.sender.start_send(r) {
-                    error!("Failed to notify job of segment id: {} blk: {} completion, err: {}", id, blk, e.verbose());
-                }
-            });
+            let r = TaskResult {
+                blk,
+                id,
+                error: job.locked_copy_one(id, blk, len).await.err(),
+            };
 
-            Some(next)
-        }
+            let task = &mut job.task_pool.tasks[id];
+            if let Err(e) = task.sender.start_send(r) {
+                error!("Failed to notify job of segment id: {} blk: {} completion, err: {}", id, blk, e.verbose());
+            }
+        });
+
+        Some(blk)
     }
 }
 
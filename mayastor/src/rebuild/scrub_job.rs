@@ -0,0 +1,503 @@
+#![warn(missing_docs)]
+
+//! A scrub job verifies that the children of a nexus hold the same data.
+//!
+//! The data partition is read segment by segment from each of the children,
+//! with the LBA range of the segment locked on the nexus, as the rebuild does,
+//! so that front end writes cannot make the children diverge meanwhile. The
+//! checksums of each block are compared across the children and the blocks
+//! which differ are reported. When an authoritative child is given, the
+//! divergent children are repaired by rewriting the segment from it.
+
+use std::{collections::HashMap, ops::Range};
+
+use crc::crc32;
+use futures::channel::oneshot;
+use once_cell::sync::OnceCell;
+use snafu::{ResultExt, Snafu};
+
+use crate::{
+    core::{
+        Bdev,
+        BdevHandle,
+        CoreError,
+        Descriptor,
+        DmaBuf,
+        DmaError,
+        Reactors,
+    },
+    nexus_uri::{bdev_get_name, NexusBdevError},
+};
+
+use super::{
+    common::{JobInstances, SegmentLock, SegmentLockError},
+    rebuild_api::RebuildState,
+    rebuild_impl::SEGMENT_SIZE,
+};
+
+#[derive(Debug, Snafu, Clone)]
+#[snafu(visibility = "pub(crate)")]
+#[allow(missing_docs)]
+/// Errors when interacting with a scrub job or encountered during a scrub
+pub enum ScrubError {
+    #[snafu(display("Failed to allocate buffer for the scrub"))]
+    NoScrubBuffer { source: DmaError },
+    #[snafu(display("Failed to validate scrub job creation parameters"))]
+    InvalidScrubParameters {},
+    #[snafu(display("Failed to get a handle for bdev {}", bdev))]
+    NoScrubBdevHandle { source: CoreError, bdev: String },
+    #[snafu(display("Bdev {} not found", bdev))]
+    ScrubBdevNotFound { source: CoreError, bdev: String },
+    #[snafu(display("Failed to get bdev name from URI {}", uri))]
+    ScrubBdevInvalidURI { source: NexusBdevError, uri: String },
+    #[snafu(display("Read IO failed for bdev {}", bdev))]
+    ScrubReadIoError { source: CoreError, bdev: String },
+    #[snafu(display("Write IO failed for bdev {}", bdev))]
+    ScrubWriteIoError { source: CoreError, bdev: String },
+    #[snafu(display("Failed to lock or unlock the LBA range of a segment"))]
+    ScrubRangeLockError { source: SegmentLockError },
+    #[snafu(display("Failed to find scrub job of nexus {}", nexus))]
+    ScrubJobNotFound { nexus: String },
+    #[snafu(display("Nexus {} is already being scrubbed", nexus))]
+    ScrubJobAlreadyExists { nexus: String },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// A range of blocks which differs between the children
+pub struct ScrubMismatch {
+    /// first block of the range, relative to the start of the nexus
+    pub offset: u64,
+    /// number of blocks in the range
+    pub num_blocks: u64,
+    /// whether the range has been rewritten from the authoritative child
+    pub repaired: bool,
+}
+
+/// scrub statistics
+pub struct ScrubStats {
+    /// total number of blocks to scrub
+    pub blocks_total: u64,
+    /// number of blocks scrubbed
+    pub blocks_scrubbed: u64,
+    /// scrub progress in % (0-100)
+    pub progress: u64,
+}
+
+/// A scrub job compares the data of all the given children of a nexus from
+/// start to end of the data partition, and optionally repairs it
+#[derive(Debug)]
+pub struct ScrubJob {
+    /// name of the nexus associated with the scrub job
+    pub nexus: String,
+    /// descriptor for the nexus
+    nexus_descriptor: Descriptor,
+    /// URIs of the children being compared
+    pub children: Vec<String>,
+    hdls: Vec<BdevHandle>,
+    /// a buffer per child to read a segment into
+    buffers: Vec<DmaBuf>,
+    /// index of the child the divergent children are repaired from, if any
+    authority: Option<usize>,
+    block_size: u64,
+    range: Range<u64>,
+    next: u64,
+    segment_size_blks: u64,
+    state: RebuildState,
+    /// a stop has been requested whilst running
+    stopping: bool,
+    /// ranges found to differ between the children
+    mismatches: Vec<ScrubMismatch>,
+    /// channel list which allows the await of the scrub
+    complete_chan: Vec<oneshot::Sender<RebuildState>>,
+    /// scrub error, if any
+    pub error: Option<ScrubError>,
+}
+
+impl ScrubJob {
+    /// Creates a new ScrubJob which compares the children from start to end
+    /// of the data partition, repairing the divergent children from
+    /// `authority` if given, which has to be one of the children
+    pub fn create<'a>(
+        nexus: &'a str,
+        children: &[String],
+        authority: Option<&str>,
+        range: Range<u64>,
+    ) -> Result<&'a mut Self, ScrubError> {
+        let instances = Self::get_instances();
+        if matches!(instances.get(nexus), Some(job) if !job.state.done()) {
+            return Err(ScrubError::ScrubJobAlreadyExists {
+                nexus: nexus.to_string(),
+            });
+        }
+
+        let job = Self::new(nexus, children, authority, range)?;
+        instances.insert(nexus.to_string(), Box::new(job));
+        Self::lookup(nexus)
+    }
+
+    fn new(
+        nexus: &str,
+        children: &[String],
+        authority: Option<&str>,
+        range: Range<u64>,
+    ) -> Result<Self, ScrubError> {
+        let authority = match authority {
+            Some(authority) => Some(
+                children
+                    .iter()
+                    .position(|c| c == authority)
+                    .ok_or(ScrubError::InvalidScrubParameters {})?,
+            ),
+            None => None,
+        };
+
+        let mut hdls = Vec::new();
+        for child in children {
+            let hdl = BdevHandle::open(
+                &bdev_get_name(child).context(ScrubBdevInvalidURI {
+                    uri: child.to_string(),
+                })?,
+                true,
+                false,
+            )
+            .context(NoScrubBdevHandle {
+                bdev: child,
+            })?;
+            hdls.push(hdl);
+        }
+
+        let first =
+            hdls.first().ok_or(ScrubError::InvalidScrubParameters {})?;
+        let block_size = first.get_bdev().block_len() as u64;
+        if !hdls
+            .iter()
+            .all(|hdl| Self::validate(&hdl.get_bdev(), block_size, &range))
+        {
+            return Err(ScrubError::InvalidScrubParameters {});
+        }
+
+        let segment_size_blks = SEGMENT_SIZE / block_size;
+        let buffers =
+            Self::alloc_buffers(&hdls, segment_size_blks, block_size)?;
+
+        let nexus_descriptor =
+            Bdev::open_by_name(nexus, false).context(ScrubBdevNotFound {
+                bdev: nexus.to_string(),
+            })?;
+
+        Ok(Self {
+            nexus: nexus.to_string(),
+            nexus_descriptor,
+            children: children.to_vec(),
+            hdls,
+            buffers,
+            authority,
+            block_size,
+            next: range.start,
+            range,
+            segment_size_blks,
+            state: RebuildState::Init,
+            stopping: false,
+            mismatches: Vec::new(),
+            complete_chan: Vec::new(),
+            error: None,
+        })
+    }
+
+    /// Check that the range lies within the child and that its block size is
+    /// the same as that of the other children
+    fn validate(bdev: &Bdev, block_size: u64, range: &Range<u64>) -> bool {
+        range.start < range.end
+            && range.end <= bdev.num_blocks()
+            && bdev.block_len() as u64 == block_size
+    }
+
+    /// Allocates a buffer of `len` blocks for each of the children
+    fn alloc_buffers(
+        hdls: &[BdevHandle],
+        len: u64,
+        block_size: u64,
+    ) -> Result<Vec<DmaBuf>, ScrubError> {
+        hdls.iter()
+            .map(|hdl| {
+                hdl.dma_malloc(len * block_size).context(NoScrubBuffer {})
+            })
+            .collect()
+    }
+
+    /// Schedules the job to start in a future and returns a complete channel
+    /// which can be waited on
+    pub fn start(
+        &mut self,
+    ) -> Result<oneshot::Receiver<RebuildState>, ScrubError> {
+        let end_channel = oneshot::channel();
+        self.complete_chan.push(end_channel.0);
+
+        if self.state == RebuildState::Init {
+            self.state = RebuildState::Running;
+            info!(
+                "Scrub job of nexus {}: scrubbing children {:?}",
+                self.nexus, self.children
+            );
+
+            let nexus = self.nexus.clone();
+            Reactors::master().send_future(async move {
+                match ScrubJob::lookup(&nexus) {
+                    Ok(job) => job.run().await,
+                    Err(_) => {
+                        error!(
+                            "Failed to find and start the scrub job {}",
+                            nexus
+                        )
+                    }
+                }
+            });
+        }
+        Ok(end_channel.1)
+    }
+
+    /// Stops the job, the returned channel resolves once it has stopped
+    pub fn stop(&mut self) -> oneshot::Receiver<RebuildState> {
+        let end_channel = oneshot::channel();
+        match self.state {
+            RebuildState::Running => {
+                self.stopping = true;
+                self.complete_chan.push(end_channel.0);
+            }
+            RebuildState::Init => {
+                self.complete_chan.push(end_channel.0);
+                self.end(RebuildState::Stopped);
+            }
+            state => {
+                let _ = end_channel.0.send(state);
+            }
+        }
+        end_channel.1
+    }
+
+    /// Scrubs one segment at a time until all of the range has been scrubbed,
+    /// a stop is requested or an IO fails
+    async fn run(&mut self) {
+        while self.next < self.range.end {
+            if self.stopping {
+                self.end(RebuildState::Stopped);
+                return;
+            }
+
+            let blk = self.next;
+            let len =
+                std::cmp::min(self.segment_size_blks, self.range.end - blk);
+            if let Err(e) = self.locked_scrub_one(blk, len).await {
+                error!(
+                    "Scrub job of nexus {}: failed to scrub block {} with error: {}",
+                    self.nexus, blk, e
+                );
+                self.error = Some(e);
+                self.end(RebuildState::Failed);
+                return;
+            }
+            self.next = blk + len;
+        }
+        self.end(RebuildState::Completed);
+    }
+
+    /// Scrubs one segment worth of data with the LBA range locked on the
+    /// nexus, so that there cannot be front end I/O to the same range
+    async fn locked_scrub_one(
+        &mut self,
+        blk: u64,
+        len: u64,
+    ) -> Result<(), ScrubError> {
+        let lock = SegmentLock::lock(
+            &self.nexus_descriptor,
+            self.range.start,
+            blk,
+            len,
+        )
+        .await
+        .context(ScrubRangeLockError {})?;
+
+        let result = self.scrub_one(blk, len).await;
+
+        lock.unlock(&self.nexus_descriptor)
+            .await
+            .context(ScrubRangeLockError {})?;
+
+        result
+    }
+
+    /// Reads one segment from each of the children and compares the
+    /// checksums of its blocks, rewriting the divergent children from the
+    /// authoritative one, if any
+    async fn scrub_one(
+        &mut self,
+        blk: u64,
+        len: u64,
+    ) -> Result<(), ScrubError> {
+        let mut last_buffers: Vec<DmaBuf>;
+        let buffers = if len == self.segment_size_blks {
+            &mut self.buffers
+        } else {
+            last_buffers =
+                Self::alloc_buffers(&self.hdls, len, self.block_size)?;
+            &mut last_buffers
+        };
+
+        for ((hdl, child), buffer) in self
+            .hdls
+            .iter()
+            .zip(self.children.iter())
+            .zip(buffers.iter_mut())
+        {
+            hdl.read_at(blk * self.block_size, buffer).await.context(
+                ScrubReadIoError {
+                    bdev: child,
+                },
+            )?;
+        }
+
+        let block_size = self.block_size as usize;
+        let checksums = buffers
+            .iter()
+            .map(|buffer| {
+                buffer
+                    .as_slice()
+                    .chunks(block_size)
+                    .map(crc32::checksum_ieee)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // without an authority the children are compared to the first one
+        let reference = &checksums[self.authority.unwrap_or(0)];
+        let divergent = checksums
+            .iter()
+            .map(|sums| sums != reference)
+            .collect::<Vec<_>>();
+        if !divergent.iter().any(|d| *d) {
+            return Ok(());
+        }
+
+        let mut repaired = false;
+        if let Some(authority) = self.authority {
+            for (i, _) in divergent.iter().enumerate().filter(|(_, d)| **d) {
+                self.hdls[i]
+                    .write_at(blk * self.block_size, &buffers[authority])
+                    .await
+                    .context(ScrubWriteIoError {
+                        bdev: &self.children[i],
+                    })?;
+            }
+            repaired = true;
+        }
+
+        let mut start = None;
+        for i in 0 ..= len as usize {
+            let differs = i < len as usize
+                && checksums.iter().any(|sums| sums[i] != reference[i]);
+            match (differs, start) {
+                (true, None) => start = Some(i as u64),
+                (false, Some(first)) => {
+                    self.record(blk + first, i as u64 - first, repaired);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a range of divergent blocks, merging it with the previous one
+    /// when they are contiguous
+    fn record(&mut self, blk: u64, num_blocks: u64, repaired: bool) {
+        let offset = blk - self.range.start;
+        warn!(
+            "Scrub job of nexus {}: {} block(s) at offset {} differ between the children{}",
+            self.nexus,
+            num_blocks,
+            offset,
+            if repaired { ", repaired" } else { "" }
+        );
+
+        if let Some(last) = self.mismatches.last_mut() {
+            if last.offset + last.num_blocks == offset
+                && last.repaired == repaired
+            {
+                last.num_blocks += num_blocks;
+                return;
+            }
+        }
+        self.mismatches.push(ScrubMismatch {
+            offset,
+            num_blocks,
+            repaired,
+        });
+    }
+
+    /// Moves the job to its final state and signals whoever awaits it
+    fn end(&mut self, state: RebuildState) {
+        info!(
+            "Scrub job of nexus {}: changing state from {:?} to {:?}, {} mismatch(es) found",
+            self.nexus,
+            self.state,
+            state,
+            self.mismatches.len()
+        );
+        self.state = state;
+        self.stopping = false;
+        for sender in self.complete_chan.drain(..) {
+            let _ = sender.send(state);
+        }
+    }
+
+    /// State of the scrub job, which shares the states of the rebuild jobs
+    /// but is never paused
+    pub fn state(&self) -> RebuildState {
+        self.state
+    }
+
+    /// Ranges found to differ between the children so far
+    pub fn mismatches(&self) -> &[ScrubMismatch] {
+        &self.mismatches
+    }
+
+    /// Collects statistics from the job
+    pub fn stats(&self) -> ScrubStats {
+        let blocks_total = self.range.end - self.range.start;
+        let blocks_scrubbed = self.next - self.range.start;
+        ScrubStats {
+            blocks_total,
+            blocks_scrubbed,
+            progress: (blocks_scrubbed * 100) / blocks_total,
+        }
+    }
+
+    /// Lookup the scrub job of a nexus and return it
+    pub fn lookup(nexus: &str) -> Result<&mut Self, ScrubError> {
+        if let Some(job) = Self::get_instances().get_mut(nexus) {
+            Ok(job)
+        } else {
+            Err(ScrubError::ScrubJobNotFound {
+                nexus: nexus.to_owned(),
+            })
+        }
+    }
+
+    /// Lookup the scrub job of a nexus then remove and return it
+    pub fn remove(nexus: &str) -> Result<Self, ScrubError> {
+        match Self::get_instances().remove(nexus) {
+            Some(job) => Ok(*job),
+            None => Err(ScrubError::ScrubJobNotFound {
+                nexus: nexus.to_owned(),
+            }),
+        }
+    }
+
+    /// Get the scrub job instances container, we ensure that this can only
+    /// ever be called on a properly allocated thread
+    fn get_instances() -> &'static mut HashMap<String, Box<Self>> {
+        static SCRUB_INSTANCES: OnceCell<JobInstances<ScrubJob>> =
+            OnceCell::new();
+        JobInstances::get(&SCRUB_INSTANCES)
+    }
+}
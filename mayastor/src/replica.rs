@@ -145,7 +145,7 @@ impl Replica {
         let bdev = unsafe { Bdev::from((*self.lvol_ptr).bdev) };
 
         match kind {
-            ShareType::Nvmf => target::nvmf::share(&uuid, &bdev)
+            ShareType::Nvmf => target::nvmf::share(&uuid, &bdev, &[])
                 .await
                 .context(ShareNvmf {})?,
             ShareType::Iscsi => {
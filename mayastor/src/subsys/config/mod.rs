@@ -32,10 +32,13 @@ use crate::{
     bdev::{
         nexus::{
             instances,
+            nexus_bdev::ReadPolicy,
             nexus_child::{ChildState, NexusChild, Reason},
             nexus_child_status_config::ChildStatusConfig,
+            nexus_qos::NexusQos,
         },
         nexus_create,
+        nexus_lookup,
         VerboseError,
     },
     core::{Bdev, Cores, Reactor, Share},
@@ -272,6 +275,8 @@ impl Config {
                     .iter()
                     .map(|child| child.name.clone())
                     .collect::<Vec<_>>(),
+                read_policy: nexus.read_policy,
+                qos: nexus.qos,
             })
             .collect::<Vec<_>>();
 
@@ -366,6 +371,9 @@ impl Config {
                                 e.verbose()
                             );
                             failures += 1;
+                        } else if let Some(n) = nexus_lookup(&nexus.name) {
+                            n.set_read_policy(nexus.read_policy);
+                            n.set_qos(nexus.qos);
                         }
                     }
                     Err(_e) => {
@@ -574,6 +582,12 @@ pub struct NexusBdev {
     pub size: String,
     /// the children the nexus should be created on
     pub children: Vec<String>,
+    /// the policy used to select the child to read from
+    #[serde(default)]
+    pub read_policy: ReadPolicy,
+    /// the IOPS and bandwidth limits of the nexus
+    #[serde(default)]
+    pub qos: NexusQos,
 }
 
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,188 @@
+//! Publishes the state changes of nexuses, their children and rebuilds on the
+//! message bus, so that the control plane does not have to poll for them.
+//! A local pool is reported degraded when IO to one of its replicas fails.
+//!
+//! The events are taken from the nexus event feed, which replays the events
+//! that happened before the message bus was up. The messages are published
+//! without any delivery guarantees, a control plane service that missed some
+//! of them has to resync through gRPC.
+
+use std::{convert::TryFrom, io};
+
+use mbus_api::*;
+use tokio::sync::broadcast::RecvError;
+
+use crate::{
+    bdev::{
+        nexus_events::{self, NexusEvent, NexusEventType},
+        ChildState,
+        NexusStatus,
+        Reason,
+    },
+    core::{Bdev, MayastorEnvironment},
+    grpc::name_to_uuid,
+    lvs::Lvol,
+    nexus_uri::bdev_get_name,
+};
+
+pub struct EventPublisher;
+
+impl EventPublisher {
+    /// runner publishing the nexus events for as long as mayastor runs
+    pub async fn run() -> Result<(), ()> {
+        let env = MayastorEnvironment::global_or_default();
+        if env.mbus_endpoint.is_none() {
+            return Ok(());
+        }
+
+        // pick up the events from startup onwards, if they are still known
+        let mut watch = match nexus_events::watch(nexus_events::epoch(), 1) {
+            Ok(watch) => watch,
+            Err(_) => nexus_events::watch(0, 0).unwrap(),
+        };
+        for event in watch.replay.drain(..) {
+            Self::publish(&env.node_name, &event).await;
+        }
+
+        loop {
+            match watch.receiver.recv().await {
+                Ok(event) => Self::publish(&env.node_name, &event).await,
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Dropped {} nexus events for the message bus", count)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// publish the messages of a single event, if it has any
+    async fn publish(node: &str, event: &NexusEvent) {
+        let pool = match event.event {
+            NexusEventType::ChildState {
+                new: ChildState::Faulted(Reason::IoError),
+                ..
+            } => event.child.as_deref().and_then(child_pool),
+            _ => None,
+        };
+
+        for message in EventMessage::from_event(node, event, pool.as_deref()) {
+            if let Err(error) = message.publish().await {
+                error!(
+                    "Failed to publish nexus event {}: {}",
+                    event.seq, error
+                );
+            }
+        }
+    }
+}
+
+/// A message published on the events channel
+#[derive(Debug, PartialEq)]
+pub enum EventMessage {
+    NexusStateChanged(NexusStateChanged),
+    ChildFaulted(ChildFaulted),
+    PoolDegraded(PoolDegraded),
+    RebuildStarted(RebuildStarted),
+    RebuildCompleted(RebuildCompleted),
+    RebuildFailed(RebuildFailed),
+}
+
+impl EventMessage {
+    /// The messages of an event of a nexus on mayastor instance `node`, where
+    /// `pool` is the local pool of the child the event is about, if any
+    pub fn from_event(
+        node: &str,
+        event: &NexusEvent,
+        pool: Option<&str>,
+    ) -> Vec<Self> {
+        let node = node.to_string();
+        // nexuses created through gRPC are named after their uuid
+        let uuid = name_to_uuid(&event.nexus).to_string();
+        let uri = event.child.clone().unwrap_or_default();
+
+        match event.event {
+            NexusEventType::NexusStatus {
+                old,
+                new,
+            } => vec![Self::NexusStateChanged(NexusStateChanged {
+                node,
+                uuid,
+                old_state: nexus_state(old),
+                new_state: nexus_state(new),
+            })],
+            // a child that is out of sync is only degraded
+            NexusEventType::ChildState {
+                new: ChildState::Faulted(reason),
+                ..
+            } if reason != Reason::OutOfSync => {
+                let mut messages = Vec::new();
+                // an IO error on a replica of a local pool is a failure of the
+                // pool, the other replicas of which may still be working
+                if let (Reason::IoError, Some(pool)) = (reason, pool) {
+                    messages.push(Self::PoolDegraded(PoolDegraded {
+                        node: node.clone(),
+                        name: pool.to_string(),
+                        reason: format!("IO to replica {} failed", uri),
+                    }));
+                }
+                messages.push(Self::ChildFaulted(ChildFaulted {
+                    node,
+                    uuid,
+                    uri,
+                    reason: reason.to_string(),
+                }));
+                messages
+            }
+            NexusEventType::RebuildStarted => {
+                vec![Self::RebuildStarted(RebuildStarted {
+                    node,
+                    uuid,
+                    uri,
+                })]
+            }
+            NexusEventType::RebuildCompleted => {
+                vec![Self::RebuildCompleted(RebuildCompleted {
+                    node,
+                    uuid,
+                    uri,
+                })]
+            }
+            NexusEventType::RebuildFailed => {
+                vec![Self::RebuildFailed(RebuildFailed {
+                    node,
+                    uuid,
+                    uri,
+                })]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    async fn publish(&self) -> io::Result<()> {
+        match self {
+            Self::NexusStateChanged(message) => message.publish().await,
+            Self::ChildFaulted(message) => message.publish().await,
+            Self::PoolDegraded(message) => message.publish().await,
+            Self::RebuildStarted(message) => message.publish().await,
+            Self::RebuildCompleted(message) => message.publish().await,
+            Self::RebuildFailed(message) => message.publish().await,
+        }
+    }
+}
+
+/// name of the pool of the child, if it is a replica of a local pool
+fn child_pool(uri: &str) -> Option<String> {
+    let bdev = Bdev::lookup_by_name(&bdev_get_name(uri).ok()?)?;
+    Lvol::try_from(bdev)
+        .ok()
+        .map(|lvol| lvol.lvs().name().to_string())
+}
+
+fn nexus_state(status: NexusStatus) -> NexusState {
+    match status {
+        NexusStatus::Online => NexusState::Online,
+        NexusStatus::Degraded => NexusState::Degraded,
+        NexusStatus::Faulted => NexusState::Faulted,
+    }
+}
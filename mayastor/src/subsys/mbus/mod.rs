@@ -4,7 +4,11 @@
 //!
 //! A Registration subsystem is used to keep moac in the loop
 //! about the lifecycle of mayastor instances.
+//!
+//! The state changes of nexuses, their children and rebuilds are published
+//! on the events channel.
 
+pub mod events;
 pub mod registration;
 
 use crate::core::MayastorEnvironment;
@@ -11,6 +11,8 @@ pub use config::{
 };
 pub use nvmf::{
     create_snapshot,
+    encode_block_range,
+    encode_snapshot_time,
     set_snapshot_time,
     Error as NvmfError,
     NvmeCpl,
@@ -18,6 +20,7 @@ pub use nvmf::{
     NvmfSubsystem,
     SubType,
     Target as NvmfTarget,
+    TransportID,
 };
 use spdk_sys::{
     spdk_add_subsystem,
@@ -26,6 +29,7 @@ use spdk_sys::{
 };
 
 pub use mbus::{
+    events::{EventMessage, EventPublisher},
     mbus_endpoint,
     message_bus_init,
     registration::Registration,
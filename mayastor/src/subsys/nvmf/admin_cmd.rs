@@ -32,6 +32,11 @@ impl NvmeCpl {
     pub(crate) fn status(&mut self) -> &mut spdk_nvme_status {
         unsafe { &mut *spdk_sys::nvme_status_get(self.0.as_mut()) }
     }
+
+    /// Sets the command specific dword 0 of the completion
+    pub(crate) fn set_cdw0(&mut self, cdw0: u32) {
+        unsafe { self.0.as_mut().cdw0 = cdw0 }
+    }
 }
 
 #[derive(Clone)]
@@ -47,6 +52,24 @@ impl NvmfReq {
             .unwrap(),
         )
     }
+
+    /// Completes the request with a generic success or internal device error
+    /// status
+    pub(crate) fn complete(&self, success: bool) {
+        let mut rsp = self.response();
+        let nvme_status = rsp.status();
+
+        nvme_status.set_sct(0); // SPDK_NVME_SCT_GENERIC
+        nvme_status.set_sc(if success {
+            0
+        } else {
+            0x06 // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
+        });
+
+        unsafe {
+            spdk_sys::spdk_nvmf_request_complete(self.0.as_ptr());
+        }
+    }
 }
 
 impl From<*mut c_void> for NvmfReq {
@@ -58,34 +81,67 @@ impl From<*mut c_void> for NvmfReq {
 /// Set the snapshot time in an spdk_nvme_cmd struct to the current time
 /// Returns seconds since Unix epoch
 pub fn set_snapshot_time(cmd: &mut spdk_nvme_cmd) -> u64 {
-    // encode snapshot time in cdw10/11
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs();
+    encode_snapshot_time(cmd, now);
+    now as u64
+}
+
+/// Set the snapshot time in an spdk_nvme_cmd struct to the given time
+pub fn encode_snapshot_time(cmd: &mut spdk_nvme_cmd, snapshot_time: u64) {
+    // encode snapshot time in cdw10/11
     unsafe {
-        *spdk_sys::nvme_cmd_cdw10_get(&mut *cmd) = now as u32;
-        *spdk_sys::nvme_cmd_cdw11_get(&mut *cmd) = (now >> 32) as u32;
+        *spdk_sys::nvme_cmd_cdw10_get(&mut *cmd) = snapshot_time as u32;
+        *spdk_sys::nvme_cmd_cdw11_get(&mut *cmd) = (snapshot_time >> 32) as u32;
     }
-    now as u64
 }
 
-/// NVMf custom command handler for opcode c0h
-/// Called from nvmf_ctrlr_process_admin_cmd
-/// Return: <0 for any error, caller handles it as unsupported opcode
-extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
-    debug!("nvmf_create_snapshot_hdlr {:?}", req);
+/// Decode the snapshot time from the cdw10/11 of an spdk_nvme_cmd struct
+fn decode_snapshot_time(cmd: *const spdk_nvme_cmd) -> u64 {
+    unsafe {
+        spdk_sys::nvme_cmd_cdw10_get_val(cmd) as u64
+            | (spdk_sys::nvme_cmd_cdw11_get_val(cmd) as u64) << 32
+    }
+}
+
+/// Set the block range in an spdk_nvme_cmd struct, the first block in
+/// cdw10/11 and the number of blocks in cdw12
+pub fn encode_block_range(cmd: &mut spdk_nvme_cmd, blk: u64, len: u32) {
+    unsafe {
+        *spdk_sys::nvme_cmd_cdw10_get(&mut *cmd) = blk as u32;
+        *spdk_sys::nvme_cmd_cdw11_get(&mut *cmd) = (blk >> 32) as u32;
+        *spdk_sys::nvme_cmd_cdw12_get(&mut *cmd) = len;
+    }
+}
 
+/// Decode the block range from the cdw10/11/12 of an spdk_nvme_cmd struct
+fn decode_block_range(cmd: *const spdk_nvme_cmd) -> (u64, u64) {
+    unsafe {
+        (
+            spdk_sys::nvme_cmd_cdw10_get_val(cmd) as u64
+                | (spdk_sys::nvme_cmd_cdw11_get_val(cmd) as u64) << 32,
+            spdk_sys::nvme_cmd_cdw12_get_val(cmd) as u64,
+        )
+    }
+}
+
+/// Returns the bdev of the only namespace of the subsystem the request was
+/// received on along with its descriptor and channel
+fn request_bdev(
+    req: *mut spdk_nvmf_request,
+) -> Option<(*mut spdk_bdev, *mut spdk_bdev_desc, *mut spdk_io_channel)> {
     let subsys = unsafe { spdk_sys::spdk_nvmf_request_get_subsystem(req) };
     if subsys.is_null() {
         debug!("subsystem is null");
-        return -1;
+        return None;
     }
 
     /* Only process this request if it has exactly one namespace */
     if unsafe { spdk_sys::spdk_nvmf_subsystem_get_max_nsid(subsys) } != 1 {
         debug!("multiple namespaces");
-        return -1;
+        return None;
     }
 
     /* Forward to first namespace if it supports NVME admin commands */
@@ -100,9 +156,23 @@ extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
     if rc != 0 {
         /* No bdev found for this namespace. Continue. */
         debug!("no bdev found");
-        return -1;
+        return None;
     }
 
+    Some((bdev, desc, ch))
+}
+
+/// NVMf custom command handler for opcode c0h
+/// Called from nvmf_ctrlr_process_admin_cmd
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_create_snapshot_hdlr {:?}", req);
+
+    let (bdev, desc, ch) = match request_bdev(req) {
+        Some(bdev) => bdev,
+        None => return -1,
+    };
+
     let bd = Bdev::from(bdev);
     if bd.driver() == nexus_module::NEXUS_NAME {
         // Received command on a published Nexus
@@ -115,12 +185,8 @@ extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
     } else if let Ok(lvol) = Lvol::try_from(bd) {
         // Received command on a shared replica (lvol)
         let cmd = unsafe { spdk_sys::spdk_nvmf_request_get_cmd(req) };
-        let snapshot_time = unsafe {
-            spdk_sys::nvme_cmd_cdw10_get_val(cmd) as u64
-                | (spdk_sys::nvme_cmd_cdw11_get_val(cmd) as u64) << 32
-        };
         let snapshot_name =
-            Lvol::format_snapshot_name(&lvol.name(), snapshot_time);
+            Lvol::format_snapshot_name(&lvol.name(), decode_snapshot_time(cmd));
         let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
         // Blobfs operations must be on md_thread
         Reactors::master().send_future(async move {
@@ -133,28 +199,103 @@ extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
     }
 }
 
+/// NVMf custom command handler for opcode c1h, reverting a shared replica to
+/// its snapshot taken at the time given in cdw10/11. A nexus does not accept
+/// the command, it is reverted through its management interface instead.
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_revert_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_revert_snapshot_hdlr {:?}", req);
+
+    let (bdev, _, _) = match request_bdev(req) {
+        Some(bdev) => bdev,
+        None => return -1,
+    };
+
+    let lvol = match Lvol::try_from(Bdev::from(bdev)) {
+        Ok(lvol) => lvol,
+        Err(_) => {
+            debug!("unsupported bdev driver");
+            return -1;
+        }
+    };
+
+    let cmd = unsafe { spdk_sys::spdk_nvmf_request_get_cmd(req) };
+    let snapshot_name =
+        Lvol::format_snapshot_name(&lvol.name(), decode_snapshot_time(cmd));
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    // Blobfs operations must be on md_thread
+    Reactors::master().send_future(async move {
+        let result = match Bdev::lookup_by_name(&snapshot_name)
+            .and_then(|bdev| Lvol::try_from(bdev).ok())
+        {
+            Some(snapshot) => lvol.revert(&snapshot).await.is_ok(),
+            None => {
+                error!("{}: snapshot {} not found", lvol, snapshot_name);
+                false
+            }
+        };
+        nvmf_req.complete(result);
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
+/// NVMf custom command handler for opcode c2h, telling whether any of the
+/// blocks in the range given in cdw10/11/12 is allocated on a shared replica,
+/// which lets a rebuild from the replica skip what it has never written to.
+/// The answer is returned in dword 0 of the completion, 1 if allocated and 0
+/// otherwise.
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_blocks_allocated_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    let (bdev, _, _) = match request_bdev(req) {
+        Some(bdev) => bdev,
+        None => return -1,
+    };
+
+    let lvol = match Lvol::try_from(Bdev::from(bdev)) {
+        Ok(lvol) => lvol,
+        Err(_) => {
+            debug!("unsupported bdev driver");
+            return -1;
+        }
+    };
+
+    let (blk, len) =
+        decode_block_range(unsafe { spdk_sys::spdk_nvmf_request_get_cmd(req) });
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    nvmf_req
+        .response()
+        .set_cdw0(lvol.is_allocated(blk, len) as u32);
+    nvmf_req.complete(true);
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
 pub fn create_snapshot(
     lvol: Lvol,
     cmd: &spdk_sys::spdk_nvme_cmd,
     io: *mut spdk_sys::spdk_bdev_io,
 ) {
-    let snapshot_time = unsafe {
-        spdk_sys::nvme_cmd_cdw10_get_val(&*cmd) as u64
-            | (spdk_sys::nvme_cmd_cdw11_get_val(&*cmd) as u64) << 32
-    };
-    let snapshot_name = Lvol::format_snapshot_name(&lvol.name(), snapshot_time);
+    let snapshot_name =
+        Lvol::format_snapshot_name(&lvol.name(), decode_snapshot_time(cmd));
     // Blobfs operations must be on md_thread
     Reactors::master().send_future(async move {
         lvol.create_snapshot_local(io, &snapshot_name).await;
     });
 }
 
-/// Register custom NVMe admin command handler
-pub fn setup_create_snapshot_hdlr() {
+/// Register custom NVMe admin command handlers
+pub fn setup_custom_admin_cmd_hdlrs() {
     unsafe {
         spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
             nvme_admin_opc::CREATE_SNAPSHOT,
             Some(nvmf_create_snapshot_hdlr),
         );
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::REVERT_SNAPSHOT,
+            Some(nvmf_revert_snapshot_hdlr),
+        );
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::BLOCKS_ALLOCATED,
+            Some(nvmf_blocks_allocated_hdlr),
+        );
     }
 }
@@ -13,7 +13,14 @@ use std::cell::RefCell;
 use nix::errno::Errno;
 use snafu::Snafu;
 
-pub use admin_cmd::{create_snapshot, set_snapshot_time, NvmeCpl, NvmfReq};
+pub use admin_cmd::{
+    create_snapshot,
+    encode_block_range,
+    encode_snapshot_time,
+    set_snapshot_time,
+    NvmeCpl,
+    NvmfReq,
+};
 use poll_groups::PollGroup;
 use spdk_sys::{
     spdk_subsystem,
@@ -22,6 +29,7 @@ use spdk_sys::{
 };
 pub use subsystem::{NvmfSubsystem, SubType};
 pub use target::Target;
+pub use transport::TransportID;
 
 use crate::{
     jsonrpc::{Code, RpcErrorCode},
@@ -87,8 +95,8 @@ impl Nvmf {
 
         // this code only ever gets run on the first core
 
-        // set up custom NVMe Admin command handler
-        admin_cmd::setup_create_snapshot_hdlr();
+        // set up custom NVMe Admin command handlers
+        admin_cmd::setup_custom_admin_cmd_hdlrs();
 
         if Config::get().nexus_opts.nvmf_enable {
             NVMF_TGT.with(|tgt| {
@@ -13,28 +13,38 @@ use serde::export::{Formatter, TryFrom};
 
 use spdk_sys::{
     spdk_bdev_nvme_opts,
+    spdk_nvmf_host_get_nqn,
     spdk_nvmf_ns_get_bdev,
     spdk_nvmf_ns_opts,
     spdk_nvmf_subsystem,
+    spdk_nvmf_subsystem_add_host,
     spdk_nvmf_subsystem_add_listener,
     spdk_nvmf_subsystem_add_ns,
     spdk_nvmf_subsystem_create,
     spdk_nvmf_subsystem_destroy,
     spdk_nvmf_subsystem_get_first,
+    spdk_nvmf_subsystem_get_first_host,
     spdk_nvmf_subsystem_get_first_listener,
     spdk_nvmf_subsystem_get_first_ns,
     spdk_nvmf_subsystem_get_next,
+    spdk_nvmf_subsystem_get_next_host,
     spdk_nvmf_subsystem_get_next_listener,
     spdk_nvmf_subsystem_get_nqn,
     spdk_nvmf_subsystem_listener_get_trid,
     spdk_nvmf_subsystem_pause,
+    spdk_nvmf_subsystem_remove_host,
+    spdk_nvmf_subsystem_remove_listener,
     spdk_nvmf_subsystem_resume,
     spdk_nvmf_subsystem_set_allow_any_host,
+    spdk_nvmf_subsystem_set_ana_reporting,
+    spdk_nvmf_subsystem_set_ana_state,
     spdk_nvmf_subsystem_set_mn,
     spdk_nvmf_subsystem_set_sn,
     spdk_nvmf_subsystem_start,
     spdk_nvmf_subsystem_stop,
     spdk_nvmf_tgt,
+    spdk_nvmf_tgt_listen,
+    spdk_nvmf_tgt_stop_listen,
     SPDK_NVMF_SUBTYPE_DISCOVERY,
     SPDK_NVMF_SUBTYPE_NVME,
 };
@@ -102,6 +112,7 @@ impl Debug for NvmfSubsystem {
                 .field("sn", &self.0.as_ref().sn.as_str().to_string())
                 .field("mn", &self.0.as_ref().mn.as_str().to_string())
                 .field("allow_any_host", &self.0.as_ref().allow_any_host)
+                .field("allowed_hosts", &self.allowed_hosts())
                 .field("listeners", &self.listeners_to_vec())
                 .finish()
         }
@@ -228,35 +239,203 @@ impl NvmfSubsystem {
         };
     }
 
-    // we currently allow all listeners to the subsystem
-    async fn add_listener(&self) -> Result<(), Error> {
-        extern "C" fn listen_cb(arg: *mut c_void, status: i32) {
-            let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
-            s.send(status).unwrap();
+    /// restrict access to the subsystem to the given host NQNs, an empty list
+    /// allows any host to connect. The subsystem must not be active; use
+    /// update_allowed_hosts() for subsystems that have been started.
+    pub fn set_allowed_hosts(&self, hosts: &[String]) -> Result<(), Error> {
+        for host in self.allowed_hosts() {
+            if hosts.contains(&host) {
+                continue;
+            }
+            let nqn = host.clone().into_cstring();
+            unsafe {
+                spdk_nvmf_subsystem_remove_host(self.0.as_ptr(), nqn.as_ptr())
+            }
+            .to_result(|e| Error::Subsystem {
+                source: Errno::from_i32(e),
+                nqn: self.get_nqn(),
+                msg: format!("failed to remove host {}", host),
+            })?;
         }
 
-        let cfg = Config::get();
+        let allowed = self.allowed_hosts();
+        for host in hosts.iter().filter(|h| !allowed.contains(h)) {
+            let nqn = host.clone().into_cstring();
+            unsafe {
+                spdk_nvmf_subsystem_add_host(self.0.as_ptr(), nqn.as_ptr())
+            }
+            .to_result(|e| Error::Subsystem {
+                source: Errno::from_i32(e),
+                nqn: self.get_nqn(),
+                msg: format!("failed to add host {}", host),
+            })?;
+        }
+
+        self.allow_any(hosts.is_empty());
+        Ok(())
+    }
 
-        // dont yet enable both ports, IOW just add one transportID now
+    /// change the hosts allowed to connect to a started subsystem, the
+    /// subsystem is paused for the duration of the change. Hosts that are
+    /// removed from the list keep the connections they already have.
+    pub async fn update_allowed_hosts(
+        &self,
+        hosts: &[String],
+    ) -> Result<(), Error> {
+        self.pause().await?;
+        let result = self.set_allowed_hosts(hosts);
+        self.resume().await?;
+        result
+    }
 
-        let trid_replica = TransportID::new(cfg.nexus_opts.nvmf_replica_port);
+    /// the host NQNs that are allowed to connect, when empty any host can
+    pub fn allowed_hosts(&self) -> Vec<String> {
+        let mut hosts = Vec::new();
+        unsafe {
+            let mut host = spdk_nvmf_subsystem_get_first_host(self.0.as_ptr());
+            while !host.is_null() {
+                hosts.push(spdk_nvmf_host_get_nqn(host).as_str().to_string());
+                host = spdk_nvmf_subsystem_get_next_host(self.0.as_ptr(), host);
+            }
+        }
+        hosts
+    }
+
+    /// report the ANA state of our listeners to hosts, allowing them to use
+    /// native NVMe multipath over the listeners of subsystems that share the
+    /// same NQN. The subsystem must not be active.
+    pub fn set_ana_reporting(&self, enable: bool) -> Result<(), Error> {
+        unsafe {
+            spdk_nvmf_subsystem_set_ana_reporting(self.0.as_ptr(), enable)
+        }
+        .to_result(|e| Error::Subsystem {
+            source: Errno::from_i32(e),
+            nqn: self.get_nqn(),
+            msg: "failed to set ANA reporting".into(),
+        })
+    }
+
+    /// the listeners of the subsystem, each with the ANA state reported to
+    /// the hosts that reach the subsystem through it
+    pub fn listener_ana_states(&self) -> Vec<(TransportID, u32)> {
+        let mut states = Vec::new();
+        unsafe {
+            let mut listener =
+                spdk_nvmf_subsystem_get_first_listener(self.0.as_ptr());
+            while !listener.is_null() {
+                states.push((
+                    TransportID(*spdk_nvmf_subsystem_listener_get_trid(
+                        listener,
+                    )),
+                    (*listener).ana_state,
+                ));
+                listener = spdk_nvmf_subsystem_get_next_listener(
+                    self.0.as_ptr(),
+                    listener,
+                );
+            }
+        }
+        states
+    }
 
+    /// change the ANA state of the given listener, hosts connected through
+    /// it are notified of the change by an asynchronous event
+    pub async fn set_ana_state(
+        &self,
+        trid: &TransportID,
+        ana_state: u32,
+    ) -> Result<(), Error> {
         let (s, r) = oneshot::channel::<i32>();
         unsafe {
-            spdk_nvmf_subsystem_add_listener(
+            spdk_nvmf_subsystem_set_ana_state(
                 self.0.as_ptr(),
-                trid_replica.as_ptr(),
-                Some(listen_cb),
+                trid.as_ptr(),
+                ana_state,
+                Some(Self::listen_cb),
                 cb_arg(s),
             );
         }
 
-        r.await.expect("listen a callback gone").to_result(|e| {
+        r.await.expect("ANA state callback gone").to_result(|e| {
+            Error::Subsystem {
+                source: Errno::from_i32(e),
+                nqn: self.get_nqn(),
+                msg: format!("failed to set ANA state on {}", trid),
+            }
+        })?;
+
+        info!(
+            "{}: ANA state of {} set to {}",
+            self.get_nqn(),
+            trid,
+            ana_state
+        );
+        Ok(())
+    }
+
+    extern "C" fn listen_cb(arg: *mut c_void, status: i32) {
+        let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
+        s.send(status).unwrap();
+    }
+
+    /// add a listener for the given transport to the subsystem, a subsystem
+    /// with several listeners is reachable through multiple paths. The
+    /// target starts listening on the transport if it did not already, and
+    /// the subsystem must be paused or inactive.
+    pub async fn add_listener(&self, trid: &TransportID) -> Result<(), Error> {
+        let tgt = NVMF_TGT.with(|t| t.borrow().tgt.as_ptr());
+        unsafe { spdk_nvmf_tgt_listen(tgt, trid.as_ptr()) }.to_result(|e| {
             Error::Transport {
                 source: Errno::from_i32(e),
-                msg: "Failed to add listener".to_string(),
+                msg: format!("Failed to listen on {}", trid),
             }
-        })
+        })?;
+
+        let (s, r) = oneshot::channel::<i32>();
+        unsafe {
+            spdk_nvmf_subsystem_add_listener(
+                self.0.as_ptr(),
+                trid.as_ptr(),
+                Some(Self::listen_cb),
+                cb_arg(s),
+            );
+        }
+
+        r.await
+            .expect("listen a callback gone")
+            .to_result(|e| Error::Transport {
+                source: Errno::from_i32(e),
+                msg: "Failed to add listener".to_string(),
+            })
+            .map_err(|e| {
+                unsafe { spdk_nvmf_tgt_stop_listen(tgt, trid.as_ptr()) };
+                e
+            })
+    }
+
+    /// remove the listener of the given transport from the subsystem, which
+    /// must be paused or inactive. The target stops listening on the
+    /// transport unless other subsystems still listen on it.
+    pub fn remove_listener(&self, trid: &TransportID) -> Result<(), Error> {
+        unsafe {
+            spdk_nvmf_subsystem_remove_listener(self.0.as_ptr(), trid.as_ptr())
+        }
+        .to_result(|e| Error::Transport {
+            source: Errno::from_i32(e),
+            msg: format!("Failed to remove listener {}", trid),
+        })?;
+
+        let tgt = NVMF_TGT.with(|t| t.borrow().tgt.as_ptr());
+        unsafe { spdk_nvmf_tgt_stop_listen(tgt, trid.as_ptr()) };
+        Ok(())
+    }
+
+    /// remove all listeners of an inactive subsystem
+    pub fn remove_listeners(&self) -> Result<(), Error> {
+        for trid in self.listeners_to_vec().unwrap_or_default() {
+            self.remove_listener(&trid)?;
+        }
+        Ok(())
     }
 
     /// start the subsystem previously created -- note that we destroy it on
@@ -281,7 +460,14 @@ impl NvmfSubsystem {
             s.send(status).unwrap();
         }
 
-        self.add_listener().await?;
+        // unless listeners were added explicitly, listen on the replica port
+        if self.listeners_to_vec().is_none() {
+            let cfg = Config::get();
+            self.add_listener(&TransportID::new(
+                cfg.nexus_opts.nvmf_replica_port,
+            ))
+            .await?;
+        }
 
         let (s, r) = oneshot::channel::<i32>();
 
@@ -349,10 +535,9 @@ impl NvmfSubsystem {
         Ok(())
     }
 
-    /// we are not making use of pause and resume yet but this will be needed
-    /// when we start to move things around
-    #[allow(dead_code)]
-    async fn pause(&self) -> Result<(), Error> {
+    /// pause the subsystem, outstanding IO is completed and new IO is queued
+    /// until the subsystem is resumed
+    pub async fn pause(&self) -> Result<(), Error> {
         extern "C" fn pause_cb(
             ss: *mut spdk_nvmf_subsystem,
             arg: *mut c_void,
@@ -393,8 +578,8 @@ impl NvmfSubsystem {
         })
     }
 
-    #[allow(dead_code)]
-    async fn resume(&self) -> Result<(), Error> {
+    /// resume the subsystem, completing the IO queued whilst paused
+    pub async fn resume(&self) -> Result<(), Error> {
         extern "C" fn resume_cb(
             ss: *mut spdk_nvmf_subsystem,
             arg: *mut c_void,
@@ -14,10 +14,12 @@ use serde::export::Formatter;
 
 use spdk_sys::{
     spdk_nvme_transport_id,
+    spdk_nvme_transport_id_compare,
     spdk_nvmf_tgt_add_transport,
     spdk_nvmf_transport_create,
     SPDK_NVME_TRANSPORT_TCP,
     SPDK_NVMF_ADRFAM_IPV4,
+    SPDK_NVMF_TRADDR_MAX_LEN,
     SPDK_NVMF_TRSVCID_MAX_LEN,
 };
 
@@ -86,12 +88,16 @@ impl DerefMut for TransportID {
 
 impl TransportID {
     pub fn new(port: u16) -> Self {
-        let address = get_ipv4_address().unwrap();
+        Self::with_address(&get_ipv4_address().unwrap(), port)
+    }
 
+    /// transport ID of the TCP listener on the given IPv4 address and port
+    pub fn with_address(address: &str, port: u16) -> Self {
         let mut trid: spdk_nvme_transport_id = Default::default();
         trid.trtype = SPDK_NVME_TRANSPORT_TCP;
         trid.adrfam = SPDK_NVMF_ADRFAM_IPV4;
 
+        assert!(address.len() < SPDK_NVMF_TRADDR_MAX_LEN as usize);
         let c_addr = address.into_cstring();
         let port = format!("{}", port);
 
@@ -121,6 +127,22 @@ impl TransportID {
     pub fn as_ptr(&self) -> *mut spdk_nvme_transport_id {
         &self.0 as *const _ as *mut spdk_nvme_transport_id
     }
+
+    /// the address the transport listens on
+    pub fn address(&self) -> String {
+        self.0.traddr.as_str().to_string()
+    }
+
+    /// the port the transport listens on
+    pub fn port(&self) -> u16 {
+        self.0.trsvcid.as_str().parse().unwrap_or_default()
+    }
+}
+
+impl PartialEq for TransportID {
+    fn eq(&self, other: &Self) -> bool {
+        unsafe { spdk_nvme_transport_id_compare(&self.0, &other.0) == 0 }
+    }
 }
 
 impl Display for TransportID {
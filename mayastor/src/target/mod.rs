@@ -0,0 +1,8 @@
+pub mod iscsi;
+pub mod nvmf;
+
+// Which kind of target interface to use for a bdev
+pub enum Side {
+    Nexus,
+    Replica,
+}
@@ -0,0 +1,80 @@
+use mayastor::{
+    core::{Bdev, MayastorCliArgs},
+    lvs::{Error, Lvol, Lvs},
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+use common::MayastorTest;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+static POOL_NAME: &str = "tpool";
+static REPLICA: &str = "snap-replica";
+static CLONE: &str = "snap-clone";
+
+const MB: u64 = 1024 * 1024;
+
+#[tokio::test]
+async fn lvol_snapshot_test() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    assert_eq!(
+        Lvol::parse_snapshot_name(&Lvol::format_snapshot_name(REPLICA, 42)),
+        Some((REPLICA, 42))
+    );
+    assert_eq!(Lvol::parse_snapshot_name(REPLICA), None);
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+        })
+        .await
+        .unwrap();
+
+        let lvol = pool.create_lvol(REPLICA, 8 * MB, false).await.unwrap();
+        let snapshot = lvol
+            .snapshot(&Lvol::format_snapshot_name(REPLICA, 42))
+            .await
+            .unwrap();
+        assert!(snapshot.is_snapshot());
+        assert_eq!(snapshot.size(), 8 * MB);
+        assert_eq!(snapshot.used(), 8 * MB);
+        // the clusters of the lvol now belong to its snapshot
+        assert_eq!(lvol.used(), 0);
+        assert!(snapshot.parent_snapshot().is_none());
+
+        // only snapshots can be cloned
+        assert!(matches!(
+            lvol.create_clone(CLONE).await,
+            Err(Error::NotASnapshot { .. })
+        ));
+
+        let clone = snapshot.create_clone(CLONE).await.unwrap();
+        assert!(clone.is_thin());
+        assert!(!clone.is_read_only());
+        assert_eq!(clone.size(), 8 * MB);
+        assert_eq!(clone.used(), 0);
+        assert_eq!(
+            clone.parent_snapshot().map(|s| s.name()),
+            Some(snapshot.name())
+        );
+
+        assert!(matches!(
+            snapshot.create_clone(CLONE).await,
+            Err(Error::RepExists { .. })
+        ));
+
+        clone.destroy().await.unwrap();
+        lvol.destroy().await.unwrap();
+        snapshot.destroy().await.unwrap();
+        assert!(Bdev::lookup_by_name(CLONE).is_none());
+
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
@@ -76,6 +76,7 @@ async fn lvs_pool_rpc() {
         .share_replica(ShareReplicaRequest {
             uuid: "cdc2a7db-3ac3-403a-af80-7fadc1581c47".to_string(),
             share: 1,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
@@ -85,6 +86,7 @@ async fn lvs_pool_rpc() {
         .share_replica(ShareReplicaRequest {
             uuid: "cdc2a7db-3ac3-403a-af80-7fadc1581c47".to_string(),
             share: 1,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
@@ -107,6 +109,7 @@ async fn lvs_pool_rpc() {
         .share_replica(ShareReplicaRequest {
             uuid: "cdc2a7db-3ac3-403a-af80-7fadc1581c47".to_string(),
             share: 0,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
@@ -0,0 +1,156 @@
+use std::time::SystemTime;
+
+use mayastor::{
+    bdev::{
+        nexus_events::{NexusEvent, NexusEventType},
+        ChildState,
+        NexusStatus,
+        Reason,
+    },
+    subsys::EventMessage,
+};
+use mbus_api::*;
+
+static NODE: &str = "mayastor-1";
+static UUID: &str = "d4f8b6c2-5b1a-4f5e-9a34-0e3c2b1d7a90";
+static CHILD: &str = "loopback:///replica-1";
+static POOL: &str = "pool-1";
+
+fn event(event: NexusEventType) -> NexusEvent {
+    NexusEvent {
+        epoch: 1,
+        seq: 1,
+        timestamp: SystemTime::now(),
+        nexus: format!("nexus-{}", UUID),
+        child: Some(CHILD.into()),
+        event,
+    }
+}
+
+fn faulted(reason: Reason) -> NexusEvent {
+    event(NexusEventType::ChildState {
+        old: ChildState::Open,
+        new: ChildState::Faulted(reason),
+    })
+}
+
+#[test]
+fn nexus_state_changed() {
+    let nexus = NexusEvent {
+        child: None,
+        ..event(NexusEventType::NexusStatus {
+            old: NexusStatus::Online,
+            new: NexusStatus::Degraded,
+        })
+    };
+
+    assert_eq!(
+        EventMessage::from_event(NODE, &nexus, None),
+        vec![EventMessage::NexusStateChanged(NexusStateChanged {
+            node: NODE.into(),
+            uuid: UUID.into(),
+            old_state: NexusState::Online,
+            new_state: NexusState::Degraded,
+        })]
+    );
+
+    // nexuses which were not created through gRPC keep their name
+    let nexus = NexusEvent {
+        nexus: "nexus0".into(),
+        ..nexus
+    };
+    match EventMessage::from_event(NODE, &nexus, None).as_slice() {
+        [EventMessage::NexusStateChanged(message)] => {
+            assert_eq!(message.uuid, "nexus0")
+        }
+        messages => panic!("unexpected messages {:?}", messages),
+    }
+}
+
+#[test]
+fn child_faulted() {
+    assert_eq!(
+        EventMessage::from_event(NODE, &faulted(Reason::Rpc), Some(POOL)),
+        vec![EventMessage::ChildFaulted(ChildFaulted {
+            node: NODE.into(),
+            uuid: UUID.into(),
+            uri: CHILD.into(),
+            reason: Reason::Rpc.to_string(),
+        })]
+    );
+
+    // a child that is out of sync is only degraded
+    assert!(
+        EventMessage::from_event(NODE, &faulted(Reason::OutOfSync), None)
+            .is_empty()
+    );
+
+    // other state changes of the children have no message
+    let open = event(NexusEventType::ChildState {
+        old: ChildState::Closed,
+        new: ChildState::Open,
+    });
+    assert!(EventMessage::from_event(NODE, &open, Some(POOL)).is_empty());
+}
+
+#[test]
+fn pool_degraded() {
+    let messages =
+        EventMessage::from_event(NODE, &faulted(Reason::IoError), Some(POOL));
+    assert_eq!(messages.len(), 2);
+    match &messages[0] {
+        EventMessage::PoolDegraded(message) => {
+            assert_eq!(message.node, NODE);
+            assert_eq!(message.name, POOL);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(matches!(messages[1], EventMessage::ChildFaulted(_)));
+
+    // the pools of other nodes are left to their own mayastor instance
+    match EventMessage::from_event(NODE, &faulted(Reason::IoError), None)
+        .as_slice()
+    {
+        [EventMessage::ChildFaulted(message)] => {
+            assert_eq!(message.reason, Reason::IoError.to_string())
+        }
+        messages => panic!("unexpected messages {:?}", messages),
+    }
+}
+
+#[test]
+fn rebuild() {
+    let started = event(NexusEventType::RebuildStarted);
+    assert_eq!(
+        EventMessage::from_event(NODE, &started, None),
+        vec![EventMessage::RebuildStarted(RebuildStarted {
+            node: NODE.into(),
+            uuid: UUID.into(),
+            uri: CHILD.into(),
+        })]
+    );
+
+    let completed = event(NexusEventType::RebuildCompleted);
+    assert_eq!(
+        EventMessage::from_event(NODE, &completed, None),
+        vec![EventMessage::RebuildCompleted(RebuildCompleted {
+            node: NODE.into(),
+            uuid: UUID.into(),
+            uri: CHILD.into(),
+        })]
+    );
+
+    let failed = event(NexusEventType::RebuildFailed);
+    assert_eq!(
+        EventMessage::from_event(NODE, &failed, None),
+        vec![EventMessage::RebuildFailed(RebuildFailed {
+            node: NODE.into(),
+            uuid: UUID.into(),
+            uri: CHILD.into(),
+        })]
+    );
+
+    // a stopped rebuild is resumed or followed by a state change
+    let stopped = event(NexusEventType::RebuildStopped);
+    assert!(EventMessage::from_event(NODE, &stopped, None).is_empty());
+}
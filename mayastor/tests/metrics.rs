@@ -0,0 +1,106 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+    time::Duration,
+};
+
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{BdevHandle, MayastorCliArgs},
+    lvs::Lvs,
+    metrics,
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "metrics_nexus";
+static POOL_NAME: &str = "metrics_pool";
+static METRICS_ENDPOINT: &str = "127.0.0.1:19502";
+
+/// issue a GET for the given path and return the raw response
+fn get(path: &str) -> String {
+    let mut stream = (0 .. 50)
+        .find_map(|_| {
+            TcpStream::connect(METRICS_ENDPOINT).ok().or_else(|| {
+                thread::sleep(Duration::from_millis(100));
+                None
+            })
+        })
+        .expect("metrics server is not listening");
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test]
+async fn metrics_test() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec!["malloc:///malloc0?size_mb=64".into()],
+        })
+        .await
+        .unwrap();
+        pool.create_lvol("metrics_replica", 8 * 1024 * 1024, false)
+            .await
+            .unwrap();
+
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[
+                "malloc:///malloc1?size_mb=64".into(),
+                "malloc:///malloc2?size_mb=64".into(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let hdl = BdevHandle::open(NEXUS_NAME, true, false).unwrap();
+        let buf = hdl.dma_malloc(4096).unwrap();
+        hdl.write_at(0, &buf).await.unwrap();
+        hdl.close();
+    })
+    .await;
+
+    let text = ms.spawn(metrics::collect()).await;
+    for line in &[
+        "# TYPE mayastor_pool_capacity_bytes gauge",
+        "mayastor_pool_used_bytes{pool=\"metrics_pool\"} 8388608",
+        "mayastor_replica_write_ops_total{replica=\"metrics_replica\",pool=\"metrics_pool\"} 0",
+        "mayastor_nexus_write_ops_total{nexus=\"metrics_nexus\"} 1",
+        "mayastor_nexus_written_bytes_total{nexus=\"metrics_nexus\"} 4096",
+        "mayastor_nexus_child_write_ops_total{nexus=\"metrics_nexus\",child=\"malloc:///malloc1?size_mb=64\"} 1",
+        "mayastor_nexus_child_state{nexus=\"metrics_nexus\",child=\"malloc:///malloc2?size_mb=64\",state=\"online\"} 1",
+        "mayastor_nexus_child_state{nexus=\"metrics_nexus\",child=\"malloc:///malloc2?size_mb=64\",state=\"faulted\"} 0",
+        "# TYPE mayastor_reactor_busy_seconds_total counter",
+    ] {
+        assert!(text.contains(line), "missing {} in:\n{}", line, text);
+    }
+    assert!(text.contains("mayastor_reactor_idle_seconds_total{core=\"0\"}"));
+
+    // the server only hands out what the collector gathered so far
+    metrics::MetricsServer::start(metrics::endpoint(METRICS_ENDPOINT.into()));
+    let response = get("/metrics");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("text/plain; version=0.0.4"));
+    assert!(get("/").starts_with("HTTP/1.1 404"));
+
+    ms.spawn(async {
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        Lvs::lookup(POOL_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,150 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::MayastorCliArgs,
+};
+use rpc::mayastor::{NvmeAnaState, ShareProtocolNexus};
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "ana_nexus";
+
+// the address and port of the second path to the nexus
+static LISTENER: (&str, u16) = ("127.0.0.1", 8440);
+
+#[tokio::test]
+async fn nexus_ana_state_test() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[
+                "malloc:///malloc0?size_mb=64".into(),
+                "malloc:///malloc1?size_mb=64".into(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        // there is no ANA state unless published over nvmf
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.get_ana_state(None).is_err());
+        assert!(nexus.listeners().is_err());
+        assert!(nexus
+            .set_ana_state(NvmeAnaState::NvmeAnaInaccessibleState, None)
+            .await
+            .is_err());
+    })
+    .await;
+
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus
+            .share(ShareProtocolNexus::NexusNvmf, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            nexus.get_ana_state(None).unwrap(),
+            NvmeAnaState::NvmeAnaOptimizedState
+        );
+
+        // drain the path through this node
+        nexus
+            .set_ana_state(NvmeAnaState::NvmeAnaInaccessibleState, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            nexus.get_ana_state(None).unwrap(),
+            NvmeAnaState::NvmeAnaInaccessibleState
+        );
+
+        nexus
+            .set_ana_state(NvmeAnaState::NvmeAnaNonOptimizedState, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            nexus.get_ana_state(None).unwrap(),
+            NvmeAnaState::NvmeAnaNonOptimizedState
+        );
+
+        assert!(nexus
+            .set_ana_state(NvmeAnaState::NvmeAnaInvalidState, None)
+            .await
+            .is_err());
+    })
+    .await;
+
+    // a second listener is a separate path with its own ANA state
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        let first = nexus.listeners().unwrap().pop().unwrap();
+        let first = (first.address.as_str(), first.port as u16);
+
+        let second = nexus
+            .add_listener(
+                LISTENER.0,
+                LISTENER.1,
+                NvmeAnaState::NvmeAnaOptimizedState,
+            )
+            .await
+            .unwrap();
+        assert_eq!(second.address, LISTENER.0);
+        assert_eq!(second.port, LISTENER.1 as u32);
+        assert_eq!(nexus.listeners().unwrap().len(), 2);
+        assert!(nexus
+            .add_listener(
+                LISTENER.0,
+                LISTENER.1,
+                NvmeAnaState::NvmeAnaOptimizedState,
+            )
+            .await
+            .is_err());
+
+        // drain the first path only
+        nexus
+            .set_ana_state(NvmeAnaState::NvmeAnaInaccessibleState, Some(first))
+            .await
+            .unwrap();
+        assert_eq!(
+            nexus.get_ana_state(Some(first)).unwrap(),
+            NvmeAnaState::NvmeAnaInaccessibleState
+        );
+        assert_eq!(
+            nexus.get_ana_state(Some(LISTENER)).unwrap(),
+            NvmeAnaState::NvmeAnaOptimizedState
+        );
+
+        // unknown listeners are rejected
+        assert!(nexus.get_ana_state(Some(("127.0.0.1", 1))).is_err());
+        assert!(nexus
+            .set_ana_state(
+                NvmeAnaState::NvmeAnaOptimizedState,
+                Some(("not-an-address", LISTENER.1))
+            )
+            .await
+            .is_err());
+
+        nexus.remove_listener(first.0, first.1).await.unwrap();
+        assert_eq!(
+            nexus
+                .listeners()
+                .unwrap()
+                .iter()
+                .map(|l| l.port)
+                .collect::<Vec<_>>(),
+            vec![LISTENER.1 as u32]
+        );
+        assert!(nexus.remove_listener(LISTENER.0, LISTENER.1).await.is_err());
+    })
+    .await;
+
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus.unshare_nexus().await.unwrap();
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
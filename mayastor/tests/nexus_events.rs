@@ -0,0 +1,86 @@
+use mayastor::{
+    bdev::{
+        nexus_create,
+        nexus_events::{self, NexusEventType},
+        nexus_lookup,
+        ChildState,
+        NexusStatus,
+        Reason,
+    },
+    core::MayastorCliArgs,
+};
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "events_nexus";
+static CHILD_1: &str = "malloc:///malloc0?size_mb=64";
+static CHILD_2: &str = "malloc:///malloc1?size_mb=64";
+
+#[tokio::test]
+async fn nexus_events_test() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[CHILD_1.into(), CHILD_2.into()],
+        )
+        .await
+        .unwrap();
+
+        // only the events from here on
+        let mut watch = nexus_events::watch(0, 0).unwrap();
+        assert!(watch.replay.is_empty());
+
+        nexus_lookup(NEXUS_NAME)
+            .unwrap()
+            .fault_child(CHILD_2, Reason::Rpc)
+            .await
+            .unwrap();
+
+        let child = watch.receiver.try_recv().unwrap();
+        assert_eq!(child.seq, watch.from_seq);
+        assert_eq!(child.epoch, watch.epoch);
+        assert_eq!(child.nexus, NEXUS_NAME);
+        assert_eq!(child.child.as_deref(), Some(CHILD_2));
+        assert_eq!(
+            child.event,
+            NexusEventType::ChildState {
+                old: ChildState::Open,
+                new: ChildState::Faulted(Reason::Rpc),
+            }
+        );
+
+        let nexus = watch.receiver.try_recv().unwrap();
+        assert_eq!(nexus.seq, child.seq + 1);
+        assert_eq!(nexus.child, None);
+        assert_eq!(
+            nexus.event,
+            NexusEventType::NexusStatus {
+                old: NexusStatus::Online,
+                new: NexusStatus::Degraded,
+            }
+        );
+        assert!(watch.receiver.try_recv().is_err());
+
+        // a watcher resuming from a sequence number has the events replayed
+        let resumed = nexus_events::watch(watch.epoch, child.seq).unwrap();
+        assert_eq!(
+            resumed.replay.iter().map(|e| e.seq).collect::<Vec<_>>(),
+            vec![child.seq, nexus.seq]
+        );
+
+        // events yet to happen cannot be resumed from
+        assert!(nexus_events::watch(watch.epoch, nexus.seq + 2).is_err());
+
+        // nor can the sequence numbers of another instance
+        assert!(nexus_events::watch(watch.epoch + 1, child.seq).is_err());
+        assert!(nexus_events::watch(0, child.seq).is_err());
+
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
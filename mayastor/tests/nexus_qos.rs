@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, NexusQos, QosLimit},
+    core::{BdevHandle, MayastorCliArgs},
+};
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "qos_nexus";
+
+const NUM_IOS: u64 = 64;
+const READ_IOPS: u64 = 100;
+
+/// issue a number of reads or writes to the nexus and return how long it took
+async fn io(write: bool) -> Duration {
+    let hdl = BdevHandle::open(NEXUS_NAME, true, false).unwrap();
+    let mut buf = hdl.dma_malloc(512).unwrap();
+    let start = Instant::now();
+    for i in 0 .. NUM_IOS {
+        if write {
+            hdl.write_at(i * 512, &buf).await.unwrap();
+        } else {
+            hdl.read_at(i * 512, &mut buf).await.unwrap();
+        }
+    }
+    hdl.close();
+    start.elapsed()
+}
+
+#[tokio::test]
+async fn nexus_qos_test() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[
+                "malloc:///malloc0?size_mb=64".into(),
+                "malloc:///malloc1?size_mb=64".into(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert_eq!(nexus.qos(), NexusQos::default());
+        assert_eq!(nexus.throttled_io(), (0, 0));
+
+        nexus.set_qos(NexusQos {
+            read_iops: QosLimit {
+                rate: READ_IOPS,
+                burst: 0,
+            },
+            ..Default::default()
+        });
+    })
+    .await;
+
+    // the reads exceed the limit and are held back, the writes are not
+    let elapsed = ms.spawn(io(false)).await;
+    assert!(
+        elapsed >= Duration::from_millis(NUM_IOS * 1000 / READ_IOPS / 2),
+        "reads took only {:?}",
+        elapsed
+    );
+    ms.spawn(io(true)).await;
+
+    let throttled = ms
+        .spawn(async {
+            let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+            let (reads, writes) = nexus.throttled_io();
+            assert!(reads > 0);
+            assert_eq!(writes, 0);
+
+            // lifting the limits stops the throttling
+            nexus.set_qos(NexusQos::default());
+            reads
+        })
+        .await;
+
+    ms.spawn(io(false)).await;
+
+    ms.spawn(async move {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert_eq!(nexus.throttled_io(), (throttled, 0));
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
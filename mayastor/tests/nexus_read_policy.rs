@@ -0,0 +1,98 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, ReadPolicy},
+    core::{Bdev, BdevHandle, MayastorCliArgs},
+};
+use rpc::mayastor::{BdevShareRequest, BdevUri, Null};
+
+pub mod common;
+use common::{Builder, MayastorTest};
+
+static NEXUS_NAME: &str = "read_policy_nexus";
+static LOCAL_CHILD: &str = "malloc0";
+
+const NUM_READS: u64 = 64;
+
+/// issue a number of reads to the nexus and return how many of them were
+/// served by the local child
+async fn local_reads() -> u64 {
+    let local = Bdev::lookup_by_name(LOCAL_CHILD).unwrap();
+    let before = local.stats().await.unwrap().num_read_ops;
+
+    let hdl = BdevHandle::open(NEXUS_NAME, true, false).unwrap();
+    let mut buf = hdl.dma_malloc(512).unwrap();
+    for i in 0 .. NUM_READS {
+        hdl.read_at(i * 512, &mut buf).await.unwrap();
+    }
+    hdl.close();
+
+    local.stats().await.unwrap().num_read_ops - before
+}
+
+#[tokio::test]
+async fn read_policy() {
+    let test = Builder::new()
+        .name("read_policy_test")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .with_clean(true)
+        .build()
+        .await
+        .unwrap();
+
+    let mut hdls = test.grpc_handles().await.unwrap();
+
+    // Create and share a bdev over nvmf
+    hdls[0].bdev.list(Null {}).await.unwrap();
+    hdls[0]
+        .bdev
+        .create(BdevUri {
+            uri: "malloc:///disk0?size_mb=100".into(),
+        })
+        .await
+        .unwrap();
+    hdls[0]
+        .bdev
+        .share(BdevShareRequest {
+            name: "disk0".into(),
+            proto: "nvmf".into(),
+        })
+        .await
+        .unwrap();
+
+    let mayastor = MayastorTest::new(MayastorCliArgs::default());
+    mayastor
+        .spawn(async move {
+            // Create a nexus with a local child and a remote child
+            nexus_create(
+                NEXUS_NAME,
+                1024 * 1024 * 50,
+                None,
+                &[
+                    format!(
+                        "malloc:///{}?blk_size=512&size_mb=100",
+                        LOCAL_CHILD
+                    ),
+                    format!(
+                        "nvmf://{}:8420/nqn.2019-05.io.openebs:disk0",
+                        hdls[0].endpoint.ip()
+                    ),
+                ],
+            )
+            .await
+            .unwrap();
+
+            let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+            assert_eq!(local_reads().await, NUM_READS / 2);
+
+            nexus.set_read_policy(ReadPolicy::PreferLocal);
+            assert_eq!(local_reads().await, NUM_READS);
+
+            // with reads issued one at a time there is never more than a
+            // single read outstanding, so ties are rotated
+            nexus.set_read_policy(ReadPolicy::LeastQueueDepth);
+            assert_eq!(local_reads().await, NUM_READS / 2);
+
+            nexus.destroy().await.unwrap();
+        })
+        .await;
+}
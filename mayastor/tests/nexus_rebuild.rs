@@ -7,8 +7,14 @@ use tracing::error;
 use common::error_bdev;
 use mayastor::{
     bdev::{nexus_lookup, ChildState, Reason, VerboseError},
-    core::{MayastorCliArgs, MayastorEnvironment, Mthread, Reactor},
-    rebuild::{RebuildJob, RebuildState, SEGMENT_SIZE},
+    core::{Bdev, MayastorCliArgs, MayastorEnvironment, Mthread, Reactor},
+    rebuild::{
+        ClientOperations,
+        RebuildJob,
+        RebuildPriority,
+        RebuildState,
+        SEGMENT_SIZE,
+    },
 };
 use rpc::mayastor::ShareProtocolNexus;
 
@@ -436,6 +442,190 @@ fn rebuild_segment_sizes() {
     test_fini();
 }
 
+#[test]
+// only the regions written to whilst the child was offline are rebuilt
+fn rebuild_dirty_regions() {
+    test_ini("rebuild_dirty_regions");
+
+    Reactor::block_on(async {
+        nexus_create(NEXUS_SIZE, 2, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+
+        nexus.offline_child(&get_dev(1)).await.unwrap();
+
+        // write a single block within the second segment of the nexus
+        let hdl = Bdev::lookup_by_name(nexus_name())
+            .unwrap()
+            .open(true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = hdl.dma_malloc(512).unwrap();
+        buf.fill(0xff);
+        hdl.write_at(SEGMENT_SIZE + 512, &buf).await.unwrap();
+        drop(hdl);
+
+        nexus.online_child(&get_dev(1)).await.unwrap();
+        let stats =
+            RebuildJob::lookup(&get_dev(1)).unwrap().as_client().stats();
+        assert_eq!(stats.blocks_total, SEGMENT_SIZE / 512);
+
+        nexus_test_child(1).await;
+        nexus.destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+// a rebuild takes at least as long as the global bandwidth limit allows
+fn rebuild_rate_limit() {
+    test_ini("rebuild_rate_limit");
+
+    Reactor::block_on(async {
+        nexus_create(NEXUS_SIZE, 1, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+
+        RebuildJob::set_global_rate(1);
+        let start = std::time::Instant::now();
+        nexus.add_child(&get_dev(1), false).await.unwrap();
+
+        // the source is the only healthy child
+        assert_eq!(
+            RebuildJob::lookup(&get_dev(1)).unwrap().priority(),
+            RebuildPriority::High
+        );
+
+        common::wait_for_rebuild(
+            get_dev(1),
+            RebuildState::Completed,
+            std::time::Duration::from_secs(20),
+        )
+        .unwrap();
+        RebuildJob::set_global_rate(0);
+
+        // the first MiB is copied at once, the remaining 4 at 1 MiB/s
+        assert!(start.elapsed() >= std::time::Duration::from_secs(3));
+
+        nexus_test_child(1).await;
+        nexus.destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+// a rebuild interrupted by the destruction of the nexus resumes from its
+// checkpoint once the nexus has been created again
+fn rebuild_resume() {
+    test_ini_large_nexus("rebuild_resume");
+
+    Reactor::block_on(async {
+        nexus_create(LARGE_NEXUS_SIZE, 1, false).await;
+        nexus_add_child(1, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+
+        reactor_poll!(100);
+        nexus.pause_rebuild(&get_dev(1)).await.unwrap();
+        common::wait_for_rebuild(
+            get_dev(1),
+            RebuildState::Paused,
+            std::time::Duration::from_millis(1000),
+        )
+        .unwrap();
+        let stats =
+            RebuildJob::lookup(&get_dev(1)).unwrap().as_client().stats();
+        assert!(stats.blocks_recovered > 0);
+        assert!(stats.blocks_recovered < stats.blocks_total);
+        nexus.destroy().await.unwrap();
+
+        // the child comes back out of sync, as it would after a restart
+        nexus_create(LARGE_NEXUS_SIZE, 2, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus
+            .fault_child(&get_dev(1), Reason::OutOfSync)
+            .await
+            .unwrap();
+        nexus.start_rebuild(&get_dev(1)).await.unwrap();
+
+        let resumed =
+            RebuildJob::lookup(&get_dev(1)).unwrap().as_client().stats();
+        assert!(
+            resumed.blocks_total <= stats.blocks_total - stats.blocks_recovered
+        );
+        assert!(resumed.blocks_total > 0);
+
+        nexus_test_child(1).await;
+        nexus.destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+// a rebuild stopped whilst the nexus is live resumes from its checkpoint and
+// also copies what has been written since it stopped
+fn rebuild_resume_stopped() {
+    test_ini_large_nexus("rebuild_resume_stopped");
+
+    Reactor::block_on(async {
+        nexus_create(LARGE_NEXUS_SIZE, 1, false).await;
+        nexus_add_child(1, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+
+        reactor_poll!(100);
+        nexus.pause_rebuild(&get_dev(1)).await.unwrap();
+        common::wait_for_rebuild(
+            get_dev(1),
+            RebuildState::Paused,
+            std::time::Duration::from_millis(1000),
+        )
+        .unwrap();
+        let stats =
+            RebuildJob::lookup(&get_dev(1)).unwrap().as_client().stats();
+        assert!(stats.blocks_recovered > 0);
+        assert!(stats.blocks_recovered < stats.blocks_total);
+
+        nexus.stop_rebuild(&get_dev(1)).await.unwrap();
+        common::wait_for_rebuild(
+            get_dev(1),
+            RebuildState::Stopped,
+            std::time::Duration::from_millis(1000),
+        )
+        .unwrap();
+        // the job is removed once its checkpoint has been kept
+        while RebuildJob::lookup(&get_dev(1)).is_ok() {
+            reactor_poll!(1);
+        }
+
+        // the first block has been copied before the job stopped
+        let hdl = Bdev::lookup_by_name(nexus_name())
+            .unwrap()
+            .open(true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = hdl.dma_malloc(512).unwrap();
+        buf.fill(0xff);
+        hdl.write_at(0, &buf).await.unwrap();
+        drop(hdl);
+
+        nexus.start_rebuild(&get_dev(1)).await.unwrap();
+        let resumed =
+            RebuildJob::lookup(&get_dev(1)).unwrap().as_client().stats();
+        assert!(
+            resumed.blocks_total
+                <= stats.blocks_total - stats.blocks_recovered
+                    + SEGMENT_SIZE / 512
+        );
+
+        nexus_test_child(1).await;
+        nexus.destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
 #[test]
 fn rebuild_lookup() {
     test_ini("rebuild_lookup");
@@ -469,31 +659,26 @@ fn rebuild_lookup() {
             .expect("now the job should exist")
             .source
             .clone();
+        assert_eq!(
+            RebuildJob::lookup(&get_dev(children))
+                .unwrap()
+                .sources
+                .len() as u64,
+            children
+        );
 
+        // the segments are read from all the healthy children
         for child in 0 .. children {
-            if get_dev(child) != src {
+            assert_eq!(
                 RebuildJob::lookup_src(&get_dev(child))
                     .iter()
-                    .filter(|s| s.destination != get_dev(child))
                     .inspect(|&job| {
-                        error!(
-                            "Job {:?} should be associated with src child {}",
-                            job, child
-                        );
+                        assert_eq!(job.destination, get_dev(children));
                     })
-                    .any(|_| panic!("Should not have found any jobs!"));
-            }
+                    .count(),
+                1
+            );
         }
-
-        assert_eq!(
-            RebuildJob::lookup_src(&src)
-                .iter()
-                .inspect(|&job| {
-                    assert_eq!(job.destination, get_dev(children));
-                })
-                .count(),
-            1
-        );
         nexus.add_child(&get_dev(children + 1), true).await.unwrap();
         let _ = nexus.start_rebuild(&get_dev(children + 1)).await.unwrap();
         assert_eq!(RebuildJob::lookup_src(&src).len(), 2);
@@ -646,6 +831,60 @@ fn rebuild_fault_src() {
     test_fini();
 }
 
+#[test]
+// the reads that fail on one source are retried on the other, the failing
+// source is faulted once the rebuild completes
+fn rebuild_retry_src() {
+    test_ini("rebuild_retry_src");
+    set_err_dev(0);
+
+    Reactor::block_on(async {
+        nexus_create(NEXUS_SIZE, 2, false).await;
+
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus.add_child(&get_dev(2), false).await.unwrap();
+        assert_eq!(RebuildJob::lookup(&get_dev(2)).unwrap().sources.len(), 2);
+
+        error_bdev::inject_error(
+            &get_err_dev(0),
+            error_bdev::SPDK_BDEV_IO_TYPE_READ,
+            error_bdev::VBDEV_IO_FAILURE,
+            88,
+        );
+
+        // the job completes from the other source, after which the failing
+        // source is faulted
+        common::wait_for_rebuild(
+            get_dev(2),
+            RebuildState::Completed,
+            std::time::Duration::from_secs(20),
+        )
+        .unwrap();
+        // allow the nexus futures to run
+        reactor_poll!(10);
+        assert_eq!(nexus.children[2].state(), ChildState::Open);
+        assert_eq!(
+            nexus.children[0].state(),
+            ChildState::Faulted(Reason::IoError)
+        );
+
+        let (s, r) = unbounded::<String>();
+        std::thread::spawn(move || {
+            s.send(common::compare_devices(
+                &get_disk(1),
+                &get_disk(2),
+                nexus.size(),
+                true,
+            ))
+        });
+        reactor_poll!(r);
+
+        nexus_lookup(nexus_name()).unwrap().destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
 #[test]
 fn rebuild_fault_dst() {
     test_ini("rebuild_fault_dst");
@@ -0,0 +1,140 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{BdevHandle, MayastorCliArgs, Share},
+    lvs::{Lvol, Lvs},
+    rebuild::RebuildState,
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+use common::MayastorTest;
+
+static POOL_NAME: &str = "thin_pool";
+static NEXUS_NAME: &str = "thin_nexus";
+static NVMF_NEXUS_NAME: &str = "thin_nvmf_nexus";
+
+static LVOLS: [&str; 2] = ["thin-vol-0", "thin-vol-1"];
+static NVMF_LVOLS: [&str; 2] = ["thin-vol-2", "thin-vol-3"];
+
+const MB: u64 = 1024 * 1024;
+
+#[tokio::test]
+async fn nexus_rebuild_thin_test() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // only the start of the nexus is written to
+    let used = ms
+        .spawn(async {
+            let pool = Lvs::create_or_import(CreatePoolRequest {
+                name: POOL_NAME.into(),
+                disks: vec!["malloc:///malloc0?size_mb=256".into()],
+            })
+            .await
+            .unwrap();
+
+            for name in LVOLS.iter() {
+                pool.create_lvol(name, 64 * MB, true).await.unwrap();
+            }
+
+            nexus_create(
+                NEXUS_NAME,
+                48 * MB,
+                None,
+                &[format!("loopback:///{}", LVOLS[0])],
+            )
+            .await
+            .unwrap();
+
+            let hdl = BdevHandle::open(NEXUS_NAME, true, false).unwrap();
+            let mut buf = hdl.dma_malloc(MB).unwrap();
+            buf.fill(0xa5);
+            hdl.write_at(0, &buf).await.unwrap();
+            hdl.close();
+
+            pool.used()
+        })
+        .await;
+
+    // the rebuilt child only allocates what the source has allocated
+    ms.spawn(async move {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        let child = format!("loopback:///{}", LVOLS[1]);
+        nexus.add_child(&child, true).await.unwrap();
+        let state = nexus.start_rebuild(&child).await.unwrap().await.unwrap();
+        assert_eq!(state, RebuildState::Completed);
+
+        let pool = Lvs::lookup(POOL_NAME).unwrap();
+        assert!(pool.used() <= 2 * used);
+
+        let hdl = BdevHandle::open(LVOLS[1], false, false).unwrap();
+        let mut buf = hdl.dma_malloc(MB).unwrap();
+        let block_len = hdl.get_bdev().block_len() as u64;
+        hdl.read_at(nexus.data_ent_offset * block_len, &mut buf)
+            .await
+            .unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0xa5));
+        hdl.close();
+    })
+    .await;
+
+    // the allocation of a source shared over nvmf is queried from its target
+    ms.spawn(async {
+        let pool = Lvs::lookup(POOL_NAME).unwrap();
+        for name in NVMF_LVOLS.iter() {
+            pool.create_lvol(name, 64 * MB, true).await.unwrap();
+        }
+
+        let source = lookup_lvol(NVMF_LVOLS[0]);
+        source.share_nvmf().await.unwrap();
+        let uri = source.share_uri().unwrap();
+        nexus_create(NVMF_NEXUS_NAME, 48 * MB, None, &[uri])
+            .await
+            .unwrap();
+
+        let hdl = BdevHandle::open(NVMF_NEXUS_NAME, true, false).unwrap();
+        let mut buf = hdl.dma_malloc(MB).unwrap();
+        buf.fill(0x5a);
+        hdl.write_at(0, &buf).await.unwrap();
+        hdl.close();
+
+        let nexus = nexus_lookup(NVMF_NEXUS_NAME).unwrap();
+        let child = format!("loopback:///{}", NVMF_LVOLS[1]);
+        nexus.add_child(&child, true).await.unwrap();
+        let state = nexus.start_rebuild(&child).await.unwrap().await.unwrap();
+        assert_eq!(state, RebuildState::Completed);
+
+        let destination = lookup_lvol(NVMF_LVOLS[1]);
+        assert!(destination.used() <= source.used());
+
+        let hdl = BdevHandle::open(NVMF_LVOLS[1], false, false).unwrap();
+        let mut buf = hdl.dma_malloc(MB).unwrap();
+        let block_len = hdl.get_bdev().block_len() as u64;
+        hdl.read_at(nexus.data_ent_offset * block_len, &mut buf)
+            .await
+            .unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0x5a));
+        hdl.close();
+    })
+    .await;
+
+    ms.spawn(async {
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        nexus_lookup(NVMF_NEXUS_NAME)
+            .unwrap()
+            .destroy()
+            .await
+            .unwrap();
+        lookup_lvol(NVMF_LVOLS[0]).unshare().await.unwrap();
+        Lvs::lookup(POOL_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
+
+fn lookup_lvol(name: &str) -> Lvol {
+    Lvs::lookup(POOL_NAME)
+        .unwrap()
+        .lvols()
+        .unwrap()
+        .find(|l| l.name() == name)
+        .unwrap()
+}
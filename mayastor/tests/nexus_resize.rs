@@ -0,0 +1,110 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{Bdev, MayastorCliArgs},
+    lvs::Lvs,
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+use common::MayastorTest;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+static POOL_NAME: &str = "tpool";
+static NEXUS_NAME: &str = "resize_nexus";
+
+static LVOLS: [&str; 2] = ["resize-vol-0", "resize-vol-1"];
+
+const MB: u64 = 1024 * 1024;
+
+#[tokio::test]
+async fn nexus_resize_test() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 128 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+        })
+        .await
+        .unwrap();
+
+        for name in LVOLS.iter() {
+            pool.create_lvol(name, 16 * MB, false).await.unwrap();
+        }
+
+        let children = LVOLS
+            .iter()
+            .map(|name| format!("loopback:///{}", name))
+            .collect::<Vec<_>>();
+        nexus_create(NEXUS_NAME, 8 * MB, None, &children)
+            .await
+            .unwrap();
+    })
+    .await;
+
+    // growing within the size of the children leaves the lvols alone
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus.resize(10 * MB).await.unwrap();
+        assert_eq!(nexus.size(), 10 * MB);
+
+        for name in LVOLS.iter() {
+            let bdev = Bdev::lookup_by_name(name).unwrap();
+            assert_eq!(bdev.size_in_bytes(), 16 * MB);
+        }
+    })
+    .await;
+
+    // growing beyond the size of the children grows the lvols as well and
+    // relocates the backup label to the new end of each child
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus.resize(32 * MB).await.unwrap();
+        assert_eq!(nexus.size(), 32 * MB);
+
+        for name in LVOLS.iter() {
+            let bdev = Bdev::lookup_by_name(name).unwrap();
+            assert!(bdev.size_in_bytes() > 32 * MB);
+        }
+
+        for child in nexus.children.iter() {
+            assert!(child.probe_label().await.is_ok());
+        }
+    })
+    .await;
+
+    // the pool has room to grow either lvol but not both, so neither is
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        let sizes = LVOLS
+            .iter()
+            .map(|name| Bdev::lookup_by_name(name).unwrap().size_in_bytes())
+            .collect::<Vec<_>>();
+
+        assert!(nexus.resize(64 * MB).await.is_err());
+        assert_eq!(nexus.size(), 32 * MB);
+        for (name, size) in LVOLS.iter().zip(sizes) {
+            let bdev = Bdev::lookup_by_name(name).unwrap();
+            assert_eq!(bdev.size_in_bytes(), size);
+        }
+    })
+    .await;
+
+    // shrinking is refused
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.resize(16 * MB).await.is_err());
+        assert_eq!(nexus.size(), 32 * MB);
+    })
+    .await;
+
+    ms.spawn(async {
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        Lvs::lookup(POOL_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
@@ -0,0 +1,94 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{BdevHandle, MayastorCliArgs},
+    rebuild::RebuildState,
+};
+use rpc::mayastor::ScrubMismatch;
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "scrub_nexus";
+static CHILD_1: &str = "malloc:///malloc0?blk_size=512&size_mb=16";
+static CHILD_2: &str = "malloc:///malloc1?blk_size=512&size_mb=16";
+
+// block of the nexus at which the children are made to diverge
+const DIVERGENT_BLOCK: u64 = 1000;
+
+#[tokio::test]
+async fn nexus_scrub_test() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // write to the second child behind the back of the nexus
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            8 * 1024 * 1024,
+            None,
+            &[CHILD_1.into(), CHILD_2.into()],
+        )
+        .await
+        .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+
+        let hdl = BdevHandle::open("malloc1", true, false).unwrap();
+        let mut buf = hdl.dma_malloc(2 * 512).unwrap();
+        buf.fill(0xa5);
+        hdl.write_at((nexus.data_ent_offset + DIVERGENT_BLOCK) * 512, &buf)
+            .await
+            .unwrap();
+        hdl.close();
+    })
+    .await;
+
+    // a scrub without an authority only reports the divergent blocks
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        let state = nexus.start_scrub(None).await.unwrap().await.unwrap();
+        assert_eq!(state, RebuildState::Completed);
+
+        let progress = nexus.get_scrub_progress().unwrap();
+        assert_eq!(progress.progress, 100);
+        assert_eq!(
+            progress.mismatches,
+            vec![ScrubMismatch {
+                offset: DIVERGENT_BLOCK,
+                num_blocks: 2,
+                repaired: false,
+            }]
+        );
+    })
+    .await;
+
+    // the authority is used to repair the other child
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.start_scrub(Some("malloc:///unknown")).await.is_err());
+
+        let state = nexus
+            .start_scrub(Some(CHILD_1))
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(state, RebuildState::Completed);
+        assert_eq!(
+            nexus.get_scrub_progress().unwrap().mismatches,
+            vec![ScrubMismatch {
+                offset: DIVERGENT_BLOCK,
+                num_blocks: 2,
+                repaired: true,
+            }]
+        );
+
+        let state = nexus.start_scrub(None).await.unwrap().await.unwrap();
+        assert_eq!(state, RebuildState::Completed);
+        assert!(nexus.get_scrub_progress().unwrap().mismatches.is_empty());
+    })
+    .await;
+
+    ms.spawn(async {
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
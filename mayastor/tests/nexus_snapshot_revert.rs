@@ -0,0 +1,131 @@
+use std::convert::TryFrom;
+
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, ChildState, Reason},
+    core::{Bdev, MayastorCliArgs},
+    lvs::{Lvol, Lvs},
+};
+use rpc::mayastor::{CreatePoolRequest, ShareProtocolNexus};
+
+pub mod common;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+static POOL_NAME: &str = "tpool";
+static NEXUS_NAME: &str = "revert_nexus";
+
+static LVOLS: [&str; 2] = ["revert-vol-0", "revert-vol-1"];
+static MALLOC: &str = "malloc:///malloc0?size_mb=16";
+
+const MB: u64 = 1024 * 1024;
+
+#[tokio::test]
+async fn nexus_snapshot_revert_test() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 128 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+        })
+        .await
+        .unwrap();
+
+        let mut children = Vec::new();
+        for name in LVOLS.iter() {
+            pool.create_lvol(name, 16 * MB, false).await.unwrap();
+            children.push(format!("loopback:///{}", name));
+        }
+        children.push(MALLOC.to_string());
+
+        nexus_create(NEXUS_NAME, 8 * MB, None, &children)
+            .await
+            .unwrap();
+    })
+    .await;
+
+    let first = ms
+        .spawn(async {
+            bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+            let reply = nexus_lookup(NEXUS_NAME)
+                .unwrap()
+                .create_snapshot()
+                .await
+                .unwrap();
+
+            // the malloc child cannot be snapshotted
+            assert!(!reply.complete);
+            assert_eq!(reply.children.iter().filter(|c| c.created).count(), 2);
+
+            bdev_io::write_some(NEXUS_NAME, 0, 0x55).await.unwrap();
+            bdev_io::read_some(NEXUS_NAME, 0, 0x55).await.unwrap();
+            reply.timestamp
+        })
+        .await;
+
+    // snapshot names have a resolution of a second
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    let second = ms
+        .spawn(async {
+            let reply = nexus_lookup(NEXUS_NAME)
+                .unwrap()
+                .create_snapshot()
+                .await
+                .unwrap();
+
+            bdev_io::write_some(NEXUS_NAME, 0, 0x77).await.unwrap();
+            reply.timestamp
+        })
+        .await;
+
+    // the hosts of the nexus are paused whilst it is reverted
+    ms.spawn(async move {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus
+            .share(ShareProtocolNexus::NexusNvmf, None)
+            .await
+            .unwrap();
+        assert!(nexus.revert_to_snapshot(second + 1).await.is_err());
+
+        nexus.revert_to_snapshot(first).await.unwrap();
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        // the replicas were created thick and remain so
+        for name in LVOLS.iter() {
+            let lvol =
+                Lvol::try_from(Bdev::lookup_by_name(name).unwrap()).unwrap();
+            assert!(!lvol.is_thin());
+        }
+
+        // the malloc child lacks the snapshot
+        let states =
+            nexus.children.iter().map(|c| c.state()).collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            vec![
+                ChildState::Open,
+                ChildState::Open,
+                ChildState::Faulted(Reason::SnapshotMissing)
+            ]
+        );
+
+        // the later snapshot is still there to revert to
+        nexus.revert_to_snapshot(second).await.unwrap();
+        bdev_io::read_some(NEXUS_NAME, 0, 0x55).await.unwrap();
+
+        nexus.unshare_nexus().await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async {
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        Lvs::lookup(POOL_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
@@ -0,0 +1,90 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{BdevHandle, MayastorCliArgs},
+};
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "stats_nexus";
+
+const NUM_IOS: u64 = 32;
+
+#[tokio::test]
+async fn nexus_stats_test() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[
+                "malloc:///malloc0?size_mb=64".into(),
+                "malloc:///malloc1?size_mb=64".into(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let stats = nexus_lookup(NEXUS_NAME).unwrap().stats_to_grpc();
+        assert_eq!(stats.stats.unwrap().num_write_ops, 0);
+        assert_eq!(stats.children.len(), 2);
+    })
+    .await;
+
+    // the bdev layer may have examined the nexus, only count our own reads
+    let before = ms
+        .spawn(async {
+            let stats = nexus_lookup(NEXUS_NAME).unwrap().stats_to_grpc();
+            let children = stats
+                .children
+                .iter()
+                .map(|c| c.stats.clone().unwrap().num_read_ops)
+                .sum::<u64>();
+            (stats.stats.unwrap().num_read_ops, children)
+        })
+        .await;
+
+    ms.spawn(async {
+        let hdl = BdevHandle::open(NEXUS_NAME, true, false).unwrap();
+        let mut buf = hdl.dma_malloc(4096).unwrap();
+        for i in 0 .. NUM_IOS {
+            hdl.write_at(i * 4096, &buf).await.unwrap();
+        }
+        for i in 0 .. NUM_IOS {
+            hdl.read_at(i * 4096, &mut buf).await.unwrap();
+        }
+        hdl.close();
+    })
+    .await;
+
+    ms.spawn(async move {
+        let stats = nexus_lookup(NEXUS_NAME).unwrap().stats_to_grpc();
+
+        let io = stats.stats.unwrap();
+        assert_eq!(io.num_write_ops, NUM_IOS);
+        assert_eq!(io.bytes_written, NUM_IOS * 4096);
+        assert_eq!(io.num_read_ops - before.0, NUM_IOS);
+        assert_eq!(io.num_retried_ops, 0);
+
+        let latency = io.write_latency.unwrap();
+        assert!(latency.p50_us <= latency.p99_us);
+        assert!(latency.p99_us <= latency.p999_us);
+
+        // every write goes to all children, the reads are spread over them
+        let children = stats
+            .children
+            .iter()
+            .map(|c| c.stats.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert!(children.iter().all(|c| c.num_write_ops == NUM_IOS));
+        assert_eq!(
+            children.iter().map(|c| c.num_read_ops).sum::<u64>() - before.1,
+            NUM_IOS
+        );
+
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,126 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{Bdev, MayastorCliArgs, Protocol, Share},
+    lvs::{Lvs, PropName, PropValue},
+};
+use rpc::mayastor::{CreatePoolRequest, ShareProtocolNexus};
+
+pub mod common;
+use common::MayastorTest;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+static POOL_NAME: &str = "tpool";
+static NEXUS_NAME: &str = "hosts_nexus";
+static LVOL_NAME: &str = "hosts-vol";
+
+static NEXUS_HOST: &str = "nqn.2019-05.io.openebs:node-1";
+static APP_HOST: &str = "nqn.2019-05.io.openebs:node-2";
+
+#[tokio::test]
+async fn nvmf_allowed_hosts_test() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // a replica accepts only the hosts it was shared to
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+        })
+        .await
+        .unwrap();
+
+        let lvol = pool
+            .create_lvol(LVOL_NAME, 16 * 1024 * 1024, false)
+            .await
+            .unwrap();
+
+        let bdev = Bdev::lookup_by_name(LVOL_NAME).unwrap();
+        lvol.set_allowed_hosts(&[NEXUS_HOST.into()]).await.unwrap();
+        lvol.share_nvmf().await.unwrap();
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+        assert_eq!(bdev.allowed_hosts(), vec![NEXUS_HOST]);
+
+        // the list can be changed whilst shared
+        lvol.set_allowed_hosts(&[NEXUS_HOST.into(), APP_HOST.into()])
+            .await
+            .unwrap();
+        assert_eq!(bdev.allowed_hosts(), vec![NEXUS_HOST, APP_HOST]);
+
+        lvol.set_allowed_hosts(&[NEXUS_HOST.into()]).await.unwrap();
+        assert_eq!(bdev.allowed_hosts(), vec![NEXUS_HOST]);
+        assert_eq!(
+            lvol.get(PropName::AllowedHosts).await.unwrap(),
+            PropValue::AllowedHosts(vec![NEXUS_HOST.into()])
+        );
+
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    // the allowed hosts are restored when the pool is imported
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+        })
+        .await
+        .unwrap();
+
+        let lvol = pool.lvols().unwrap().find(|l| l.name() == LVOL_NAME);
+        let lvol = lvol.unwrap();
+        let bdev = Bdev::lookup_by_name(LVOL_NAME).unwrap();
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+        assert_eq!(bdev.allowed_hosts(), vec![NEXUS_HOST]);
+
+        // an empty list opens the replica up to any host again
+        lvol.set_allowed_hosts(&[]).await.unwrap();
+        assert!(bdev.allowed_hosts().is_empty());
+        lvol.unshare().await.unwrap();
+    })
+    .await;
+
+    // a nexus accepts only the host the workload runs on
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            8 * 1024 * 1024,
+            None,
+            &[format!("loopback:///{}", LVOL_NAME)],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus
+            .set_allowed_hosts(vec![APP_HOST.into()])
+            .await
+            .unwrap();
+        nexus
+            .share(ShareProtocolNexus::NexusNvmf, None)
+            .await
+            .unwrap();
+
+        let bdev = Bdev::lookup_by_name(NEXUS_NAME).unwrap();
+        assert_eq!(bdev.allowed_hosts(), vec![APP_HOST]);
+
+        // moving the workload to another node
+        nexus
+            .set_allowed_hosts(vec![NEXUS_HOST.into()])
+            .await
+            .unwrap();
+        assert_eq!(bdev.allowed_hosts(), vec![NEXUS_HOST]);
+
+        nexus.unshare_nexus().await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async {
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        Lvs::lookup(POOL_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
@@ -0,0 +1,88 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{MayastorCliArgs, MayastorEnvironment},
+};
+use rpc::mayastor::{
+    CreatePoolRequest,
+    CreateReplicaRequest,
+    ShareProtocolReplica,
+    ShareReplicaRequest,
+};
+
+pub mod common;
+use common::{Builder, MayastorTest};
+
+static NEXUS_NAME: &str = "host_nqn_nexus";
+static UUID: &str = "1e3ea1d4-4b5b-4e84-9e4a-1b1c3c6bd6c1";
+
+static FOREIGN_HOST: &str = "nqn.2019-05.io.openebs:node-name:foreign";
+
+#[tokio::test]
+async fn nvmf_host_nqn_test() {
+    let test = Builder::new()
+        .name("nvmf_host_nqn_test")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .with_clean(true)
+        .build()
+        .await
+        .unwrap();
+
+    let mut hdls = test.grpc_handles().await.unwrap();
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    let host_nqn = ms
+        .spawn(async { MayastorEnvironment::global_or_default().host_nqn() })
+        .await;
+
+    // the replica only accepts the node of the nexus
+    hdls[0]
+        .mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+        })
+        .await
+        .unwrap();
+    hdls[0]
+        .mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: UUID.into(),
+            pool: "tpool".into(),
+            size: 32 * 1024 * 1024,
+            thin: false,
+            share: ShareProtocolReplica::ReplicaNone as i32,
+        })
+        .await
+        .unwrap();
+    let uri = hdls[0]
+        .mayastor
+        .share_replica(ShareReplicaRequest {
+            uuid: UUID.into(),
+            share: ShareProtocolReplica::ReplicaNvmf as i32,
+            allowed_hosts: vec![host_nqn],
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .uri;
+
+    ms.spawn(async move {
+        // any other host is turned away
+        assert!(nexus_create(
+            NEXUS_NAME,
+            16 * 1024 * 1024,
+            None,
+            &[format!("{}?hostnqn={}", uri, FOREIGN_HOST)],
+        )
+        .await
+        .is_err());
+
+        // whereas the nexus connects with the NQN of its node
+        nexus_create(NEXUS_NAME, 16 * 1024 * 1024, None, &[uri])
+            .await
+            .unwrap();
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
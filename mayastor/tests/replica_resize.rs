@@ -0,0 +1,110 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, Nexus},
+    core::MayastorCliArgs,
+    lvs::{Error, Lvs},
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+use common::MayastorTest;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+static POOL_NAME: &str = "tpool";
+static NEXUS_NAME: &str = "resize_nexus";
+
+const MB: u64 = 1024 * 1024;
+
+#[tokio::test]
+async fn replica_resize_test() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+        })
+        .await
+        .unwrap();
+    })
+    .await;
+
+    // thick lvols can grow as long as the pool has room for them
+    ms.spawn(async {
+        let pool = Lvs::lookup(POOL_NAME).unwrap();
+        let lvol = pool.create_lvol("thick", 8 * MB, false).await.unwrap();
+
+        lvol.resize(16 * MB).await.unwrap();
+        assert_eq!(lvol.size(), 16 * MB);
+
+        assert!(matches!(
+            lvol.resize(128 * MB).await,
+            Err(Error::RepResizeNoSpace { .. })
+        ));
+        assert_eq!(lvol.size(), 16 * MB);
+
+        let available = pool.available();
+        lvol.resize(8 * MB).await.unwrap();
+        assert_eq!(lvol.size(), 8 * MB);
+        assert!(pool.available() > available);
+
+        // the nexus labels must still fit along with some data
+        assert!(matches!(
+            lvol.resize(MB).await,
+            Err(Error::RepShrinkTooSmall { .. })
+        ));
+        assert!(matches!(
+            lvol.resize(Nexus::DATA_PARTITION_OFFSET).await,
+            Err(Error::RepShrinkTooSmall { .. })
+        ));
+        assert_eq!(lvol.size(), 8 * MB);
+
+        lvol.destroy().await.unwrap();
+    })
+    .await;
+
+    // a nexus on top of the lvol keeps its data partition
+    ms.spawn(async {
+        let pool = Lvs::lookup(POOL_NAME).unwrap();
+        let lvol = pool.create_lvol("child", 16 * MB, false).await.unwrap();
+        nexus_create(NEXUS_NAME, 8 * MB, None, &["loopback:///child".into()])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            lvol.resize(12 * MB).await,
+            Err(Error::RepShrinkInUse { .. })
+        ));
+        assert_eq!(lvol.size(), 16 * MB);
+
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        lvol.resize(12 * MB).await.unwrap();
+        assert_eq!(lvol.size(), 12 * MB);
+
+        lvol.destroy().await.unwrap();
+    })
+    .await;
+
+    // thin lvols are not limited by the free space of the pool
+    ms.spawn(async {
+        let pool = Lvs::lookup(POOL_NAME).unwrap();
+        let lvol = pool.create_lvol("thin", 8 * MB, true).await.unwrap();
+
+        lvol.resize(128 * MB).await.unwrap();
+        assert_eq!(lvol.size(), 128 * MB);
+
+        lvol.resize(16 * MB).await.unwrap();
+        assert_eq!(lvol.size(), 16 * MB);
+
+        lvol.destroy().await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async {
+        Lvs::lookup(POOL_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
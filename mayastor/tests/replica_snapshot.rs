@@ -2,7 +2,7 @@ use std::{io, io::Write, process::Command, thread, time};
 
 use common::{bdev_io, ms_exec::MayastorProcess};
 use mayastor::{
-    bdev::nexus_create,
+    bdev::{nexus_create, nexus_lookup},
     core::{
         mayastor_env_stop,
         BdevHandle,
@@ -152,6 +152,20 @@ fn replica_snapshot() {
         bdev_io::read_some(NXNAME, 0, 0x55).await.unwrap();
         bdev_io::read_some(NXNAME_SNAP, 0, 0xff).await.unwrap();
         bdev_io::read_some(NXNAME_SNAP, 1024, 0).await.unwrap();
+        // Snapshot both replicas through the nexus, snapshot names have a
+        // resolution of a second
+        thread::sleep(time::Duration::from_secs(1));
+        let reply = nexus_lookup(NXNAME)
+            .unwrap()
+            .create_snapshot()
+            .await
+            .unwrap();
+        assert!(reply.complete);
+        assert_eq!(reply.children.len(), 2);
+        assert!(reply.children.iter().all(|c| c.created));
+        assert!(reply.children.iter().all(|c| c.name.starts_with(UUID1)));
+        bdev_io::write_some(NXNAME, 0, 0xaa).await.unwrap();
+        bdev_io::read_some(NXNAME, 0, 0xaa).await.unwrap();
     });
     mayastor_env_stop(0);
 
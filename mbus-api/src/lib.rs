@@ -28,6 +28,14 @@ pub enum Channel {
     Registry,
     /// Keep it In Sync Service
     Kiiss,
+    /// State changes of nexuses, their children and rebuilds
+    Events,
+    /// Node Service
+    Node,
+    /// Pool Service
+    Pool,
+    /// Volume Service
+    Volume,
     /// Reply to requested Channel
     Reply(String),
 }
@@ -40,6 +48,10 @@ impl FromStr for Channel {
             "default" => Ok(Self::Default),
             "registry" => Ok(Self::Registry),
             "kiiss" => Ok(Self::Kiiss),
+            "events" => Ok(Self::Events),
+            "node" => Ok(Self::Node),
+            "pool" => Ok(Self::Pool),
+            "volume" => Ok(Self::Volume),
             _ => Err(format!("Could not parse the channel: {}", source)),
         }
     }
@@ -57,6 +69,10 @@ impl std::fmt::Display for Channel {
             Channel::Default => write!(f, "default"),
             Channel::Registry => write!(f, "registry"),
             Channel::Kiiss => write!(f, "kiiss"),
+            Channel::Events => write!(f, "events"),
+            Channel::Node => write!(f, "node"),
+            Channel::Pool => write!(f, "pool"),
+            Channel::Volume => write!(f, "volume"),
             Channel::Reply(ch) => write!(f, "{}", ch),
         }
     }
@@ -78,6 +94,36 @@ pub enum MessageId {
     Register,
     /// Deregister mayastor
     Deregister,
+    /// State of a nexus changed
+    NexusStateChanged,
+    /// A nexus child has been faulted
+    ChildFaulted,
+    /// Rebuild of a nexus child started
+    RebuildStarted,
+    /// Rebuild of a nexus child completed
+    RebuildCompleted,
+    /// Rebuild of a nexus child failed
+    RebuildFailed,
+    /// A pool has experienced a failure
+    PoolDegraded,
+    /// Get all the nodes
+    GetNodes,
+    /// Get pools with filter
+    GetPools,
+    /// Create Pool
+    CreatePool,
+    /// Destroy Pool
+    DestroyPool,
+    /// Get replicas with filter
+    GetReplicas,
+    /// Create Replica
+    CreateReplica,
+    /// Destroy Replica
+    DestroyReplica,
+    /// Create Volume
+    CreateVolume,
+    /// Destroy Volume
+    DestroyVolume,
 }
 
 /// Sender identification (eg which mayastor instance sent the message)
@@ -107,6 +153,10 @@ pub struct ConfigUpdate {
     pub kind: Config,
     /// actual config data
     pub data: Vec<u8>,
+    /// revision of the config which the update is based on, the update is
+    /// rejected if that is no longer the current revision
+    /// None updates the config unconditionally
+    pub revision: Option<u64>,
 }
 bus_impl_message_all!(ConfigUpdate, ConfigUpdate, (), Kiiss);
 
@@ -122,6 +172,9 @@ pub struct ConfigGetCurrent {
 pub struct ReplyConfig {
     /// config data
     pub config: Vec<u8>,
+    /// revision of the config, which every update increments by one
+    #[serde(default)]
+    pub revision: u64,
 }
 bus_impl_message_all!(
     ConfigGetCurrent,
@@ -152,10 +205,378 @@ pub struct Deregister {
 }
 bus_impl_message_all!(Deregister, Deregister, (), Registry);
 
+/// Node Service
+
+/// Get all the nodes
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct GetNodes {}
+
+/// State of the Node
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum NodeState {
+    /// state is not known
+    Unknown,
+    /// the node has registered within the keep alive deadline
+    Online,
+    /// the node has missed the keep alive deadline or it deregistered
+    Offline,
+}
+
+impl Default for NodeState {
+    fn default() -> Self {
+        Self::Unknown
+    }
+}
+
+/// Node information
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    /// id of the mayastor instance
+    pub id: String,
+    /// grpc_endpoint of the mayastor instance
+    pub grpc_endpoint: String,
+    /// deemed state of the node
+    pub state: NodeState,
+}
+
+/// All the nodes
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct Nodes(pub Vec<Node>);
+bus_impl_message_all!(GetNodes, GetNodes, Nodes, Node);
+
+/// Filter Objects based on one of the following criteria
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Filter {
+    /// All objects
+    None,
+    /// Filter by Node id
+    Node(String),
+    /// Pool filters
+    ///
+    /// Filter by Pool name
+    Pool(String),
+    /// Filter by Node id and Pool name
+    NodePool(String, String),
+}
+impl Default for Filter {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// Pool Service
+
+/// Get all the pools from specific node or None for all nodes
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct GetPools {
+    /// Filter request
+    pub filter: Filter,
+}
+
+/// State of the Pool
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PoolState {
+    /// unknown state
+    Unknown,
+    /// the pool is in normal working order
+    Online,
+    /// the pool has experienced a failure but can still function
+    Degraded,
+    /// the pool is completely inaccessible
+    Faulted,
+}
+
+impl Default for PoolState {
+    fn default() -> Self {
+        Self::Unknown
+    }
+}
+
+/// Pool information
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Pool {
+    /// id of the mayastor instance
+    pub node: String,
+    /// name of the pool
+    pub name: String,
+    /// absolute disk paths claimed by the pool
+    pub disks: Vec<String>,
+    /// current state of the pool
+    pub state: PoolState,
+    /// size of the pool in bytes
+    pub capacity: u64,
+    /// used bytes from the pool
+    pub used: u64,
+}
+
+/// All the pools
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct Pools(pub Vec<Pool>);
+bus_impl_message_all!(GetPools, GetPools, Pools, Pool);
+
+/// Create Pool Request
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePool {
+    /// id of the mayastor instance
+    pub node: String,
+    /// name of the pool
+    pub name: String,
+    /// disk device paths or URIs to be claimed by the pool
+    pub disks: Vec<String>,
+}
+bus_impl_message_all!(CreatePool, CreatePool, Pool, Pool);
+
+/// Destroy Pool Request
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DestroyPool {
+    /// id of the mayastor instance
+    pub node: String,
+    /// name of the pool
+    pub name: String,
+}
+bus_impl_message_all!(DestroyPool, DestroyPool, (), Pool);
+
+/// Get all the replicas from specific node and pool
+/// or None for all nodes or all pools
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct GetReplicas {
+    /// Filter request
+    pub filter: Filter,
+}
+
+/// Protocol over which a replica is shared
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Protocol {
+    /// not shared by any of the variants
+    Off,
+    /// shared as NVMe-oF TCP
+    Nvmf,
+    /// shared as iSCSI
+    Iscsi,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self::Off
+    }
+}
+
+/// Replica information
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Replica {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the replica
+    pub uuid: String,
+    /// name of the pool
+    pub pool: String,
+    /// thin provisioning
+    pub thin: bool,
+    /// size of the replica in bytes
+    pub size: u64,
+    /// protocol used for exposing the replica
+    pub share: Protocol,
+    /// uri usable by nexus to access it
+    pub uri: String,
+}
+
+/// All the replicas
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct Replicas(pub Vec<Replica>);
+bus_impl_message_all!(GetReplicas, GetReplicas, Replicas, Pool);
+
+/// Create Replica Request
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateReplica {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the replica
+    pub uuid: String,
+    /// name of the pool
+    pub pool: String,
+    /// size of the replica in bytes
+    pub size: u64,
+    /// thin provisioning
+    pub thin: bool,
+    /// protocol to expose the replica over
+    pub share: Protocol,
+}
+bus_impl_message_all!(CreateReplica, CreateReplica, Replica, Pool);
+
+/// Destroy Replica Request
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DestroyReplica {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the replica
+    pub uuid: String,
+}
+bus_impl_message_all!(DestroyReplica, DestroyReplica, (), Pool);
+
+/// Volume Service
+
+/// Create a volume, placing its replicas on distinct nodes and creating the
+/// nexus on the node of the first replica
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateVolume {
+    /// uuid of the volume, shared by its nexus and replicas
+    pub uuid: String,
+    /// size of the volume in bytes
+    pub size: u64,
+    /// number of replicas
+    pub replicas: u64,
+    /// protocol to publish the nexus over, not published if Off
+    pub protocol: Protocol,
+}
+
+/// Volume information
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Volume {
+    /// uuid of the volume
+    pub uuid: String,
+    /// size of the volume in bytes
+    pub size: u64,
+    /// id of the mayastor instance hosting the nexus
+    pub node: String,
+    /// protocol the nexus is published over
+    pub protocol: Protocol,
+    /// uri of the published nexus, empty if not published
+    pub device_uri: String,
+    /// replicas of the volume
+    pub replicas: Vec<Replica>,
+}
+bus_impl_message_all!(CreateVolume, CreateVolume, Volume, Volume);
+
+/// Destroy a volume, with its nexus and all of its replicas
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DestroyVolume {
+    /// uuid of the volume
+    pub uuid: String,
+}
+bus_impl_message_all!(DestroyVolume, DestroyVolume, (), Volume);
+
+/// Events
+
+/// State of a nexus
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum NexusState {
+    /// state is not known
+    Unknown,
+    /// healthy and working
+    Online,
+    /// able to serve IO but not healthy, i.e. a rebuild is in progress
+    Degraded,
+    /// unable to serve IO
+    Faulted,
+}
+impl Default for NexusState {
+    fn default() -> Self {
+        NexusState::Unknown
+    }
+}
+
+/// The state of a nexus changed
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NexusStateChanged {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the nexus
+    pub uuid: String,
+    /// state before the change
+    pub old_state: NexusState,
+    /// state after the change
+    pub new_state: NexusState,
+}
+bus_impl_message_all!(NexusStateChanged, NexusStateChanged, (), Events);
+
+/// A nexus child has been faulted
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChildFaulted {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the nexus
+    pub uuid: String,
+    /// uri of the child
+    pub uri: String,
+    /// why the child has been faulted
+    pub reason: String,
+}
+bus_impl_message_all!(ChildFaulted, ChildFaulted, (), Events);
+
+/// Rebuild of a nexus child started
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RebuildStarted {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the nexus
+    pub uuid: String,
+    /// uri of the child being rebuilt
+    pub uri: String,
+}
+bus_impl_message_all!(RebuildStarted, RebuildStarted, (), Events);
+
+/// Rebuild of a nexus child completed, the child is now online
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RebuildCompleted {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the nexus
+    pub uuid: String,
+    /// uri of the rebuilt child
+    pub uri: String,
+}
+bus_impl_message_all!(RebuildCompleted, RebuildCompleted, (), Events);
+
+/// Rebuild of a nexus child failed, the child is faulted
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RebuildFailed {
+    /// id of the mayastor instance
+    pub node: String,
+    /// uuid of the nexus
+    pub uuid: String,
+    /// uri of the child that failed to rebuild
+    pub uri: String,
+}
+bus_impl_message_all!(RebuildFailed, RebuildFailed, (), Events);
+
+/// A pool has experienced a failure but can still function, i.e. IO to one
+/// of its replicas failed
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolDegraded {
+    /// id of the mayastor instance
+    pub node: String,
+    /// name of the pool
+    pub name: String,
+    /// what failed
+    pub reason: String,
+}
+bus_impl_message_all!(PoolDegraded, PoolDegraded, (), Events);
+
 /// This trait defines all Bus Messages which must:
 /// 1 - be uniquely identifiable via MessageId
 /// 2 - have a default Channel on which they are sent/received
-#[async_trait(?Send)]
+#[async_trait]
 pub trait Message {
     /// type which is sent back in response to a request
     type Reply;
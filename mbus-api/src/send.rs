@@ -135,7 +135,7 @@ macro_rules! bus_impl_message {
         bus_impl_message!($S, $I, $R, $C, $S);
     };
     ($S:ident, $I:ident, $R:tt, $C:ident, $T:ident) => {
-        #[async_trait::async_trait(?Send)]
+        #[async_trait::async_trait]
         impl Message for $S {
             type Reply = $R;
 
@@ -165,11 +165,11 @@ macro_rules! bus_impl_message {
 /// a `channel` and requesting a response back with the payload type `R`
 /// via a specific reply channel.
 /// Trait can be implemented using the macro helper `bus_impl_request`.
-#[async_trait(?Send)]
+#[async_trait]
 pub trait MessageRequest<'a, S, R>
 where
     S: 'a + Sync + Message + Serialize,
-    for<'de> R: Deserialize<'de> + Default + 'a + Sync,
+    for<'de> R: Deserialize<'de> + Default + 'a + Sync + Send,
 {
     /// Sends the message and requests a reply
     /// May fail if the bus fails to publish the message.
@@ -201,11 +201,11 @@ where
 /// Trait to send a message `bus` publish with the `payload` type `S` via a
 /// a `channel`. No reply is requested.
 /// Trait can be implemented using the macro helper `bus_impl_publish`.
-#[async_trait(?Send)]
+#[async_trait]
 pub trait MessagePublish<'a, S, R>
 where
     S: 'a + Sync + Message + Serialize,
-    for<'de> R: Deserialize<'de> + Default + 'a + Sync,
+    for<'de> R: Deserialize<'de> + Default + 'a + Sync + Send,
 {
     /// Publishes the Message - not guaranteed to be sent or received (fire and
     /// forget)
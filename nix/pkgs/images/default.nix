@@ -122,4 +122,40 @@ rec {
     contents = [ busybox mayastor ];
     config = { Entrypoint = [ "/bin/kiiss" ]; };
   });
+
+  services-node-image = dockerTools.buildLayeredImage (servicesImageProps // {
+    name = "mayadata/services-node";
+    contents = [ busybox mayastor ];
+    config = { Entrypoint = [ "/bin/node" ]; };
+  });
+
+  services-node-dev-image = dockerTools.buildImage (servicesImageProps // {
+    name = "mayadata/services-node-dev";
+    contents = [ busybox mayastor ];
+    config = { Entrypoint = [ "/bin/node" ]; };
+  });
+
+  services-pool-image = dockerTools.buildLayeredImage (servicesImageProps // {
+    name = "mayadata/services-pool";
+    contents = [ busybox mayastor ];
+    config = { Entrypoint = [ "/bin/pool" ]; };
+  });
+
+  services-pool-dev-image = dockerTools.buildImage (servicesImageProps // {
+    name = "mayadata/services-pool-dev";
+    contents = [ busybox mayastor ];
+    config = { Entrypoint = [ "/bin/pool" ]; };
+  });
+
+  services-volume-image = dockerTools.buildLayeredImage (servicesImageProps // {
+    name = "mayadata/services-volume";
+    contents = [ busybox mayastor ];
+    config = { Entrypoint = [ "/bin/volume" ]; };
+  });
+
+  services-volume-dev-image = dockerTools.buildImage (servicesImageProps // {
+    name = "mayadata/services-volume-dev";
+    contents = [ busybox mayastor ];
+    config = { Entrypoint = [ "/bin/volume" ]; };
+  });
 }
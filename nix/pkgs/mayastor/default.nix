@@ -104,6 +104,9 @@ in
       ../../../target/debug/mayastor-client
       ../../../target/debug/jsonrpc
       ../../../target/debug/kiiss
+      ../../../target/debug/node
+      ../../../target/debug/pool
+      ../../../target/debug/volume
     ];
 
     buildInputs = [
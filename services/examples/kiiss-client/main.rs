@@ -28,6 +28,7 @@ async fn client() {
     ConfigUpdate {
         kind: Config::MayastorConfig,
         data: "My config...".into(),
+        revision: None,
     }
     .request()
     .await
@@ -44,7 +45,8 @@ async fn client() {
     .unwrap();
 
     info!(
-        "Received config: {:?}",
-        std::str::from_utf8(&config.config).unwrap()
+        "Received config: {:?}, revision {}",
+        std::str::from_utf8(&config.config).unwrap(),
+        config.revision
     );
 }
@@ -1,12 +1,15 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod store;
+
 use async_trait::async_trait;
 use common::*;
-use log::info;
+use log::{error, info, warn};
 use mbus_api::*;
 use smol::io;
-use std::{collections::HashMap, convert::TryInto, marker::PhantomData};
+use std::{convert::TryInto, marker::PhantomData, path::PathBuf};
+use store::{FileStore, MemoryStore, Store, StoreError, StoredConfig};
 use structopt::StructOpt;
 use tokio::sync::Mutex;
 
@@ -17,6 +20,11 @@ struct CliArgs {
     /// Default: nats://127.0.0.1:4222
     #[structopt(long, short, default_value = "nats://127.0.0.1:4222")]
     url: String,
+
+    /// File in which the configs are stored, so that they survive a restart
+    /// of the service. The configs are only kept in memory if not set
+    #[structopt(long, short, parse(from_os_str))]
+    store: Option<PathBuf>,
 }
 
 /// Needed so we can implement the ServiceSubscriber trait for
@@ -26,13 +34,35 @@ struct ServiceHandler<T> {
     data: PhantomData<T>,
 }
 
-#[derive(Default)]
 struct ConfigState {
-    state: Mutex<HashMap<SenderId, HashMap<Config, Vec<u8>>>>,
+    store: Mutex<Box<dyn Store>>,
+}
+
+impl Default for ConfigState {
+    fn default() -> Self {
+        Self {
+            store: Mutex::new(Box::new(MemoryStore::default())),
+        }
+    }
+}
+
+impl ConfigState {
+    /// keep the configs in the store given on the command line, if any
+    async fn open(&self, cli_args: &CliArgs) -> Result<(), StoreError> {
+        match &cli_args.store {
+            Some(path) => {
+                *self.store.lock().await = Box::new(FileStore::open(path)?);
+            }
+            None => {
+                warn!("No store given, the configs are lost on restart");
+            }
+        }
+        Ok(())
+    }
 }
 
 lazy_static! {
-    static ref CONFIGS: ConfigState = Default::default();
+    static ref CONFIGS: ConfigState = ConfigState::default();
 }
 
 #[async_trait]
@@ -44,20 +74,38 @@ impl ServiceSubscriber for ServiceHandler<ConfigUpdate> {
         let msg: ReceivedMessage<ConfigUpdate, ()> = args.request.try_into()?;
         let config = msg.inner();
 
-        let mut state = CONFIGS.state.lock().await;
-
-        match state.get_mut(&msg.sender()) {
-            Some(map) => {
-                map.insert(config.kind, config.data);
-            }
-            None => {
-                let mut config_map = HashMap::new();
-                config_map.insert(config.kind, config.data);
-                state.insert(msg.sender(), config_map);
+        let mut store = CONFIGS.store.lock().await;
+
+        let revision = store
+            .get(&msg.sender(), &config.kind)
+            .map_or(0, |current| current.revision);
+        if let Some(expected) = config.revision {
+            if expected != revision {
+                return msg
+                    .reply(Err(Error::WithMessage {
+                        message: format!(
+                            "Stale config update based on revision {}, the \
+                             current revision is {}",
+                            expected, revision
+                        ),
+                    }))
+                    .await;
             }
         }
 
-        msg.reply(()).await
+        let result = store
+            .put(
+                &msg.sender(),
+                &config.kind,
+                StoredConfig {
+                    revision: revision + 1,
+                    data: config.data,
+                },
+            )
+            .map_err(|error| Error::WithMessage {
+                message: error.to_string(),
+            });
+        msg.reply(result).await
     }
     fn filter(&self) -> Vec<MessageId> {
         vec![ConfigUpdate::default().id()]
@@ -74,23 +122,16 @@ impl ServiceSubscriber for ServiceHandler<ConfigGetCurrent> {
             args.request.try_into()?;
         let request = msg.inner();
 
-        let state = CONFIGS.state.lock().await;
-
-        match state.get(&msg.sender()) {
-            Some(config) => match config.get(&request.kind) {
-                Some(data) => {
-                    msg.reply(ReplyConfig {
-                        config: data.clone(),
-                    })
-                    .await
-                }
-                None => {
-                    msg.reply(Err(Error::WithMessage {
-                        message: "Config is missing".into(),
-                    }))
-                    .await
-                }
-            },
+        let store = CONFIGS.store.lock().await;
+
+        match store.get(&msg.sender(), &request.kind) {
+            Some(config) => {
+                msg.reply(ReplyConfig {
+                    config: config.data,
+                    revision: config.revision,
+                })
+                .await
+            }
             None => {
                 msg.reply(Err(Error::WithMessage {
                     message: "Config is missing".into(),
@@ -136,6 +177,12 @@ async fn main() {
     let cli_args = CliArgs::from_args();
     info!("Using options: {:?}", &cli_args);
 
+    // open the store before any of the requests come in
+    if let Err(error) = CONFIGS.open(&cli_args).await {
+        error!("{}", error);
+        std::process::exit(1);
+    }
+
     server(cli_args).await;
 }
 
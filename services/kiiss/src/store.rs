@@ -0,0 +1,383 @@
+//! Backing stores for the configs which the nodes keep with kiiss.
+//!
+//! The file store is an append-only log with a JSON record for every update,
+//! which is replayed when the store is opened. Only the latest revision of a
+//! config is of interest, so the log is rewritten without the superseded
+//! records whenever it is opened.
+
+use log::warn;
+use mbus_api::{Config, SenderId};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Snafu)]
+pub(crate) enum StoreError {
+    #[snafu(display("Failed to open store '{}': {}", path.display(), source))]
+    Open { path: PathBuf, source: io::Error },
+    #[snafu(display(
+        "Record {} of store '{}' is corrupt: {}",
+        record,
+        path.display(),
+        source
+    ))]
+    Corrupt {
+        path: PathBuf,
+        record: usize,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Failed to write to store '{}': {}", path.display(), source))]
+    Write { path: PathBuf, source: io::Error },
+}
+
+/// a config together with its revision
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub(crate) struct StoredConfig {
+    pub(crate) revision: u64,
+    pub(crate) data: Vec<u8>,
+}
+
+pub(crate) trait Store: Send {
+    /// the config of the given kind stored for the node, if any
+    fn get(&self, node: &str, kind: &Config) -> Option<StoredConfig>;
+    /// store the config of the given kind for the node, replacing the
+    /// previous one
+    fn put(
+        &mut self,
+        node: &str,
+        kind: &Config,
+        config: StoredConfig,
+    ) -> Result<(), StoreError>;
+}
+
+/// store which is lost when the service restarts
+#[derive(Default)]
+pub(crate) struct MemoryStore {
+    configs: HashMap<SenderId, HashMap<Config, StoredConfig>>,
+}
+
+impl MemoryStore {
+    fn insert(&mut self, node: &str, kind: &Config, config: StoredConfig) {
+        self.configs
+            .entry(node.to_string())
+            .or_default()
+            .insert(kind.clone(), config);
+    }
+
+    /// number of configs stored across all the nodes
+    fn len(&self) -> usize {
+        self.configs.values().map(HashMap::len).sum()
+    }
+
+    fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        self.configs.iter().flat_map(|(node, configs)| {
+            configs.iter().map(move |(kind, config)| Record {
+                node: node.clone(),
+                kind: kind.clone(),
+                config: config.clone(),
+            })
+        })
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, node: &str, kind: &Config) -> Option<StoredConfig> {
+        self.configs.get(node)?.get(kind).cloned()
+    }
+
+    fn put(
+        &mut self,
+        node: &str,
+        kind: &Config,
+        config: StoredConfig,
+    ) -> Result<(), StoreError> {
+        self.insert(node, kind, config);
+        Ok(())
+    }
+}
+
+/// record appended to the log for every update
+#[derive(Serialize, Deserialize)]
+struct Record {
+    node: SenderId,
+    kind: Config,
+    config: StoredConfig,
+}
+
+/// store which persists the configs in a file
+pub(crate) struct FileStore {
+    path: PathBuf,
+    log: File,
+    configs: MemoryStore,
+}
+
+impl FileStore {
+    /// open the store at `path`, creating it if it does not exist
+    pub(crate) fn open(path: &Path) -> Result<Self, StoreError> {
+        let mut configs = MemoryStore::default();
+        let mut records = 0;
+
+        match File::open(path) {
+            Ok(file) => {
+                let lines = BufReader::new(file)
+                    .lines()
+                    .collect::<Result<Vec<_>, _>>()
+                    .context(Open {
+                        path,
+                    })?;
+                for (i, line) in lines.iter().enumerate() {
+                    match serde_json::from_str::<Record>(line) {
+                        Ok(record) => {
+                            configs.insert(
+                                &record.node,
+                                &record.kind,
+                                record.config,
+                            );
+                            records += 1;
+                        }
+                        // a record torn by a crash while it was appended
+                        Err(error) if i + 1 == lines.len() => {
+                            warn!(
+                                "Dropping the incomplete last record of \
+                                 store '{}': {}",
+                                path.display(),
+                                error
+                            );
+                            records += 1;
+                        }
+                        Err(source) => {
+                            return Err(StoreError::Corrupt {
+                                path: path.into(),
+                                record: i + 1,
+                                source,
+                            })
+                        }
+                    }
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => {
+                return Err(StoreError::Open {
+                    path: path.into(),
+                    source,
+                })
+            }
+        }
+
+        if records > configs.len() {
+            Self::compact(path, &configs)?;
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(Open {
+                path,
+            })?;
+
+        Ok(Self {
+            path: path.into(),
+            log,
+            configs,
+        })
+    }
+
+    /// rewrite the log with a single record per config, the new log only
+    /// replaces the old one once it is complete
+    fn compact(path: &Path, configs: &MemoryStore) -> Result<(), StoreError> {
+        let compacted = path.with_extension("compact");
+        let write = || -> io::Result<()> {
+            let mut file = File::create(&compacted)?;
+            for record in configs.records() {
+                serde_json::to_writer(&mut file, &record)?;
+                file.write_all(b"\n")?;
+            }
+            file.sync_all()?;
+            fs::rename(&compacted, path)
+        };
+        write().context(Write {
+            path,
+        })
+    }
+}
+
+impl Store for FileStore {
+    fn get(&self, node: &str, kind: &Config) -> Option<StoredConfig> {
+        self.configs.get(node, kind)
+    }
+
+    fn put(
+        &mut self,
+        node: &str,
+        kind: &Config,
+        config: StoredConfig,
+    ) -> Result<(), StoreError> {
+        let record = Record {
+            node: node.to_string(),
+            kind: kind.clone(),
+            config,
+        };
+        let mut line = serde_json::to_vec(&record)
+            .map_err(io::Error::from)
+            .context(Write {
+                path: &self.path,
+            })?;
+        line.push(b'\n');
+
+        // the update only takes effect once it is on disk
+        self.log
+            .write_all(&line)
+            .and_then(|_| self.log.sync_data())
+            .context(Write {
+                path: &self.path,
+            })?;
+
+        self.configs.insert(node, kind, record.config);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// path of a store in the temporary directory, removed when dropped
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "kiiss-{}-{}.log",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn config(revision: u64, data: &[u8]) -> StoredConfig {
+        StoredConfig {
+            revision,
+            data: data.to_vec(),
+        }
+    }
+
+    fn records(path: &Path) -> usize {
+        fs::read_to_string(path).unwrap().lines().count()
+    }
+
+    #[test]
+    fn replay() {
+        let path = TempPath::new("replay");
+        {
+            let mut store = FileStore::open(&path.0).unwrap();
+            store
+                .put("node-1", &Config::MayastorConfig, config(1, b"a"))
+                .unwrap();
+            store
+                .put("node-1", &Config::ChildStatesConfig, config(1, b"b"))
+                .unwrap();
+            store
+                .put("node-2", &Config::MayastorConfig, config(2, b"c"))
+                .unwrap();
+        }
+
+        let store = FileStore::open(&path.0).unwrap();
+        let stored = store.get("node-1", &Config::MayastorConfig).unwrap();
+        assert_eq!((stored.revision, stored.data), (1, b"a".to_vec()));
+        let stored = store.get("node-1", &Config::ChildStatesConfig).unwrap();
+        assert_eq!((stored.revision, stored.data), (1, b"b".to_vec()));
+        let stored = store.get("node-2", &Config::MayastorConfig).unwrap();
+        assert_eq!((stored.revision, stored.data), (2, b"c".to_vec()));
+        assert!(store.get("node-2", &Config::ChildStatesConfig).is_none());
+        assert_eq!(records(&path.0), 3);
+    }
+
+    #[test]
+    fn torn_last_record() {
+        let path = TempPath::new("torn");
+        {
+            let mut store = FileStore::open(&path.0).unwrap();
+            store
+                .put("node-1", &Config::MayastorConfig, config(1, b"a"))
+                .unwrap();
+        }
+        // a crash whilst the next record was appended
+        OpenOptions::new()
+            .append(true)
+            .open(&path.0)
+            .unwrap()
+            .write_all(br#"{"node":"node-1","kind":"Mayas"#)
+            .unwrap();
+
+        let mut store = FileStore::open(&path.0).unwrap();
+        let stored = store.get("node-1", &Config::MayastorConfig).unwrap();
+        assert_eq!((stored.revision, stored.data), (1, b"a".to_vec()));
+        // the torn record is gone so the next one is appended after it
+        assert_eq!(records(&path.0), 1);
+        store
+            .put("node-1", &Config::MayastorConfig, config(2, b"b"))
+            .unwrap();
+        drop(store);
+
+        let store = FileStore::open(&path.0).unwrap();
+        let stored = store.get("node-1", &Config::MayastorConfig).unwrap();
+        assert_eq!((stored.revision, stored.data), (2, b"b".to_vec()));
+    }
+
+    #[test]
+    fn corrupt_record() {
+        let path = TempPath::new("corrupt");
+        fs::write(&path.0, "garbage\n{}\n").unwrap();
+
+        assert!(matches!(
+            FileStore::open(&path.0),
+            Err(StoreError::Corrupt {
+                record: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn compaction() {
+        let path = TempPath::new("compaction");
+        {
+            let mut store = FileStore::open(&path.0).unwrap();
+            for revision in 1 ..= 3 {
+                store
+                    .put(
+                        "node-1",
+                        &Config::MayastorConfig,
+                        config(revision, &[revision as u8]),
+                    )
+                    .unwrap();
+            }
+            store
+                .put("node-2", &Config::MayastorConfig, config(1, b"a"))
+                .unwrap();
+        }
+        assert_eq!(records(&path.0), 4);
+
+        let store = FileStore::open(&path.0).unwrap();
+        assert_eq!(records(&path.0), 2);
+        assert!(!path.0.with_extension("compact").exists());
+        let stored = store.get("node-1", &Config::MayastorConfig).unwrap();
+        assert_eq!((stored.revision, stored.data), (3, vec![3]));
+        let stored = store.get("node-2", &Config::MayastorConfig).unwrap();
+        assert_eq!((stored.revision, stored.data), (1, b"a".to_vec()));
+    }
+}
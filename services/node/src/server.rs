@@ -0,0 +1,193 @@
+//! Node service, keeping track of the mayastor instances which register with
+//! the control plane. A node which misses a number of consecutive
+//! registrations is deemed to be offline, as is a node that deregisters.
+
+#[macro_use]
+extern crate lazy_static;
+
+use async_trait::async_trait;
+use common::*;
+use log::{info, warn};
+use mbus_api::*;
+use smol::io;
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use tokio::sync::Mutex;
+
+#[derive(Debug, StructOpt)]
+struct CliArgs {
+    /// The Nats Server URL to connect to
+    /// (supports the nats schema)
+    /// Default: nats://127.0.0.1:4222
+    #[structopt(long, short, default_value = "nats://127.0.0.1:4222")]
+    url: String,
+
+    /// Interval in seconds at which the nodes register, which must match
+    /// MAYASTOR_HB_INTERVAL of the mayastor instances
+    #[structopt(long, short = "i", default_value = "10")]
+    hb_interval: u64,
+
+    /// Number of consecutive registrations a node may miss before it is
+    /// deemed to be offline
+    #[structopt(long, short = "m", default_value = "3")]
+    missed_hbs: u32,
+}
+
+/// Needed so we can implement the ServiceSubscriber trait for
+/// the message types external to the crate
+#[derive(Clone, Default)]
+struct ServiceHandler<T> {
+    data: PhantomData<T>,
+}
+
+/// a node together with the time of its last registration
+struct NodeEntry {
+    node: Node,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct NodeRegistry {
+    nodes: Mutex<HashMap<String, NodeEntry>>,
+}
+
+lazy_static! {
+    static ref NODES: NodeRegistry = Default::default();
+}
+
+impl NodeRegistry {
+    /// a node registered, bringing it online if it was not already
+    async fn register(&self, registration: Register) {
+        let mut nodes = self.nodes.lock().await;
+        let node = Node {
+            id: registration.id.clone(),
+            grpc_endpoint: registration.grpc_endpoint,
+            state: NodeState::Online,
+        };
+        match nodes.get_mut(&registration.id) {
+            Some(entry) => {
+                if entry.node != node {
+                    info!("Node {:?} is online", node);
+                }
+                entry.node = node;
+                entry.last_seen = Instant::now();
+            }
+            None => {
+                info!("New node {:?} is online", node);
+                nodes.insert(
+                    registration.id,
+                    NodeEntry {
+                        node,
+                        last_seen: Instant::now(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// a node deregistered as it is going down
+    async fn deregister(&self, id: &str) {
+        let mut nodes = self.nodes.lock().await;
+        match nodes.get_mut(id) {
+            Some(entry) => {
+                info!("Node '{}' deregistered, it is offline", id);
+                entry.node.state = NodeState::Offline;
+            }
+            None => warn!("Node '{}' deregistered but it is not known", id),
+        }
+    }
+
+    /// mark the nodes which have not registered within the deadline offline
+    async fn expire(&self, deadline: Duration) {
+        let mut nodes = self.nodes.lock().await;
+        for entry in nodes.values_mut() {
+            if entry.node.state == NodeState::Online
+                && entry.last_seen.elapsed() > deadline
+            {
+                warn!(
+                    "Node '{}' has not registered for {:?}, it is offline",
+                    entry.node.id,
+                    entry.last_seen.elapsed()
+                );
+                entry.node.state = NodeState::Offline;
+            }
+        }
+    }
+
+    async fn get_nodes(&self) -> Vec<Node> {
+        let nodes = self.nodes.lock().await;
+        nodes.values().map(|entry| entry.node.clone()).collect()
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<Register> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<Register, ()> = args.request.try_into()?;
+        NODES.register(msg.inner()).await;
+        Ok(())
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![Register::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<Deregister> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<Deregister, ()> = args.request.try_into()?;
+        NODES.deregister(&msg.inner().id).await;
+        Ok(())
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![Deregister::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<GetNodes> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<GetNodes, Nodes> = args.request.try_into()?;
+        msg.reply(Nodes(NODES.get_nodes().await)).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![GetNodes::default().id()]
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init_from_env(
+        env_logger::Env::default()
+            .filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+    );
+
+    let cli_args = CliArgs::from_args();
+    info!("Using options: {:?}", &cli_args);
+
+    server(cli_args).await;
+}
+
+async fn server(cli_args: CliArgs) {
+    let hb_interval = Duration::from_secs(cli_args.hb_interval);
+    let deadline = hb_interval * cli_args.missed_hbs;
+    tokio::spawn(async move {
+        loop {
+            tokio::time::delay_for(hb_interval).await;
+            NODES.expire(deadline).await;
+        }
+    });
+
+    Service::builder(cli_args.url, Channel::Registry)
+        .with_subscription(ServiceHandler::<Register>::default())
+        .with_subscription(ServiceHandler::<Deregister>::default())
+        .with_channel(Channel::Node)
+        .with_subscription(ServiceHandler::<GetNodes>::default())
+        .run()
+        .await;
+}
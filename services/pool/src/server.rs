@@ -0,0 +1,404 @@
+//! Pool service, aggregating the pools and replicas of all the mayastor
+//! instances known to the node service.
+//!
+//! The service keeps no state of its own: every request is forwarded to the
+//! gRPC endpoint of the node(s) it concerns, and listings are merged across
+//! all the online nodes. A node which fails to answer a listing is skipped so
+//! that a single unresponsive node does not hide the rest of the cluster.
+
+use async_trait::async_trait;
+use common::*;
+use futures::future::join_all;
+use log::{info, warn};
+use mbus_api::*;
+use rpc::mayastor::{self as rpc, mayastor_client::MayastorClient};
+use smol::io;
+use snafu::{ResultExt, Snafu};
+use std::{convert::TryInto, marker::PhantomData};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct CliArgs {
+    /// The Nats Server URL to connect to
+    /// (supports the nats schema)
+    /// Default: nats://127.0.0.1:4222
+    #[structopt(long, short, default_value = "nats://127.0.0.1:4222")]
+    url: String,
+}
+
+/// Needed so we can implement the ServiceSubscriber trait for
+/// the message types external to the crate
+#[derive(Clone, Default)]
+struct ServiceHandler<T> {
+    data: PhantomData<T>,
+}
+
+type GrpcClient = MayastorClient<tonic::transport::Channel>;
+
+#[derive(Debug, Snafu)]
+enum SvcError {
+    #[snafu(display("Failed to get the nodes: {}", source))]
+    NodesUnavailable { source: io::Error },
+    #[snafu(display("Node '{}' not found", node))]
+    NodeNotFound { node: String },
+    #[snafu(display("Node '{}' is not online", node))]
+    NodeNotOnline { node: String },
+    #[snafu(display(
+        "Failed to connect to node '{}' at '{}': {}",
+        node,
+        endpoint,
+        source
+    ))]
+    GrpcConnect {
+        node: String,
+        endpoint: String,
+        source: tonic::transport::Error,
+    },
+    #[snafu(display(
+        "gRPC request '{}' to node '{}' failed: {}",
+        request,
+        node,
+        source
+    ))]
+    GrpcRequest {
+        node: String,
+        request: String,
+        source: tonic::Status,
+    },
+}
+
+impl From<SvcError> for Error {
+    fn from(error: SvcError) -> Self {
+        Error::WithMessage {
+            message: error.to_string(),
+        }
+    }
+}
+
+/// all the nodes which are online, or only the given one
+async fn online_nodes(node: Option<&str>) -> Result<Vec<Node>, SvcError> {
+    let nodes = GetNodes {}.request().await.context(NodesUnavailable)?.0;
+    match node {
+        None => Ok(nodes
+            .into_iter()
+            .filter(|n| n.state == NodeState::Online)
+            .collect()),
+        Some(id) => match nodes.into_iter().find(|n| n.id == id) {
+            Some(n) if n.state == NodeState::Online => Ok(vec![n]),
+            Some(_) => Err(SvcError::NodeNotOnline {
+                node: id.to_string(),
+            }),
+            None => Err(SvcError::NodeNotFound {
+                node: id.to_string(),
+            }),
+        },
+    }
+}
+
+async fn online_node(node: &str) -> Result<Node, SvcError> {
+    Ok(online_nodes(Some(node)).await?.remove(0))
+}
+
+async fn grpc_client(node: &Node) -> Result<GrpcClient, SvcError> {
+    let endpoint = format!("http://{}", node.grpc_endpoint);
+    GrpcClient::connect(endpoint.clone())
+        .await
+        .context(GrpcConnect {
+            node: node.id.clone(),
+            endpoint,
+        })
+}
+
+/// run `request` against each of the nodes and merge the results, the nodes
+/// which fail are logged and left out
+async fn merge_nodes<T, F, Fut>(nodes: Vec<Node>, request: F) -> Vec<T>
+where
+    F: Fn(Node) -> Fut,
+    Fut: std::future::Future<Output = Result<Vec<T>, SvcError>>,
+{
+    join_all(nodes.into_iter().map(request))
+        .await
+        .into_iter()
+        .filter_map(|result| match result {
+            Ok(items) => Some(items),
+            Err(error) => {
+                warn!("Skipping node: {}", error);
+                None
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+fn pool_from_rpc(node: &str, pool: rpc::Pool) -> Pool {
+    Pool {
+        node: node.to_string(),
+        name: pool.name,
+        disks: pool.disks,
+        state: match rpc::PoolState::from_i32(pool.state) {
+            Some(rpc::PoolState::PoolOnline) => PoolState::Online,
+            Some(rpc::PoolState::PoolDegraded) => PoolState::Degraded,
+            Some(rpc::PoolState::PoolFaulted) => PoolState::Faulted,
+            _ => PoolState::Unknown,
+        },
+        capacity: pool.capacity,
+        used: pool.used,
+    }
+}
+
+fn replica_from_rpc(node: &str, replica: rpc::Replica) -> Replica {
+    Replica {
+        node: node.to_string(),
+        uuid: replica.uuid,
+        pool: replica.pool,
+        thin: replica.thin,
+        size: replica.size,
+        share: match rpc::ShareProtocolReplica::from_i32(replica.share) {
+            Some(rpc::ShareProtocolReplica::ReplicaNvmf) => Protocol::Nvmf,
+            Some(rpc::ShareProtocolReplica::ReplicaIscsi) => Protocol::Iscsi,
+            _ => Protocol::Off,
+        },
+        uri: replica.uri,
+    }
+}
+
+fn protocol_to_rpc(share: Protocol) -> rpc::ShareProtocolReplica {
+    match share {
+        Protocol::Off => rpc::ShareProtocolReplica::ReplicaNone,
+        Protocol::Nvmf => rpc::ShareProtocolReplica::ReplicaNvmf,
+        Protocol::Iscsi => rpc::ShareProtocolReplica::ReplicaIscsi,
+    }
+}
+
+/// split a filter into its node and pool parts
+fn filter_parts(filter: &Filter) -> (Option<&str>, Option<&str>) {
+    match filter {
+        Filter::None => (None, None),
+        Filter::Node(node) => (Some(node), None),
+        Filter::Pool(pool) => (None, Some(pool)),
+        Filter::NodePool(node, pool) => (Some(node), Some(pool)),
+    }
+}
+
+async fn get_pools(request: GetPools) -> Result<Pools, SvcError> {
+    let (node, pool) = filter_parts(&request.filter);
+    let nodes = online_nodes(node).await?;
+
+    let pools = merge_nodes(nodes, |node| async move {
+        let mut client = grpc_client(&node).await?;
+        let reply =
+            client.list_pools(rpc::Null {}).await.context(GrpcRequest {
+                node: node.id.clone(),
+                request: "list_pools",
+            })?;
+        Ok(reply
+            .into_inner()
+            .pools
+            .into_iter()
+            .map(|p| pool_from_rpc(&node.id, p))
+            .collect())
+    })
+    .await;
+
+    Ok(Pools(
+        pools
+            .into_iter()
+            .filter(|p| pool.map_or(true, |name| p.name == name))
+            .collect(),
+    ))
+}
+
+async fn get_replicas(request: GetReplicas) -> Result<Replicas, SvcError> {
+    let (node, pool) = filter_parts(&request.filter);
+    let nodes = online_nodes(node).await?;
+
+    let replicas =
+        merge_nodes(nodes, |node| async move {
+            let mut client = grpc_client(&node).await?;
+            let reply = client.list_replicas(rpc::Null {}).await.context(
+                GrpcRequest {
+                    node: node.id.clone(),
+                    request: "list_replicas",
+                },
+            )?;
+            Ok(reply
+                .into_inner()
+                .replicas
+                .into_iter()
+                .map(|r| replica_from_rpc(&node.id, r))
+                .collect())
+        })
+        .await;
+
+    Ok(Replicas(
+        replicas
+            .into_iter()
+            .filter(|r| pool.map_or(true, |name| r.pool == name))
+            .collect(),
+    ))
+}
+
+async fn create_pool(request: CreatePool) -> Result<Pool, SvcError> {
+    let node = online_node(&request.node).await?;
+    let mut client = grpc_client(&node).await?;
+    let pool = client
+        .create_pool(rpc::CreatePoolRequest {
+            name: request.name,
+            disks: request.disks,
+        })
+        .await
+        .context(GrpcRequest {
+            node: node.id.clone(),
+            request: "create_pool",
+        })?;
+    Ok(pool_from_rpc(&node.id, pool.into_inner()))
+}
+
+async fn destroy_pool(request: DestroyPool) -> Result<(), SvcError> {
+    let node = online_node(&request.node).await?;
+    let mut client = grpc_client(&node).await?;
+    client
+        .destroy_pool(rpc::DestroyPoolRequest {
+            name: request.name,
+        })
+        .await
+        .context(GrpcRequest {
+            node: node.id.clone(),
+            request: "destroy_pool",
+        })?;
+    Ok(())
+}
+
+async fn create_replica(request: CreateReplica) -> Result<Replica, SvcError> {
+    let node = online_node(&request.node).await?;
+    let mut client = grpc_client(&node).await?;
+    let replica = client
+        .create_replica(rpc::CreateReplicaRequest {
+            uuid: request.uuid,
+            pool: request.pool,
+            size: request.size,
+            thin: request.thin,
+            share: protocol_to_rpc(request.share) as i32,
+        })
+        .await
+        .context(GrpcRequest {
+            node: node.id.clone(),
+            request: "create_replica",
+        })?;
+    Ok(replica_from_rpc(&node.id, replica.into_inner()))
+}
+
+async fn destroy_replica(request: DestroyReplica) -> Result<(), SvcError> {
+    let node = online_node(&request.node).await?;
+    let mut client = grpc_client(&node).await?;
+    client
+        .destroy_replica(rpc::DestroyReplicaRequest {
+            uuid: request.uuid,
+        })
+        .await
+        .context(GrpcRequest {
+            node: node.id.clone(),
+            request: "destroy_replica",
+        })?;
+    Ok(())
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<GetPools> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<GetPools, Pools> = args.request.try_into()?;
+        let reply = get_pools(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![GetPools::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<GetReplicas> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<GetReplicas, Replicas> =
+            args.request.try_into()?;
+        let reply = get_replicas(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![GetReplicas::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<CreatePool> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<CreatePool, Pool> = args.request.try_into()?;
+        let reply = create_pool(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![CreatePool::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<DestroyPool> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<DestroyPool, ()> = args.request.try_into()?;
+        let reply = destroy_pool(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![DestroyPool::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<CreateReplica> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<CreateReplica, Replica> =
+            args.request.try_into()?;
+        let reply = create_replica(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![CreateReplica::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<DestroyReplica> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<DestroyReplica, ()> =
+            args.request.try_into()?;
+        let reply = destroy_replica(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![DestroyReplica::default().id()]
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init_from_env(
+        env_logger::Env::default()
+            .filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+    );
+
+    let cli_args = CliArgs::from_args();
+    info!("Using options: {:?}", &cli_args);
+
+    server(cli_args).await;
+}
+
+async fn server(cli_args: CliArgs) {
+    Service::builder(cli_args.url, Channel::Pool)
+        .with_subscription(ServiceHandler::<GetPools>::default())
+        .with_subscription(ServiceHandler::<CreatePool>::default())
+        .with_subscription(ServiceHandler::<DestroyPool>::default())
+        .with_subscription(ServiceHandler::<GetReplicas>::default())
+        .with_subscription(ServiceHandler::<CreateReplica>::default())
+        .with_subscription(ServiceHandler::<DestroyReplica>::default())
+        .run()
+        .await;
+}
@@ -0,0 +1,384 @@
+//! Volume service, provisioning a replicated volume from a single request.
+//!
+//! The replicas are placed on the pools with the most free space, at most one
+//! per node, through the pool service. The nexus is then created on the node
+//! of the first replica, which it accesses locally, while the other replicas
+//! are shared over NVMe-oF. If any of the steps fails whatever was created
+//! up to that point is destroyed again.
+
+use async_trait::async_trait;
+use common::*;
+use log::{info, warn};
+use mbus_api::*;
+use rpc::mayastor::{self as rpc, mayastor_client::MayastorClient};
+use smol::io;
+use snafu::{ResultExt, Snafu};
+use std::{cmp::Reverse, convert::TryInto, marker::PhantomData};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct CliArgs {
+    /// The Nats Server URL to connect to
+    /// (supports the nats schema)
+    /// Default: nats://127.0.0.1:4222
+    #[structopt(long, short, default_value = "nats://127.0.0.1:4222")]
+    url: String,
+}
+
+/// Needed so we can implement the ServiceSubscriber trait for
+/// the message types external to the crate
+#[derive(Clone, Default)]
+struct ServiceHandler<T> {
+    data: PhantomData<T>,
+}
+
+type GrpcClient = MayastorClient<tonic::transport::Channel>;
+
+#[derive(Debug, Snafu)]
+enum SvcError {
+    #[snafu(display("A volume needs at least one replica"))]
+    NoReplicas {},
+    #[snafu(display(
+        "Not enough pools to place {} replicas of {} bytes, found {}",
+        replicas,
+        size,
+        available
+    ))]
+    NotEnoughResources {
+        replicas: u64,
+        size: u64,
+        available: u64,
+    },
+    #[snafu(display("Bus request '{}' failed: {}", request, source))]
+    BusRequest { request: String, source: io::Error },
+    #[snafu(display("Node '{}' not found or not online", node))]
+    NodeNotOnline { node: String },
+    #[snafu(display(
+        "Failed to connect to node '{}' at '{}': {}",
+        node,
+        endpoint,
+        source
+    ))]
+    GrpcConnect {
+        node: String,
+        endpoint: String,
+        source: tonic::transport::Error,
+    },
+    #[snafu(display(
+        "gRPC request '{}' to node '{}' failed: {}",
+        request,
+        node,
+        source
+    ))]
+    GrpcRequest {
+        node: String,
+        request: String,
+        source: tonic::Status,
+    },
+}
+
+impl From<SvcError> for Error {
+    fn from(error: SvcError) -> Self {
+        Error::WithMessage {
+            message: error.to_string(),
+        }
+    }
+}
+
+async fn online_nodes() -> Result<Vec<Node>, SvcError> {
+    let nodes = GetNodes {}.request().await.context(BusRequest {
+        request: "get_nodes",
+    })?;
+    Ok(nodes
+        .0
+        .into_iter()
+        .filter(|n| n.state == NodeState::Online)
+        .collect())
+}
+
+async fn grpc_client(node: &Node) -> Result<GrpcClient, SvcError> {
+    let endpoint = format!("http://{}", node.grpc_endpoint);
+    GrpcClient::connect(endpoint.clone())
+        .await
+        .context(GrpcConnect {
+            node: node.id.clone(),
+            endpoint,
+        })
+}
+
+async fn node_client(node: &str) -> Result<GrpcClient, SvcError> {
+    match online_nodes().await?.into_iter().find(|n| n.id == node) {
+        Some(node) => grpc_client(&node).await,
+        None => Err(SvcError::NodeNotOnline {
+            node: node.to_string(),
+        }),
+    }
+}
+
+/// pick the online pools with the most free space which can hold a replica
+/// of `size` bytes, no more than one per node
+fn place_replicas(
+    mut pools: Vec<Pool>,
+    size: u64,
+    replicas: u64,
+) -> Result<Vec<Pool>, SvcError> {
+    pools.retain(|p| {
+        p.state == PoolState::Online
+            && p.capacity.saturating_sub(p.used) >= size
+    });
+    pools.sort_by_key(|p| Reverse(p.capacity.saturating_sub(p.used)));
+
+    let mut placed: Vec<Pool> = Vec::new();
+    for pool in pools {
+        if placed.len() as u64 == replicas {
+            break;
+        }
+        if !placed.iter().any(|p| p.node == pool.node) {
+            placed.push(pool);
+        }
+    }
+
+    if (placed.len() as u64) < replicas {
+        return Err(SvcError::NotEnoughResources {
+            replicas,
+            size,
+            available: placed.len() as u64,
+        });
+    }
+    Ok(placed)
+}
+
+/// destroy the given replicas, failures are only logged as this is used to
+/// clean up after a failure
+async fn destroy_replicas(replicas: &[Replica]) {
+    for replica in replicas {
+        let request = DestroyReplica {
+            node: replica.node.clone(),
+            uuid: replica.uuid.clone(),
+        };
+        if let Err(error) = request.request().await {
+            warn!(
+                "Failed to destroy replica '{}' on node '{}': {}",
+                replica.uuid, replica.node, error
+            );
+        }
+    }
+}
+
+async fn create_replicas(
+    request: &CreateVolume,
+    pools: &[Pool],
+) -> Result<Vec<Replica>, SvcError> {
+    let mut replicas = Vec::new();
+    for (i, pool) in pools.iter().enumerate() {
+        let create = CreateReplica {
+            node: pool.node.clone(),
+            uuid: request.uuid.clone(),
+            pool: pool.name.clone(),
+            size: request.size,
+            thin: false,
+            // the first replica is local to the nexus
+            share: if i == 0 {
+                Protocol::Off
+            } else {
+                Protocol::Nvmf
+            },
+        };
+        match create.request().await {
+            Ok(replica) => replicas.push(replica),
+            Err(source) => {
+                destroy_replicas(&replicas).await;
+                return Err(SvcError::BusRequest {
+                    request: "create_replica".to_string(),
+                    source,
+                });
+            }
+        }
+    }
+    Ok(replicas)
+}
+
+/// create the nexus and publish it if requested, returning its device uri
+async fn create_nexus(
+    request: &CreateVolume,
+    node: &str,
+    replicas: &[Replica],
+) -> Result<String, SvcError> {
+    let mut client = node_client(node).await?;
+    client
+        .create_nexus(rpc::CreateNexusRequest {
+            uuid: request.uuid.clone(),
+            size: request.size,
+            children: replicas.iter().map(|r| r.uri.clone()).collect(),
+            ..Default::default()
+        })
+        .await
+        .context(GrpcRequest {
+            node,
+            request: "create_nexus",
+        })?;
+
+    let share = match request.protocol {
+        Protocol::Off => return Ok(String::new()),
+        Protocol::Nvmf => rpc::ShareProtocolNexus::NexusNvmf,
+        Protocol::Iscsi => rpc::ShareProtocolNexus::NexusIscsi,
+    };
+    let published = client
+        .publish_nexus(rpc::PublishNexusRequest {
+            uuid: request.uuid.clone(),
+            share: share as i32,
+            ..Default::default()
+        })
+        .await
+        .context(GrpcRequest {
+            node,
+            request: "publish_nexus",
+        });
+
+    match published {
+        Ok(reply) => Ok(reply.into_inner().device_uri),
+        Err(error) => {
+            let destroy = client
+                .destroy_nexus(rpc::DestroyNexusRequest {
+                    uuid: request.uuid.clone(),
+                })
+                .await;
+            if let Err(status) = destroy {
+                warn!(
+                    "Failed to destroy nexus '{}' on node '{}': {}",
+                    request.uuid, node, status
+                );
+            }
+            Err(error)
+        }
+    }
+}
+
+async fn create_volume(request: CreateVolume) -> Result<Volume, SvcError> {
+    if request.replicas == 0 {
+        return Err(SvcError::NoReplicas {});
+    }
+
+    let pools = GetPools::default().request().await.context(BusRequest {
+        request: "get_pools",
+    })?;
+    let pools = place_replicas(pools.0, request.size, request.replicas)?;
+    let replicas = create_replicas(&request, &pools).await?;
+
+    let node = replicas[0].node.clone();
+    let device_uri = match create_nexus(&request, &node, &replicas).await {
+        Ok(device_uri) => device_uri,
+        Err(error) => {
+            destroy_replicas(&replicas).await;
+            return Err(error);
+        }
+    };
+
+    info!(
+        "Created volume '{}' with nexus on node '{}' and replicas in {:?}",
+        request.uuid,
+        node,
+        pools.iter().map(|p| &p.name).collect::<Vec<_>>()
+    );
+    Ok(Volume {
+        uuid: request.uuid,
+        size: request.size,
+        node,
+        protocol: request.protocol,
+        device_uri,
+        replicas,
+    })
+}
+
+async fn destroy_volume(request: DestroyVolume) -> Result<(), SvcError> {
+    // the nexus has to go first, it holds the replicas open
+    for node in online_nodes().await? {
+        let mut client = grpc_client(&node).await?;
+        let nexus_list = client
+            .list_nexus(rpc::Null {})
+            .await
+            .context(GrpcRequest {
+                node: node.id.clone(),
+                request: "list_nexus",
+            })?
+            .into_inner()
+            .nexus_list;
+        if nexus_list.iter().any(|n| n.uuid == request.uuid) {
+            client
+                .destroy_nexus(rpc::DestroyNexusRequest {
+                    uuid: request.uuid.clone(),
+                })
+                .await
+                .context(GrpcRequest {
+                    node: node.id.clone(),
+                    request: "destroy_nexus",
+                })?;
+        }
+    }
+
+    let replicas =
+        GetReplicas::default().request().await.context(BusRequest {
+            request: "get_replicas",
+        })?;
+    for replica in replicas.0.into_iter().filter(|r| r.uuid == request.uuid) {
+        DestroyReplica {
+            node: replica.node,
+            uuid: replica.uuid,
+        }
+        .request()
+        .await
+        .context(BusRequest {
+            request: "destroy_replica",
+        })?;
+    }
+
+    info!("Destroyed volume '{}'", request.uuid);
+    Ok(())
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<CreateVolume> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<CreateVolume, Volume> =
+            args.request.try_into()?;
+        let reply = create_volume(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![CreateVolume::default().id()]
+    }
+}
+
+#[async_trait]
+impl ServiceSubscriber for ServiceHandler<DestroyVolume> {
+    async fn handler(&self, args: Arguments<'_>) -> Result<(), io::Error> {
+        let msg: ReceivedMessage<DestroyVolume, ()> =
+            args.request.try_into()?;
+        let reply = destroy_volume(msg.inner()).await.map_err(Error::from);
+        msg.reply(reply).await
+    }
+    fn filter(&self) -> Vec<MessageId> {
+        vec![DestroyVolume::default().id()]
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init_from_env(
+        env_logger::Env::default()
+            .filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+    );
+
+    let cli_args = CliArgs::from_args();
+    info!("Using options: {:?}", &cli_args);
+
+    server(cli_args).await;
+}
+
+async fn server(cli_args: CliArgs) {
+    Service::builder(cli_args.url, Channel::Volume)
+        .with_subscription(ServiceHandler::<CreateVolume>::default())
+        .with_subscription(ServiceHandler::<DestroyVolume>::default())
+        .run()
+        .await;
+}
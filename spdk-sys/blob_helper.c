@@ -0,0 +1,237 @@
+#include "blob_helper.h"
+
+#include <errno.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include <spdk/blob.h>
+#include <spdk/env.h>
+#include <spdk/lib/blob/blobstore.h>
+#include <spdk/thread.h>
+#include <spdk/util.h>
+
+/*
+ * Returns whether any of the clusters backing the io units [offset, offset +
+ * length) of the blob is allocated. The unallocated clusters of a clone are
+ * read from its snapshot, so these are reported as allocated as well; only
+ * the unallocated clusters of other blobs are known to read as zeroes.
+ */
+bool
+blob_range_allocated(struct spdk_blob *blob, uint64_t offset, uint64_t length)
+{
+	uint64_t io_units_per_cluster;
+	uint64_t cluster, last;
+
+	if (length == 0) {
+		return false;
+	}
+
+	if (blob->parent_id != SPDK_BLOBID_INVALID) {
+		return true;
+	}
+
+	io_units_per_cluster = blob->bs->cluster_sz / blob->bs->io_unit_size;
+	last = (offset + length - 1) / io_units_per_cluster;
+
+	for (cluster = offset / io_units_per_cluster; cluster <= last; cluster++) {
+		if (cluster >= blob->active.num_clusters ||
+		    blob->active.clusters[cluster] != 0) {
+			return true;
+		}
+	}
+
+	return false;
+}
+
+/*
+ * Returns the number of clusters allocated to the blob itself, as opposed to
+ * the total number of clusters which includes those of a thin provisioned
+ * blob that are not allocated yet.
+ */
+uint64_t
+blob_num_allocated_clusters(struct spdk_blob *blob)
+{
+	uint64_t cluster, allocated = 0;
+
+	for (cluster = 0; cluster < blob->active.num_clusters; cluster++) {
+		if (blob->active.clusters[cluster] != 0) {
+			allocated++;
+		}
+	}
+
+	return allocated;
+}
+
+/* the blob is reverted in chunks of at most 1MiB */
+#define BLOB_REVERT_CHUNK_SIZE (1024 * 1024)
+
+struct blob_revert_ctx {
+	struct spdk_blob *blob;
+	struct spdk_blob *snapshot;
+	struct spdk_io_channel *channel;
+	uint64_t io_unit_size;
+	uint64_t num_io_units;
+	uint64_t snapshot_io_units;
+	/* the chunk being reverted, in io units */
+	uint64_t offset;
+	uint64_t length;
+	uint64_t chunk;
+	/* the chunk as read from the snapshot and from the blob */
+	void *snapshot_data;
+	void *blob_data;
+	void (*cb_fn)(void *cb_arg, int bserrno);
+	void *cb_arg;
+};
+
+static void
+blob_revert_done(struct blob_revert_ctx *ctx, int bserrno)
+{
+	if (ctx->channel != NULL) {
+		spdk_bs_free_io_channel(ctx->channel);
+	}
+	spdk_dma_free(ctx->snapshot_data);
+	spdk_dma_free(ctx->blob_data);
+
+	ctx->cb_fn(ctx->cb_arg, bserrno);
+	free(ctx);
+}
+
+static void blob_revert_chunk(void *arg);
+
+/*
+ * Continue with the next chunk from a message, as the reads of unallocated
+ * clusters complete inline and would otherwise recurse once per chunk.
+ */
+static void
+blob_revert_next(struct blob_revert_ctx *ctx)
+{
+	ctx->offset += ctx->length;
+	spdk_thread_send_msg(spdk_get_thread(), blob_revert_chunk, ctx);
+}
+
+static void
+blob_revert_write_cpl(void *cb_arg, int bserrno)
+{
+	struct blob_revert_ctx *ctx = cb_arg;
+
+	if (bserrno != 0) {
+		blob_revert_done(ctx, bserrno);
+		return;
+	}
+
+	blob_revert_next(ctx);
+}
+
+static void
+blob_revert_blob_read_cpl(void *cb_arg, int bserrno)
+{
+	struct blob_revert_ctx *ctx = cb_arg;
+
+	if (bserrno != 0) {
+		blob_revert_done(ctx, bserrno);
+		return;
+	}
+
+	/*
+	 * Only what differs is written, such that a thin provisioned blob does
+	 * not allocate the clusters that still match the snapshot.
+	 */
+	if (memcmp(ctx->snapshot_data, ctx->blob_data,
+		   ctx->length * ctx->io_unit_size) == 0) {
+		blob_revert_next(ctx);
+		return;
+	}
+
+	spdk_blob_io_write(ctx->blob, ctx->channel, ctx->snapshot_data,
+			   ctx->offset, ctx->length, blob_revert_write_cpl, ctx);
+}
+
+static void
+blob_revert_snapshot_read_cpl(void *cb_arg, int bserrno)
+{
+	struct blob_revert_ctx *ctx = cb_arg;
+
+	if (bserrno != 0) {
+		blob_revert_done(ctx, bserrno);
+		return;
+	}
+
+	spdk_blob_io_read(ctx->blob, ctx->channel, ctx->blob_data, ctx->offset,
+			  ctx->length, blob_revert_blob_read_cpl, ctx);
+}
+
+static void
+blob_revert_chunk(void *arg)
+{
+	struct blob_revert_ctx *ctx = arg;
+
+	if (ctx->offset >= ctx->num_io_units) {
+		blob_revert_done(ctx, 0);
+		return;
+	}
+
+	ctx->length = spdk_min(ctx->chunk, ctx->num_io_units - ctx->offset);
+
+	/* a blob grown since the snapshot reads as zeroes past its end */
+	if (ctx->offset >= ctx->snapshot_io_units) {
+		memset(ctx->snapshot_data, 0, ctx->length * ctx->io_unit_size);
+		blob_revert_snapshot_read_cpl(ctx, 0);
+		return;
+	}
+
+	ctx->length = spdk_min(ctx->length,
+			       ctx->snapshot_io_units - ctx->offset);
+	spdk_blob_io_read(ctx->snapshot, ctx->channel, ctx->snapshot_data,
+			  ctx->offset, ctx->length,
+			  blob_revert_snapshot_read_cpl, ctx);
+}
+
+/*
+ * Reverts the blob to the given snapshot of it by copying the data of the
+ * snapshot over that of the blob, chunk by chunk, writing only the chunks
+ * that differ. Only the public blob API is used and the metadata of the
+ * blob is left as is, so a thick provisioned blob stays thick, and a revert
+ * that fails half way leaves the blob partially reverted, to be reverted
+ * again, rather than lose its data. The size of the blob is retained. Must
+ * be called on a thread of the blobstore, with no I/O outstanding on the
+ * blob.
+ */
+void
+blob_revert(struct spdk_blob_store *bs, struct spdk_blob *blob,
+	    struct spdk_blob *snapshot,
+	    void (*cb_fn)(void *cb_arg, int bserrno), void *cb_arg)
+{
+	struct blob_revert_ctx *ctx;
+
+	if (!spdk_blob_is_snapshot(snapshot) || spdk_blob_is_read_only(blob)) {
+		cb_fn(cb_arg, -EINVAL);
+		return;
+	}
+
+	ctx = calloc(1, sizeof(*ctx));
+	if (ctx == NULL) {
+		cb_fn(cb_arg, -ENOMEM);
+		return;
+	}
+	ctx->blob = blob;
+	ctx->snapshot = snapshot;
+	ctx->io_unit_size = spdk_bs_get_io_unit_size(bs);
+	ctx->num_io_units = spdk_blob_get_num_io_units(blob);
+	ctx->snapshot_io_units = spdk_blob_get_num_io_units(snapshot);
+	ctx->chunk = BLOB_REVERT_CHUNK_SIZE / ctx->io_unit_size;
+	ctx->cb_fn = cb_fn;
+	ctx->cb_arg = cb_arg;
+
+	ctx->channel = spdk_bs_alloc_io_channel(bs);
+	ctx->snapshot_data = spdk_dma_malloc(BLOB_REVERT_CHUNK_SIZE,
+					     ctx->io_unit_size, NULL);
+	ctx->blob_data = spdk_dma_malloc(BLOB_REVERT_CHUNK_SIZE,
+					 ctx->io_unit_size, NULL);
+	if (ctx->channel == NULL || ctx->snapshot_data == NULL ||
+	    ctx->blob_data == NULL) {
+		blob_revert_done(ctx, -ENOMEM);
+		return;
+	}
+
+	blob_revert_chunk(ctx);
+}
@@ -0,0 +1,14 @@
+#include <stdbool.h>
+#include <stdint.h>
+
+struct spdk_blob;
+struct spdk_blob_store;
+
+bool blob_range_allocated(struct spdk_blob *blob, uint64_t offset,
+			  uint64_t length);
+
+uint64_t blob_num_allocated_clusters(struct spdk_blob *blob);
+
+void blob_revert(struct spdk_blob_store *bs, struct spdk_blob *blob,
+		 struct spdk_blob *snapshot,
+		 void (*cb_fn)(void *cb_arg, int bserrno), void *cb_arg);
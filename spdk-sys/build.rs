@@ -36,6 +36,11 @@ fn build_wrapper() {
         .include(".")
         .file("nvme_helper.c")
         .compile("nvme_helper");
+    cc::Build::new()
+        .include("spdk/include")
+        .include(".")
+        .file("blob_helper.c")
+        .compile("blob_helper");
 }
 
 fn main() {
@@ -80,6 +85,7 @@ fn main() {
         .whitelist_function("create_malloc_disk")
         .whitelist_function("delete_malloc_disk")
         .whitelist_function("^bdev.*")
+        .whitelist_function("^blob_.*")
         .whitelist_function("^nbd_.*")
         .whitelist_function("^vbdev_.*")
         .whitelist_function("^nvme_cmd_.*")
@@ -125,4 +131,5 @@ fn main() {
     println!("cargo:rerun-if-changed=wrapper.h");
     println!("cargo:rerun-if-changed=logwrapper.c");
     println!("cargo:rerun-if-changed=nvme_helper.c");
+    println!("cargo:rerun-if-changed=blob_helper.c");
 }
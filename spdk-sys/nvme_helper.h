@@ -9,8 +9,10 @@ struct spdk_nvme_status;
 
 uint32_t nvme_cmd_cdw10_get_val(const struct spdk_nvme_cmd *cmd);
 uint32_t nvme_cmd_cdw11_get_val(const struct spdk_nvme_cmd *cmd);
+uint32_t nvme_cmd_cdw12_get_val(const struct spdk_nvme_cmd *cmd);
 uint32_t *nvme_cmd_cdw10_get(struct spdk_nvme_cmd *cmd);
 uint32_t *nvme_cmd_cdw11_get(struct spdk_nvme_cmd *cmd);
+uint32_t *nvme_cmd_cdw12_get(struct spdk_nvme_cmd *cmd);
 
 struct spdk_nvme_status *nvme_status_get(struct spdk_nvme_cpl *cpl);
 uint16_t *nvme_status_raw_get(struct spdk_nvme_cpl *cpl);
@@ -35,5 +35,6 @@
 #include <spdk_internal/thread.h>
 #include <spdk_internal/lvolstore.h>
 
+#include "blob_helper.h"
 #include "logwrapper.h"
 #include "nvme_helper.h"